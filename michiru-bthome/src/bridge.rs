@@ -0,0 +1,263 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use btleplug::api::bleuuid::uuid_from_u16;
+use chrono::Local;
+use michiru_bthome::{
+    capture::Advertisement,
+    codec::{self, Object},
+};
+use michiru_device::{
+    DataType, Device, DeviceBuilder, DeviceState, NodeAttributes, Payload, PropertyAttributes, Unit,
+};
+
+use crate::{
+    config::{self, Config},
+    health::Health,
+};
+
+const LINK_ID: &str = "link";
+const RSSI_ID: &str = "rssi";
+const LAST_SEEN_ID: &str = "last-seen";
+const DECODE_ERRORS_ID: &str = "decode-errors";
+
+/// Advertisements closer together than this are treated as repeats of the same packet
+const REPEAT_WINDOW: Duration = Duration::from_secs(1);
+
+pub struct Bridge {
+    config: Config,
+    health: Arc<Health>,
+    devices: HashMap<String, TrackedDevice>,
+}
+
+struct TrackedDevice {
+    device: Device,
+    state: DeviceState,
+    last_seen: Instant,
+    interval: Option<Duration>,
+    fixed_interval: bool,
+    decode_errors: u64,
+}
+
+impl Bridge {
+    pub fn new(config: Config, health: Arc<Health>) -> Self {
+        Self {
+            config,
+            health,
+            devices: HashMap::new(),
+        }
+    }
+
+    pub async fn handle(&mut self, advertisement: Advertisement) -> Result<()> {
+        if !self.config.is_allowed(&advertisement.address) {
+            tracing::trace!(address = advertisement.address, "Ignoring device");
+            return Ok(());
+        }
+
+        self.health.advertisements.fetch_add(1, Ordering::Relaxed);
+
+        let device_config = self.config.device(&advertisement.address);
+
+        let objects = if advertisement.uuid == uuid_from_u16(codec::UUID_ENCRYPTED) {
+            let Some(key) = device_config.and_then(|d| d.bind_key.as_ref()) else {
+                tracing::warn!(address = advertisement.address, "No bind key for device");
+                return Ok(());
+            };
+
+            parse_address(&advertisement.address)
+                .and_then(|address| codec::decrypt(&advertisement.data, address, key))
+                .and_then(|data| Object::decode(data.as_slice()))
+        } else {
+            Object::decode(advertisement.data.as_slice())
+        };
+
+        let id = match device_config.and_then(|d| d.id.clone()) {
+            Some(id) => id,
+            None => format!("bthome-{}", config::normalize(&advertisement.address)),
+        };
+
+        let name = device_config
+            .and_then(|d| d.name.clone())
+            .unwrap_or(advertisement.name);
+
+        let interval = device_config
+            .and_then(|d| d.interval)
+            .map(Duration::from_secs);
+
+        let tracked = match self.devices.entry(id.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(TrackedDevice {
+                device: {
+                    let options = self.config.mqtt.options(id.clone());
+
+                    DeviceBuilder::new(options, id.clone(), name)
+                        .await?
+                        .node(NodeAttributes {
+                            id: LINK_ID.into(),
+                            name: "Link".into(),
+                            type_: "Bluetooth LE".into(),
+                            properties: vec![
+                                PropertyAttributes {
+                                    id: RSSI_ID.into(),
+                                    name: "RSSI".into(),
+                                    datatype: DataType::Integer,
+                                    settable: false,
+                                    retained: true,
+                                    unit: Some(Unit::Other("dBm".into())),
+                                    format: None,
+                                },
+                                PropertyAttributes {
+                                    id: LAST_SEEN_ID.into(),
+                                    name: "Last seen".into(),
+                                    datatype: DataType::DateTime,
+                                    settable: false,
+                                    retained: true,
+                                    unit: None,
+                                    format: None,
+                                },
+                                PropertyAttributes {
+                                    id: DECODE_ERRORS_ID.into(),
+                                    name: "Decode errors".into(),
+                                    datatype: DataType::Integer,
+                                    settable: false,
+                                    retained: true,
+                                    unit: Some(Unit::Count),
+                                    format: None,
+                                },
+                            ],
+                        })
+                        .await?
+                        .build()
+                        .await?
+                },
+                state: DeviceState::Ready,
+                last_seen: Instant::now(),
+                interval,
+                fixed_interval: interval.is_some(),
+                decode_errors: 0,
+            }),
+        };
+
+        tracked.seen(Instant::now());
+
+        if tracked.state != DeviceState::Ready {
+            tracing::info!(id, "Device is back");
+            tracked.device.set_state(DeviceState::Ready).await?;
+            tracked.state = DeviceState::Ready;
+        }
+
+        let objects = match objects {
+            Ok(objects) => objects,
+            Err(e) => {
+                tracked.decode_errors += 1;
+                self.health.decode_errors.fetch_add(1, Ordering::Relaxed);
+
+                tracing::warn!(id, errors = tracked.decode_errors, "Failed to decode: {e:#}");
+
+                tracked
+                    .device
+                    .node(LINK_ID)
+                    .unwrap()
+                    .property(DECODE_ERRORS_ID)
+                    .await
+                    .unwrap()
+                    .send(Payload::Integer(tracked.decode_errors as i64))
+                    .await?;
+
+                vec![]
+            }
+        };
+
+        let device = &mut tracked.device;
+
+        device
+            .node(LINK_ID)
+            .unwrap()
+            .property(LAST_SEEN_ID)
+            .await
+            .unwrap()
+            .send(Payload::DateTime(advertisement.timestamp.with_timezone(&Local)))
+            .await?;
+
+        if let Some(rssi) = advertisement.rssi {
+            device
+                .node(LINK_ID)
+                .unwrap()
+                .property(RSSI_ID)
+                .await
+                .unwrap()
+                .send(Payload::Integer(rssi as i64))
+                .await?;
+        }
+
+        for object in objects {
+            let Some((node, property, payload)) = object.into_michiru() else {
+                continue;
+            };
+
+            device
+                .node_or_insert(&node)
+                .await?
+                .property_or_insert(&property)
+                .await?
+                .send(payload)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Marks devices that haven't advertised for a while as lost (or sleeping)
+    pub async fn check_timeouts(&mut self) -> Result<()> {
+        let now = Instant::now();
+
+        for (id, tracked) in &mut self.devices {
+            if tracked.state != DeviceState::Ready {
+                continue;
+            }
+
+            let timeout = self
+                .config
+                .timeout
+                .timeout(tracked.interval.unwrap_or_default());
+
+            if now.duration_since(tracked.last_seen) > timeout {
+                let state = self.config.timeout.state.into();
+
+                tracing::info!(id, ?state, "Device timed out");
+                tracked.device.set_state(state).await?;
+                tracked.state = state;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TrackedDevice {
+    fn seen(&mut self, now: Instant) {
+        let delta = now.duration_since(self.last_seen);
+        self.last_seen = now;
+
+        if self.fixed_interval || delta < REPEAT_WINDOW {
+            return;
+        }
+
+        self.interval = Some(match self.interval {
+            Some(interval) => (interval * 3 + delta) / 4,
+            None => delta,
+        });
+    }
+}
+
+fn parse_address(address: &str) -> Result<[u8; 6]> {
+    let mut out = [0; 6];
+    hex::decode_to_slice(address.replace(':', ""), &mut out)
+        .with_context(|| format!("Invalid MAC address {address:?}"))?;
+
+    Ok(out)
+}
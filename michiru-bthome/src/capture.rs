@@ -0,0 +1,116 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
+    sync::mpsc,
+};
+use uuid::Uuid;
+
+/// A single service data advertisement, as received from the scanner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Advertisement {
+    pub timestamp: DateTime<Utc>,
+    pub address: String,
+    pub name: String,
+    pub rssi: Option<i16>,
+    pub uuid: Uuid,
+    #[serde(with = "hex")]
+    pub data: Vec<u8>,
+}
+
+/// Appends advertisements to a JSON-lines file
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub async fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Failed to open capture file {}", path.display()))?;
+
+        Ok(Self { file: BufWriter::new(file) })
+    }
+
+    pub async fn record(&mut self, advertisement: &Advertisement) -> Result<()> {
+        let mut line = serde_json::to_vec(advertisement)?;
+        line.push(b'\n');
+
+        self.file.write_all(&line).await?;
+        self.file.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Feeds a capture file into `tx`, keeping the original spacing between advertisements
+pub async fn replay(path: impl AsRef<Path>, tx: mpsc::Sender<Advertisement>) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .await
+        .with_context(|| format!("Failed to open capture file {}", path.display()))?;
+
+    let mut lines = BufReader::new(file).lines();
+    let mut previous: Option<DateTime<Utc>> = None;
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let advertisement = match serde_json::from_str::<Advertisement>(&line) {
+            Ok(advertisement) => advertisement,
+            Err(e) => {
+                tracing::warn!(?e, "Skipping invalid capture line");
+                continue;
+            }
+        };
+
+        if let Some(previous) = previous {
+            let delay = (advertisement.timestamp - previous)
+                .to_std()
+                .unwrap_or(Duration::ZERO);
+
+            tokio::time::sleep(delay).await;
+        }
+
+        previous = Some(advertisement.timestamp);
+
+        if tx.send(advertisement).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advertisement_json() {
+        let advertisement = Advertisement {
+            timestamp: "2023-10-01T12:00:00Z".parse().unwrap(),
+            address: "A4:C1:38:00:11:22".into(),
+            name: "ATC_001122".into(),
+            rssi: Some(-70),
+            uuid: uuid::uuid!("0000181c-0000-1000-8000-00805f9b34fb"),
+            data: vec![0x02, 0x00, 0xa5],
+        };
+
+        let json = serde_json::to_string(&advertisement).unwrap();
+        assert!(json.contains(r#""data":"0200a5""#), "{json}");
+
+        let parsed = serde_json::from_str::<Advertisement>(&json).unwrap();
+        assert_eq!(parsed, advertisement);
+    }
+}
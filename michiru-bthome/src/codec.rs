@@ -0,0 +1,318 @@
+use aes::Aes128;
+use anyhow::{anyhow, bail, ensure, Result};
+use bytes::{Buf, BufMut};
+use ccm::{
+    aead::{generic_array::GenericArray, AeadInPlace, KeyInit},
+    consts::{U12, U4},
+    Ccm,
+};
+use michiru_device::{DataType, Format, NodeAttributes, Payload, PropertyAttributes, Unit};
+
+/// Service data UUID of unencrypted BTHome (v1) advertisements
+pub const UUID: u16 = 0x181c;
+/// Service data UUID of encrypted BTHome (v1) advertisements
+pub const UUID_ENCRYPTED: u16 = 0x181e;
+
+type Cipher = Ccm<Aes128, U4, U12>;
+
+fn nonce(address: [u8; 6], counter: [u8; 4]) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..6].copy_from_slice(&address);
+    nonce[6..8].copy_from_slice(&UUID_ENCRYPTED.to_le_bytes());
+    nonce[8..].copy_from_slice(&counter);
+    nonce
+}
+
+/// Decrypts the service data of an encrypted advertisement into the plain object list
+pub fn decrypt(data: &[u8], address: [u8; 6], key: &[u8; 16]) -> Result<Vec<u8>> {
+    ensure!(data.len() >= 8, "encrypted advertisement too short");
+
+    let (payload, rest) = data.split_at(data.len() - 8);
+    let (counter, tag) = rest.split_at(4);
+
+    let mut out = payload.to_vec();
+    Cipher::new(GenericArray::from_slice(key))
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(&nonce(address, counter.try_into().unwrap())),
+            &[0x11],
+            &mut out,
+            GenericArray::from_slice(tag),
+        )
+        .map_err(|_| anyhow!("failed to decrypt advertisement"))?;
+
+    Ok(out)
+}
+
+pub fn encrypt(data: &[u8], address: [u8; 6], key: &[u8; 16], counter: u32) -> Vec<u8> {
+    let counter = counter.to_le_bytes();
+
+    let mut out = data.to_vec();
+    let tag = Cipher::new(GenericArray::from_slice(key))
+        .encrypt_in_place_detached(
+            GenericArray::from_slice(&nonce(address, counter)),
+            &[0x11],
+            &mut out,
+        )
+        .expect("payload fits in a single CCM message");
+
+    out.extend_from_slice(&counter);
+    out.extend_from_slice(&tag);
+    out
+}
+
+fn node_battery() -> NodeAttributes {
+    NodeAttributes {
+        id: "battery".into(),
+        name: "Battery".into(),
+        type_: "Battery".into(),
+        properties: vec![],
+    }
+}
+
+fn node_thermometer() -> NodeAttributes {
+    NodeAttributes {
+        id: "thermometer".into(),
+        name: "Thermometer".into(),
+        type_: "Thermometer".into(),
+        properties: vec![],
+    }
+}
+
+fn node_hygrometer() -> NodeAttributes {
+    NodeAttributes {
+        id: "hygrometer".into(),
+        name: "Hygrometer".into(),
+        type_: "Hygrometer".into(),
+        properties: vec![],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Object {
+    PacketId(u8),
+    Battery(f32),
+    Temperature(f32),
+    Humidity(f32),
+    Voltage(f32),
+    Power(bool),
+}
+
+impl Object {
+    pub fn decode(mut data: impl Buf) -> Result<Vec<Self>> {
+        let mut out = vec![];
+
+        while data.has_remaining() {
+            let header = data.get_u8();
+            let len = (header & 0b11111) as usize;
+            let ty = header >> 5;
+
+            ensure!(len > 0, "object without object id");
+            ensure!(
+                data.remaining() >= len,
+                "object length {len} exceeds remaining {} bytes",
+                data.remaining()
+            );
+
+            let mut data = data.copy_to_bytes(len);
+
+            let object_id = data.get_u8();
+            let value = match (len, ty) {
+                (2, 0) => data.get_u8() as f32,
+                (3, 0) => data.get_u16_le() as f32,
+                (2, 1) => data.get_i8() as f32,
+                (3, 1) => data.get_i16_le() as f32,
+                (5, 2) => data.get_f32_le(),
+                _ => bail!("unimplemented object type {ty} with length {len}"),
+            };
+
+            let obj = match object_id {
+                0x00 => Self::PacketId(value as u8),
+                0x01 => Self::Battery(value),
+                0x02 => Self::Temperature(value / 100.),
+                0x03 => Self::Humidity(value / 100.),
+                0x0c => Self::Voltage(value / 1000.),
+                0x10 => Self::Power(value > 0.),
+                _ => bail!("unimplemented object id {object_id:#04x}"),
+            };
+
+            out.push(obj);
+        }
+
+        Ok(out)
+    }
+
+    pub fn encode(objects: &[Self], buf: &mut impl BufMut) {
+        for object in objects {
+            object.encode_one(buf);
+        }
+    }
+
+    fn encode_one(&self, buf: &mut impl BufMut) {
+        // header is the data type in the upper 3 bits and the length
+        // (including the object id) in the lower 5
+        match *self {
+            Self::PacketId(v) => {
+                buf.put_slice(&[0x02, 0x00, v]);
+            }
+            Self::Battery(v) => {
+                buf.put_slice(&[0x02, 0x01, v.round() as u8]);
+            }
+            Self::Temperature(v) => {
+                buf.put_slice(&[0x23, 0x02]);
+                buf.put_i16_le((v * 100.).round() as i16);
+            }
+            Self::Humidity(v) => {
+                buf.put_slice(&[0x03, 0x03]);
+                buf.put_u16_le((v * 100.).round() as u16);
+            }
+            Self::Voltage(v) => {
+                buf.put_slice(&[0x03, 0x0c]);
+                buf.put_u16_le((v * 1000.).round() as u16);
+            }
+            Self::Power(v) => {
+                buf.put_slice(&[0x02, 0x10, v as u8]);
+            }
+        }
+    }
+
+    /// Returns `None` for objects that only carry transport metadata
+    pub fn into_michiru(self) -> Option<(NodeAttributes, PropertyAttributes, Payload)> {
+        Some(match self {
+            Object::PacketId(_) => return None,
+            Object::Battery(v) => (
+                node_battery(),
+                PropertyAttributes {
+                    id: "battery".into(),
+                    name: "Battery".into(),
+                    datatype: DataType::Float,
+                    settable: false,
+                    retained: true,
+                    unit: Some(Unit::Percent),
+                    format: Some(Format::FloatRange(0., 100.)),
+                },
+                Payload::Float(v as f64),
+            ),
+            Object::Temperature(v) => (
+                node_thermometer(),
+                PropertyAttributes {
+                    id: "temperature".into(),
+                    name: "Temperature".into(),
+                    datatype: DataType::Float,
+                    settable: false,
+                    retained: true,
+                    unit: Some(Unit::DegreeCelsius),
+                    format: None,
+                },
+                Payload::Float(v as f64),
+            ),
+            Object::Humidity(v) => (
+                node_hygrometer(),
+                PropertyAttributes {
+                    id: "humidity".into(),
+                    name: "Humidity".into(),
+                    datatype: DataType::Float,
+                    settable: false,
+                    retained: true,
+                    unit: Some(Unit::Percent),
+                    format: Some(Format::FloatRange(0., 100.)),
+                },
+                Payload::Float(v as f64),
+            ),
+            Object::Voltage(v) => (
+                node_battery(),
+                PropertyAttributes {
+                    id: "voltage".into(),
+                    name: "Battery voltage".into(),
+                    datatype: DataType::Float,
+                    settable: false,
+                    retained: true,
+                    unit: Some(Unit::Volts),
+                    format: None,
+                },
+                Payload::Float(v as f64),
+            ),
+            Object::Power(v) => (
+                node_battery(),
+                PropertyAttributes {
+                    id: "power".into(),
+                    name: "Power".into(),
+                    datatype: DataType::Boolean,
+                    settable: false,
+                    retained: true,
+                    unit: None,
+                    format: None,
+                },
+                Payload::Boolean(v),
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn decode_advertisement() {
+        let data = [
+            0x02, 0x00, 0xa5, 0x02, 0x01, 0x64, 0x23, 0x02, 0xca, 0x09, 0x03, 0x03, 0xbf, 0x13,
+            0x03, 0x0c, 0x5e, 0x0b,
+        ];
+
+        assert_eq!(Object::decode(&data[..]).unwrap(), vec![
+            Object::PacketId(0xa5),
+            Object::Battery(100.),
+            Object::Temperature(25.06),
+            Object::Humidity(50.55),
+            Object::Voltage(2.91),
+        ]);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert!(Object::decode(&[0x23, 0x02, 0xca][..]).is_err());
+        assert!(Object::decode(&[0x00][..]).is_err());
+    }
+
+    #[test]
+    fn encryption_roundtrip() {
+        let address = [0xa4, 0xc1, 0x38, 0x00, 0x11, 0x22];
+        let key = [0x23; 16];
+        let data = [0x02, 0x00, 0xa5, 0x02, 0x01, 0x64];
+
+        let encrypted = encrypt(&data, address, &key, 7);
+        assert_eq!(encrypted.len(), data.len() + 8);
+        assert_ne!(&encrypted[..data.len()], &data);
+
+        assert_eq!(decrypt(&encrypted, address, &key).unwrap(), data);
+        assert!(decrypt(&encrypted, address, &[0x42; 16]).is_err());
+        assert!(decrypt(&encrypted[..4], address, &key).is_err());
+    }
+
+    fn object() -> impl Strategy<Value = Object> {
+        prop_oneof![
+            any::<u8>().prop_map(Object::PacketId),
+            any::<u8>().prop_map(|v| Object::Battery(v as f32)),
+            any::<i16>().prop_map(|v| Object::Temperature(v as f32 / 100.)),
+            any::<u16>().prop_map(|v| Object::Humidity(v as f32 / 100.)),
+            any::<u16>().prop_map(|v| Object::Voltage(v as f32 / 1000.)),
+            any::<bool>().prop_map(Object::Power),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn decode_never_panics(data in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let _ = Object::decode(&data[..]);
+        }
+
+        #[test]
+        fn roundtrip(objects in proptest::collection::vec(object(), 0..8)) {
+            let mut buf = vec![];
+            Object::encode(&objects, &mut buf);
+            prop_assert_eq!(Object::decode(&buf[..]).unwrap(), objects);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use michiru_device::{DeviceState, MqttConfig};
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub mqtt: MqttConfig,
+    pub scanner: ScannerConfig,
+    pub timeout: TimeoutConfig,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub devices: HashMap<String, DeviceConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ScannerConfig {
+    /// Prefix of the adapter info, e.g. `hci1`. Uses the first adapter if unset.
+    pub adapter: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Number of advertisement intervals without a packet before a device times out
+    pub multiplier: f64,
+    /// Lower bound on the timeout, in seconds
+    pub minimum: u64,
+    pub state: TimeoutState,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeoutState {
+    Lost,
+    Sleeping,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    pub name: Option<String>,
+    pub id: Option<String>,
+    /// Expected advertisement interval in seconds, estimated from traffic if unset
+    pub interval: Option<u64>,
+    #[serde(deserialize_with = "deserialize_bind_key")]
+    pub bind_key: Option<[u8; 16]>,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            multiplier: 5.,
+            minimum: 60,
+            state: TimeoutState::Lost,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    /// Saturates instead of overflowing for very long intervals
+    pub fn timeout(&self, interval: Duration) -> Duration {
+        Duration::try_from_secs_f64(interval.as_secs_f64() * self.multiplier)
+            .unwrap_or(Duration::MAX)
+            .max(Duration::from_secs(self.minimum))
+    }
+}
+
+impl From<TimeoutState> for DeviceState {
+    fn from(state: TimeoutState) -> Self {
+        match state {
+            TimeoutState::Lost => DeviceState::Lost,
+            TimeoutState::Sleeping => DeviceState::Sleeping,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        let mut config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        config.allow = config.allow.iter().map(|a| normalize(a)).collect();
+        config.deny = config.deny.iter().map(|a| normalize(a)).collect();
+        config.devices = config
+            .devices
+            .into_iter()
+            .map(|(address, device)| (normalize(&address), device))
+            .collect();
+
+        let multiplier = config.timeout.multiplier;
+        anyhow::ensure!(
+            multiplier.is_finite() && multiplier >= 0.,
+            "Invalid timeout multiplier {multiplier}"
+        );
+
+        for device in config.devices.values() {
+            if let Some(id) = &device.id {
+                anyhow::ensure!(michiru_device::valid_topic_id(id), "Invalid device id {id:?}");
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn is_allowed(&self, address: &str) -> bool {
+        let address = normalize(address);
+
+        (self.allow.is_empty() || self.allow.contains(&address)) && !self.deny.contains(&address)
+    }
+
+    pub fn device(&self, address: &str) -> Option<&DeviceConfig> {
+        self.devices.get(&normalize(address))
+    }
+}
+
+/// Lowercase address without delimiters, as used in the default Homie device id
+pub fn normalize(address: &str) -> String {
+    address.replace(':', "").to_lowercase()
+}
+
+fn deserialize_bind_key<'de, D>(deserializer: D) -> Result<Option<[u8; 16]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(key) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    let mut out = [0; 16];
+    hex::decode_to_slice(key, &mut out).map_err(serde::de::Error::custom)?;
+
+    Ok(Some(out))
+}
@@ -0,0 +1,109 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use anyhow::Result;
+use michiru_device::{
+    DataType, Device, DeviceBuilder, MqttConfig, NodeAttributes, Payload, PropertyAttributes, Unit,
+};
+
+const DEVICE_ID: &str = "michiru-bthome";
+const NODE_ID: &str = "scanner";
+
+/// Counters shared between the scanner and the bridge
+#[derive(Debug, Default)]
+pub struct Health {
+    pub adapters: AtomicUsize,
+    pub adapter: Mutex<String>,
+    pub advertisements: AtomicU64,
+    pub decode_errors: AtomicU64,
+    pub restarts: AtomicU64,
+}
+
+/// Homie device exposing the scanner health
+pub struct HealthDevice {
+    device: Device,
+    last_advertisements: u64,
+    last_publish: Instant,
+}
+
+impl HealthDevice {
+    pub async fn new(config: &MqttConfig) -> Result<Self> {
+        let property = |id: &str, name: &str, datatype, unit| PropertyAttributes {
+            id: id.into(),
+            name: name.into(),
+            datatype,
+            settable: false,
+            retained: true,
+            unit,
+            format: None,
+        };
+
+        let device = DeviceBuilder::new(config.options(DEVICE_ID), DEVICE_ID, "BTHome bridge")
+            .await?
+            .node(NodeAttributes {
+                id: NODE_ID.into(),
+                name: "Scanner".into(),
+                type_: "Bluetooth LE".into(),
+                properties: vec![
+                    property("adapter", "Adapter", DataType::String, None),
+                    property("adapters", "Adapters", DataType::Integer, Some(Unit::Count)),
+                    property(
+                        "advertisements",
+                        "Advertisements",
+                        DataType::Float,
+                        Some(Unit::Other("1/s".into())),
+                    ),
+                    property(
+                        "decode-errors",
+                        "Decode errors",
+                        DataType::Integer,
+                        Some(Unit::Count),
+                    ),
+                    property("restarts", "Scan restarts", DataType::Integer, Some(Unit::Count)),
+                ],
+            })
+            .await?
+            .build()
+            .await?;
+
+        Ok(Self {
+            device,
+            last_advertisements: 0,
+            last_publish: Instant::now(),
+        })
+    }
+
+    pub async fn publish(&mut self, health: &Health) -> Result<()> {
+        let now = Instant::now();
+        let advertisements = health.advertisements.load(Ordering::Relaxed);
+        let rate = (advertisements - self.last_advertisements) as f64
+            / now.duration_since(self.last_publish).as_secs_f64();
+
+        self.last_advertisements = advertisements;
+        self.last_publish = now;
+
+        let adapter = health.adapter.lock().unwrap().clone();
+
+        let node = self.device.node(NODE_ID).unwrap();
+
+        for (id, payload) in [
+            ("adapter", Payload::String(adapter)),
+            ("adapters", Payload::Integer(health.adapters.load(Ordering::Relaxed) as i64)),
+            ("advertisements", Payload::Float(rate)),
+            (
+                "decode-errors",
+                Payload::Integer(health.decode_errors.load(Ordering::Relaxed) as i64),
+            ),
+            ("restarts", Payload::Integer(health.restarts.load(Ordering::Relaxed) as i64)),
+        ] {
+            node.property(id).await.unwrap().send(payload).await?;
+        }
+
+        Ok(())
+    }
+}
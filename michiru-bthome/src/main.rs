@@ -1,19 +1,22 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use btleplug::{
-    api::{
-        bleuuid::uuid_from_u16, Central, CentralEvent, Manager as _, Peripheral as _, ScanFilter,
-    },
-    platform::Manager,
-};
-use futures::StreamExt;
-use michiru_device::{
-    DataType, DeviceBuilder, MqttOptions, NodeAttributes, Payload, PropertyAttributes, Unit,
+use clap::Parser;
+use michiru_bthome::{
+    capture::{self, Recorder},
+    codec::Object,
 };
+use tokio::sync::mpsc;
 
-use crate::bthome::Object;
+use crate::{
+    bridge::Bridge,
+    config::Config,
+    health::{Health, HealthDevice},
+};
 
-mod bthome;
+mod bridge;
+mod config;
+mod health;
+mod scanner;
 
 #[derive(Debug, PartialEq)]
 pub struct Update {
@@ -21,109 +24,76 @@ pub struct Update {
     object: Object,
 }
 
+#[derive(Debug, Parser)]
+struct Args {
+    /// Path to the TOML configuration file
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Append every received advertisement to this JSON-lines file
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Read advertisements from a capture file instead of scanning
+    #[arg(long)]
+    replay: Option<PathBuf>,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    let manager = Manager::new().await?;
+    let args = Args::parse();
 
-    let adapters = manager.adapters().await?;
-    let central = adapters.into_iter().next().expect("no adapters found");
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
 
-    let mut events = central.events().await?;
+    let health = Arc::new(Health::default());
 
-    central.start_scan(ScanFilter::default()).await?;
+    let (tx, mut rx) = mpsc::channel(64);
 
-    let mut devices = HashMap::new();
+    let source = match args.replay {
+        Some(path) => tokio::spawn(capture::replay(path, tx)),
+        None => tokio::spawn(scanner::scan(config.scanner.adapter.clone(), health.clone(), tx)),
+    };
 
-    while let Some(event) = events.next().await {
-        if let CentralEvent::ServiceDataAdvertisement { id, service_data } = event {
-            if let Some(data) = service_data.get(&uuid_from_u16(0x181c)) {
-                let peripherals = central.peripherals().await?;
+    let mut recorder = match args.record {
+        Some(path) => Some(Recorder::create(path).await?),
+        None => None,
+    };
 
-                let Some(peripheral) = peripherals.iter().find(|p| p.id() == id) else {
-                    eprintln!("got ad from unknown peripheral");
-                    continue;
-                };
+    let mut health_device = HealthDevice::new(&config.mqtt).await?;
 
-                let Some(properties) = peripheral.properties().await? else {
-                    eprintln!("got ad from peripheral with no properties");
-                    continue;
-                };
+    let mut bridge = Bridge::new(config, health.clone());
 
-                let Some(name) = properties.local_name else {
-                    eprintln!("got ad from peripheral with no name");
-                    continue;
-                };
+    let mut timeouts = tokio::time::interval(Duration::from_secs(10));
+    let mut health_updates = tokio::time::interval(Duration::from_secs(30));
 
-                #[cfg(not(target_os = "macos"))]
-                let id = properties.address.to_string_no_delim().to_lowercase();
-                #[cfg(target_os = "macos")]
-                let id = id.to_string().to_lowercase();
-                // let id = name.to_lowercase().replace(
-                //     |c: char| !(c.is_lowercase() || c.is_ascii_digit() || c == '-'),
-                //     "-",
-                // );
-
-                let id = format!("bthome-{id}");
-
-                const LINK_ID: &str = "link";
-                const RSSI_ID: &str = "rssi";
-
-                let device = match devices.entry(id.clone()) {
-                    Entry::Occupied(entry) => entry.into_mut(),
-                    Entry::Vacant(entry) => entry.insert({
-                        let options = MqttOptions::new(id.clone(), "michiru.fbk.red", 1883);
-
-                        DeviceBuilder::new(options, id, name.clone())
-                            .await?
-                            .node(NodeAttributes {
-                                id: LINK_ID.into(),
-                                name: "Link".into(),
-                                type_: "Bluetooth LE".into(),
-                                properties: vec![PropertyAttributes {
-                                    id: RSSI_ID.into(),
-                                    name: "RSSI".into(),
-                                    datatype: DataType::Integer,
-                                    settable: false,
-                                    retained: true,
-                                    unit: Some(Unit::Other("dBm".into())),
-                                    format: None,
-                                }],
-                            })
-                            .await?
-                            .build()
-                            .await?
-                    }),
+    loop {
+        tokio::select! {
+            advertisement = rx.recv() => {
+                let Some(advertisement) = advertisement else {
+                    break;
                 };
 
-                if let Some(rssi) = properties.rssi {
-                    device
-                        .node(LINK_ID)
-                        .unwrap()
-                        .property(RSSI_ID)
-                        .await
-                        .unwrap()
-                        .send(Payload::Integer(rssi as i64))
-                        .await?;
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&advertisement).await?;
                 }
 
-                for object in Object::decode(data.as_slice())? {
-                    let (node, property, payload) = object.into_michiru();
-
-                    device
-                        .node_or_insert(&node)
-                        .await?
-                        .property_or_insert(&property)
-                        .await?
-                        .send(payload)
-                        .await?;
-                }
+                bridge.handle(advertisement).await?;
+            }
+            _ = timeouts.tick() => {
+                bridge.check_timeouts().await?;
+            }
+            _ = health_updates.tick() => {
+                health_device.publish(&health).await?;
             }
         }
     }
 
-    Ok(())
+    source.await?
 }
@@ -0,0 +1,135 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use btleplug::{
+    api::{
+        bleuuid::uuid_from_u16, Central, CentralEvent, Manager as _, Peripheral as _, ScanFilter,
+    },
+    platform::{Adapter, Manager},
+};
+use chrono::Utc;
+use futures::StreamExt;
+use michiru_bthome::{capture::Advertisement, codec};
+use tokio::sync::mpsc;
+
+use crate::health::Health;
+
+/// Delay before re-acquiring the adapter after the scan failed
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+/// Restart the scan if the adapter has been silent for this long
+const WATCHDOG: Duration = Duration::from_secs(120);
+
+pub async fn scan(
+    adapter: Option<String>,
+    health: Arc<Health>,
+    tx: mpsc::Sender<Advertisement>,
+) -> Result<()> {
+    loop {
+        match scan_adapter(adapter.as_deref(), &health, &tx).await {
+            Ok(()) => tracing::warn!("Scan ended"),
+            Err(e) => tracing::error!(?e, "Scan failed"),
+        }
+
+        if tx.is_closed() {
+            return Ok(());
+        }
+
+        health.restarts.fetch_add(1, Ordering::Relaxed);
+        health.adapter.lock().unwrap().clear();
+
+        tokio::time::sleep(RESTART_DELAY).await;
+    }
+}
+
+async fn find_adapter(adapter: Option<&str>, health: &Health) -> Result<Adapter> {
+    let manager = Manager::new().await?;
+    let adapters = manager.adapters().await?;
+
+    health.adapters.store(adapters.len(), Ordering::Relaxed);
+
+    for candidate in adapters {
+        let info = candidate.adapter_info().await?;
+
+        let matches = match adapter {
+            Some(name) => info.starts_with(name),
+            None => true,
+        };
+
+        if matches {
+            tracing::info!(info, "Using adapter");
+            *health.adapter.lock().unwrap() = info;
+            return Ok(candidate);
+        }
+    }
+
+    anyhow::bail!("no matching adapter found")
+}
+
+async fn scan_adapter(
+    adapter: Option<&str>,
+    health: &Health,
+    tx: &mpsc::Sender<Advertisement>,
+) -> Result<()> {
+    let central = find_adapter(adapter, health).await?;
+
+    let mut events = central.events().await?;
+
+    central
+        .start_scan(ScanFilter::default())
+        .await
+        .context("Failed to start scan")?;
+
+    let uuids = [uuid_from_u16(codec::UUID), uuid_from_u16(codec::UUID_ENCRYPTED)];
+
+    while let Some(event) = tokio::time::timeout(WATCHDOG, events.next())
+        .await
+        .context("No events from adapter")?
+    {
+        if let CentralEvent::ServiceDataAdvertisement { id, service_data } = event {
+            if let Some((&uuid, data)) = service_data.iter().find(|(u, _)| uuids.contains(u)) {
+                let peripherals = central.peripherals().await?;
+
+                let Some(peripheral) = peripherals.iter().find(|p| p.id() == id) else {
+                    eprintln!("got ad from unknown peripheral");
+                    continue;
+                };
+
+                let Some(properties) = peripheral.properties().await? else {
+                    eprintln!("got ad from peripheral with no properties");
+                    continue;
+                };
+
+                let Some(name) = properties.local_name else {
+                    eprintln!("got ad from peripheral with no name");
+                    continue;
+                };
+
+                #[cfg(not(target_os = "macos"))]
+                let address = properties.address.to_string();
+                #[cfg(target_os = "macos")]
+                let address = id.to_string();
+
+                let advertisement = Advertisement {
+                    timestamp: Utc::now(),
+                    address,
+                    name,
+                    rssi: properties.rssi,
+                    uuid,
+                    data: data.clone(),
+                };
+
+                if tx.send(advertisement).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // best effort, the adapter may already be gone
+    let _ = central.stop_scan().await;
+
+    Ok(())
+}
@@ -93,7 +93,7 @@ impl From<Unit> for Vec<u8> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeviceState {
     Init,
     Ready,
@@ -0,0 +1,35 @@
+use rumqttc::MqttOptions;
+use serde::Deserialize;
+
+/// Broker connection shared by the bridges' config files
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "michiru.fbk.red".into(),
+            port: 1883,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+impl MqttConfig {
+    pub fn options(&self, client_id: impl Into<String>) -> MqttOptions {
+        let mut options = MqttOptions::new(client_id, &self.host, self.port);
+
+        if let Some(username) = &self.username {
+            options.set_credentials(username, self.password.as_deref().unwrap_or_default());
+        }
+
+        options
+    }
+}
@@ -1,15 +1,18 @@
+use std::sync::{Arc, Mutex};
+
 use anyhow::{Context, Result};
 use itertools::Itertools;
-use rumqttc::{LastWill, QoS};
-use tokio::sync::RwLock;
+use rumqttc::{ConnectionError, Event, LastWill, Outgoing, Packet, QoS};
+use tokio::sync::{mpsc, RwLock};
 
 mod attributes;
+mod config;
 mod payload;
 mod utils;
 
 pub use rumqttc::MqttOptions;
 
-pub use self::{attributes::*, payload::*, utils::*};
+pub use self::{attributes::*, config::*, payload::*, utils::*};
 
 pub const BASE_TOPIC: &str = "homie";
 pub const QOS: QoS = QoS::AtLeastOnce;
@@ -25,6 +28,17 @@ pub struct Device {
     id: String,
     // TODO: can we do this without synchronisation?
     nodes: Vec<RwLock<NodeAttributes>>,
+    /// Only set once [`Device::take_commands`] is called, commands are dropped
+    /// until then
+    commands: Arc<Mutex<Option<mpsc::UnboundedSender<SetCommand>>>>,
+}
+
+/// A value published to the `/set` topic of a property
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCommand {
+    pub node: String,
+    pub property: String,
+    pub payload: String,
 }
 
 pub struct Node<'a> {
@@ -59,18 +73,44 @@ impl DeviceBuilder {
         ));
 
         let (mqtt, mut connection) = rumqttc::AsyncClient::new(options, 10);
+        let commands = Arc::new(Mutex::new(None::<mpsc::UnboundedSender<SetCommand>>));
 
         tokio::spawn({
             let id = id.clone();
+            let commands = commands.clone();
             async move {
+                let mut disconnecting = false;
                 loop {
-                    let event = connection.poll().await.unwrap();
+                    let event = match connection.poll().await {
+                        Ok(event) => event,
+                        // the broker closing the connection after a disconnect
+                        Err(_) if disconnecting => break,
+                        Err(ConnectionError::RequestsDone) => break,
+                        Err(e) => panic!("MQTT connection failed: {e}"),
+                    };
                     tracing::trace!(?id, "Event = {:?}", event);
+
+                    if let Event::Outgoing(Outgoing::Disconnect) = event {
+                        disconnecting = true;
+                    }
+
+                    if let Event::Incoming(Packet::Publish(publish)) = event {
+                        if let Some(command) = parse_set_command(&id, &publish) {
+                            // nobody listening is fine
+                            if let Some(tx) = &*commands.lock().unwrap() {
+                                let _ = tx.send(command);
+                            }
+                        }
+                    }
                 }
             }
         });
 
-        let device = Device { mqtt, id, nodes: vec![] };
+        mqtt.subscribe(format!("{BASE_TOPIC}/{id}/+/+/set"), QOS)
+            .await
+            .context("Failed to subscribe to set topics")?;
+
+        let device = Device { mqtt, id, nodes: vec![], commands };
 
         device.send_topic("$homie", HOMIE_VERSION).await?;
         device.send_topic("$state", DeviceState::Init).await?;
@@ -168,10 +208,71 @@ impl Device {
         Ok(self.node(&id).unwrap())
     }
 
+    /// Values published to settable properties, can only be taken once
+    pub fn take_commands(&mut self) -> Option<mpsc::UnboundedReceiver<SetCommand>> {
+        let mut commands = self.commands.lock().unwrap();
+        if commands.is_some() {
+            return None;
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        *commands = Some(tx);
+        Some(rx)
+    }
+
+    pub async fn set_state(&self, state: DeviceState) -> Result<()> {
+        self.send_topic("$state", state).await
+    }
+
     pub async fn disconnect(self) -> Result<()> {
         self.send_topic("$state", DeviceState::Disconnected).await?;
         self.mqtt.disconnect().await.context("Failed to disconnect")
     }
+
+    /// Disconnects and clears all retained topics of the device, `$state`
+    /// last so controllers see it go away rather than half of it
+    pub async fn remove(self) -> Result<()> {
+        self.send_topic("$state", DeviceState::Disconnected).await?;
+
+        let mut topics = vec!["$homie".to_string(), "$name".into(), "$nodes".into()];
+        for node in &self.nodes {
+            let node = node.read().await;
+            for attribute in ["$name", "$type", "$properties"] {
+                topics.push(format!("{}/{attribute}", node.id));
+            }
+
+            for property in &node.properties {
+                let topic = format!("{}/{}", node.id, property.id);
+                for attribute in
+                    ["$name", "$datatype", "$settable", "$retained", "$format", "$unit"]
+                {
+                    topics.push(format!("{topic}/{attribute}"));
+                }
+                topics.push(topic);
+            }
+        }
+        topics.push("$state".into());
+
+        for topic in topics {
+            self.send_topic(&topic, vec![]).await?;
+        }
+
+        self.mqtt.disconnect().await.context("Failed to disconnect")
+    }
+}
+
+fn parse_set_command(id: &str, publish: &rumqttc::Publish) -> Option<SetCommand> {
+    let topic = publish
+        .topic
+        .strip_prefix(&format!("{BASE_TOPIC}/{id}/"))?
+        .strip_suffix("/set")?;
+    let (node, property) = topic.split_once('/')?;
+
+    Some(SetCommand {
+        node: node.to_string(),
+        property: property.to_string(),
+        payload: String::from_utf8_lossy(&publish.payload).into_owned(),
+    })
 }
 
 impl Node<'_> {
@@ -317,7 +418,7 @@ impl Property<'_> {
                 Color::Hsv(h, s, v) => format!("{},{},{}", h, s, v).into_bytes(),
             },
             Payload::DateTime(v) => v.to_rfc3339().into_bytes(),
-            Payload::Duration(_) => todo!(),
+            Payload::Duration(v) => format!("PT{}S", v.num_seconds()).into_bytes(),
         };
 
         self.node
@@ -8,6 +8,8 @@ pub enum DataType {
     String,
     Enum,
     Color,
+    DateTime,
+    Duration,
 }
 
 impl From<DataType> for Vec<u8> {
@@ -19,6 +21,8 @@ impl From<DataType> for Vec<u8> {
             DataType::String => b"string".to_vec(),
             DataType::Enum => b"enum".to_vec(),
             DataType::Color => b"color".to_vec(),
+            DataType::DateTime => b"datetime".to_vec(),
+            DataType::Duration => b"duration".to_vec(),
         }
     }
 }
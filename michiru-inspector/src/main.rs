@@ -70,6 +70,7 @@ impl egui_dock::TabViewer for TabViewer<'_> {
             Pane::MqttTopics => "MQTT Topics".into(),
             Pane::HomieDevices => "Homie Devices".into(),
             Pane::Zigbee2Mqtt(_) => "Zigbee2Mqtt".into(),
+            Pane::Zigbee2MqttBridge => "Zigbee2Mqtt Bridge".into(),
         }
     }
 
@@ -1,6 +1,7 @@
 mod homie_devices;
 mod mqtt_topics;
 mod zigbee2mqtt;
+mod zigbee2mqtt_bridge;
 
 use serde::{Deserialize, Serialize};
 
@@ -11,11 +12,17 @@ pub enum Pane {
     MqttTopics,
     HomieDevices,
     Zigbee2Mqtt(zigbee2mqtt::Zigbee2Mqtt),
+    Zigbee2MqttBridge,
 }
 
 impl Pane {
     pub fn all() -> Vec<Pane> {
-        vec![Pane::MqttTopics, Pane::HomieDevices, Pane::Zigbee2Mqtt(Default::default())]
+        vec![
+            Pane::MqttTopics,
+            Pane::HomieDevices,
+            Pane::Zigbee2Mqtt(Default::default()),
+            Pane::Zigbee2MqttBridge,
+        ]
     }
 
     pub fn ui(&mut self, ui: &mut egui::Ui, state: &mut AppState) {
@@ -23,6 +30,7 @@ impl Pane {
             Pane::MqttTopics => mqtt_topics::MqttTopics.ui(ui, state),
             Pane::HomieDevices => homie_devices::HomieDevices.ui(ui, state),
             Pane::Zigbee2Mqtt(p) => p.ui(ui, state),
+            Pane::Zigbee2MqttBridge => zigbee2mqtt_bridge::Zigbee2MqttBridge.ui(ui, state),
         }
     }
 }
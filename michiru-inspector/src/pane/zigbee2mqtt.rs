@@ -247,6 +247,6 @@ fn feature_ui(feature: &Feature, ui: &mut egui::Ui) {
                 });
             });
         }
-        Feature::List => {}
+        Feature::List { .. } => {}
     }
 }
@@ -0,0 +1,131 @@
+use egui::{CollapsingHeader, Grid, ScrollArea};
+use michiru_zigbee2mqtt::definitions::{BridgeEvent, BridgeInfo, Group};
+
+use crate::state::AppState;
+
+pub struct Zigbee2MqttBridge;
+
+impl Zigbee2MqttBridge {
+    pub fn ui(&mut self, ui: &mut egui::Ui, state: &mut AppState) {
+        ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                match &state.zigbee2mqtt_info {
+                    Some(info) => info_ui(info, ui),
+                    None => {
+                        ui.label("No bridge info received");
+                    }
+                }
+
+                ui.add_space(20.);
+
+                CollapsingHeader::new(format!("Groups ({})", state.zigbee2mqtt_groups.len()))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for group in &state.zigbee2mqtt_groups {
+                            group_ui(group, ui);
+                        }
+                    });
+
+                CollapsingHeader::new(format!("Events ({})", state.zigbee2mqtt_events.len()))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        Grid::new("events")
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (received, event) in state.zigbee2mqtt_events.iter().rev() {
+                                    ui.label(received.format("%H:%M:%S").to_string());
+                                    ui.label(event_name(event));
+                                    ui.label(format!(
+                                        "{} ({})",
+                                        event.friendly_name(),
+                                        event.ieee_address()
+                                    ));
+                                    ui.end_row();
+                                }
+                            });
+                    });
+
+                CollapsingHeader::new(format!("Log ({})", state.zigbee2mqtt_log.len()))
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        Grid::new("log")
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (received, log) in state.zigbee2mqtt_log.iter().rev() {
+                                    ui.label(received.format("%H:%M:%S").to_string());
+                                    ui.strong(log.level.to_string());
+                                    ui.label(&log.message);
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+    }
+}
+
+fn info_ui(info: &BridgeInfo, ui: &mut egui::Ui) {
+    ui.heading(format!("zigbee2mqtt {}", info.version));
+
+    Grid::new("bridge info")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Coordinator");
+            ui.label(format!("{} ({})", info.coordinator.ieee_address, info.coordinator.ty));
+            ui.end_row();
+
+            ui.strong("Channel");
+            ui.label(info.network.channel.to_string());
+            ui.end_row();
+
+            ui.strong("PAN ID");
+            ui.label(format!("0x{:04x}", info.network.pan_id));
+            ui.end_row();
+
+            ui.strong("Extended PAN ID");
+            ui.label(info.network.extended_pan_id.to_string());
+            ui.end_row();
+
+            ui.strong("Permit Join");
+            ui.label(if info.permit_join { "Yes" } else { "No" });
+            ui.end_row();
+
+            ui.strong("Restart Required");
+            ui.label(if info.restart_required { "Yes" } else { "No" });
+            ui.end_row();
+        });
+}
+
+fn group_ui(group: &Group, ui: &mut egui::Ui) {
+    CollapsingHeader::new(format!("{} ({})", group.friendly_name, group.id))
+        .id_source(("group", group.id))
+        .show(ui, |ui| {
+            if let Some(description) = &group.description {
+                ui.label(description);
+            }
+
+            ui.strong("Members");
+            for member in &group.members {
+                ui.label(format!("{} endpoint {}", member.ieee_address, member.endpoint));
+            }
+
+            if !group.scenes.is_empty() {
+                ui.strong("Scenes");
+                for scene in &group.scenes {
+                    ui.label(format!("{} ({})", scene.name, scene.id));
+                }
+            }
+        });
+}
+
+fn event_name(event: &BridgeEvent) -> String {
+    match event {
+        BridgeEvent::DeviceJoined(_) => "Joined".into(),
+        BridgeEvent::DeviceInterview(interview) => format!("Interview {:?}", interview.status),
+        BridgeEvent::DeviceLeave(_) => "Left".into(),
+        BridgeEvent::DeviceAnnounce(_) => "Announced".into(),
+    }
+}
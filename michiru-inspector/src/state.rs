@@ -1,5 +1,11 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Local};
 use itertools::Itertools;
-use michiru_zigbee2mqtt::definitions::DeviceInfo;
+use michiru_zigbee2mqtt::{
+    definitions::{BridgeEvent, BridgeInfo, DeviceInfo, Group, LogMessage},
+    BridgeMessage,
+};
 use tokio::sync::mpsc::UnboundedReceiver;
 
 use crate::topic_tree::{TopicPayload, TopicTree, TopicValue};
@@ -9,8 +15,15 @@ pub struct AppState {
     pub topic_tree: TopicTree,
     pub selected: Option<TopicValue>,
     pub zigbee2mqtt_devices: Vec<Result<DeviceInfo, InvalidZigbee2mqttDevice>>,
+    pub zigbee2mqtt_info: Option<BridgeInfo>,
+    pub zigbee2mqtt_groups: Vec<Group>,
+    /// Oldest first, at most [`MAX_HISTORY`]
+    pub zigbee2mqtt_events: VecDeque<(DateTime<Local>, BridgeEvent)>,
+    pub zigbee2mqtt_log: VecDeque<(DateTime<Local>, LogMessage)>,
 }
 
+pub const MAX_HISTORY: usize = 500;
+
 #[derive(Debug)]
 pub struct InvalidZigbee2mqttDevice {
     pub json: serde_json::Value,
@@ -24,6 +37,10 @@ impl AppState {
             topic_tree: TopicTree::default(),
             selected: None,
             zigbee2mqtt_devices: Vec::new(),
+            zigbee2mqtt_info: None,
+            zigbee2mqtt_groups: Vec::new(),
+            zigbee2mqtt_events: VecDeque::new(),
+            zigbee2mqtt_log: VecDeque::new(),
         }
     }
 
@@ -42,7 +59,34 @@ impl AppState {
                 }
             }
 
+            if let TopicPayload::Json(json) = &value.payload {
+                match BridgeMessage::parse(&value.topic, json) {
+                    Some(Ok(message)) => self.bridge_message(value.received, message),
+                    Some(Err(e)) => tracing::warn!("{e:#}"),
+                    None => {}
+                }
+            }
+
             self.topic_tree.insert(value);
         }
     }
+
+    fn bridge_message(&mut self, received: DateTime<Local>, message: BridgeMessage) {
+        match message {
+            BridgeMessage::Info(info) => self.zigbee2mqtt_info = Some(info),
+            BridgeMessage::Groups(groups) => self.zigbee2mqtt_groups = groups,
+            BridgeMessage::Event(event) => {
+                self.zigbee2mqtt_events.push_back((received, event));
+                if self.zigbee2mqtt_events.len() > MAX_HISTORY {
+                    self.zigbee2mqtt_events.pop_front();
+                }
+            }
+            BridgeMessage::Logging(log) => {
+                self.zigbee2mqtt_log.push_back((received, log));
+                if self.zigbee2mqtt_log.len() > MAX_HISTORY {
+                    self.zigbee2mqtt_log.pop_front();
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,66 @@
+//! Whether zigbee2mqtt and its devices are online
+
+use michiru_device::DeviceState;
+use serde::Deserialize;
+
+pub const BRIDGE_STATE_TOPIC: &str = "zigbee2mqtt/bridge/state";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Availability {
+    Online,
+    Offline,
+}
+
+impl Availability {
+    /// Parses both the legacy `online` and the newer `{"state": "online"}`
+    /// payloads
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct Json {
+            state: Availability,
+        }
+
+        if let Ok(Json { state }) = serde_json::from_slice(payload) {
+            return Some(state);
+        }
+
+        match std::str::from_utf8(payload).ok()?.trim() {
+            "online" => Some(Self::Online),
+            "offline" => Some(Self::Offline),
+            _ => None,
+        }
+    }
+}
+
+/// The Homie state of a device, a bridge that is down disconnects all
+/// devices while a device that is down is lost. Unknown counts as online as
+/// availability tracking is optional in zigbee2mqtt.
+pub fn homie_state(bridge: Option<Availability>, device: Option<Availability>) -> DeviceState {
+    match (bridge, device) {
+        (Some(Availability::Offline), _) => DeviceState::Disconnected,
+        (_, Some(Availability::Offline)) => DeviceState::Lost,
+        _ => DeviceState::Ready,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads() {
+        assert_eq!(Availability::parse(b"online"), Some(Availability::Online));
+        assert_eq!(Availability::parse(b"offline"), Some(Availability::Offline));
+        assert_eq!(Availability::parse(br#"{"state":"online"}"#), Some(Availability::Online));
+        assert_eq!(Availability::parse(br#"{"state":"offline"}"#), Some(Availability::Offline));
+        assert_eq!(Availability::parse(b"maybe"), None);
+        assert_eq!(Availability::parse(br#"{"state":"maybe"}"#), None);
+
+        use Availability::*;
+        assert_eq!(homie_state(None, None), DeviceState::Ready);
+        assert_eq!(homie_state(Some(Online), Some(Offline)), DeviceState::Lost);
+        assert_eq!(homie_state(Some(Offline), Some(Online)), DeviceState::Disconnected);
+        assert_eq!(homie_state(Some(Offline), Some(Offline)), DeviceState::Disconnected);
+    }
+}
@@ -0,0 +1,421 @@
+//! Client for the zigbee2mqtt bridge API
+//!
+//! Requests are published to `zigbee2mqtt/bridge/request/<path>` with a
+//! transaction id that zigbee2mqtt echoes on `zigbee2mqtt/bridge/response/<path>`.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tokio::{
+    sync::{oneshot, watch},
+    task::JoinHandle,
+};
+
+pub const REQUEST_TOPIC: &str = "zigbee2mqtt/bridge/request";
+pub const RESPONSE_TOPIC: &str = "zigbee2mqtt/bridge/response";
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Firmware updates are slow, they can take most of an hour on busy networks
+pub const OTA_UPDATE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+type Pending = Arc<Mutex<HashMap<String, oneshot::Sender<Response>>>>;
+
+pub struct Bridge {
+    client: AsyncClient,
+    /// Holds a client too, so it only stops when aborted
+    eventloop_task: JoinHandle<()>,
+    pending: Pending,
+    /// Whether responses can be received
+    subscribed: watch::Receiver<bool>,
+    prefix: String,
+    transaction: AtomicU64,
+    timeout: Duration,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Response {
+    #[serde(default)]
+    pub data: Value,
+    pub status: String,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub transaction: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Firmware {
+    #[serde(default)]
+    pub software_build_id: Option<String>,
+    #[serde(default)]
+    pub date_code: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OtaUpdate {
+    #[serde(default)]
+    pub from: Option<Firmware>,
+    #[serde(default)]
+    pub to: Option<Firmware>,
+}
+
+impl Response {
+    /// The data of a successful response
+    pub fn into_result<T: DeserializeOwned>(self) -> Result<T> {
+        if self.status != "ok" {
+            return Err(anyhow!(self.error.unwrap_or(self.status)));
+        }
+
+        serde_json::from_value(self.data).context("Unexpected response data")
+    }
+}
+
+impl Bridge {
+    pub fn new(options: MqttOptions) -> Self {
+        let prefix = options.client_id();
+        let (client, mut eventloop) = AsyncClient::new(options, 10);
+        let pending = Pending::default();
+        let (subscribed_tx, subscribed) = watch::channel(false);
+
+        let eventloop_task = tokio::spawn({
+            let client = client.clone();
+            let pending = pending.clone();
+
+            async move {
+                loop {
+                    let event = match eventloop.poll().await {
+                        Ok(event) => event,
+                        Err(e) => {
+                            tracing::error!(?e, "Bridge connection failed");
+                            // nothing will answer requests sent before the reconnect
+                            subscribed_tx.send_replace(false);
+                            pending.lock().unwrap().clear();
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
+                    };
+
+                    match event {
+                        // subscriptions are lost with the session
+                        Event::Incoming(Packet::ConnAck(_)) => {
+                            let topic = format!("{RESPONSE_TOPIC}/#");
+                            if let Err(e) = client.try_subscribe(topic, QoS::AtLeastOnce) {
+                                tracing::error!(?e, "Failed to subscribe to bridge responses");
+                            }
+                        }
+                        Event::Incoming(Packet::SubAck(_)) => {
+                            subscribed_tx.send_replace(true);
+                        }
+                        Event::Incoming(Packet::Publish(publish)) => {
+                            match serde_json::from_slice::<Response>(&publish.payload) {
+                                Ok(response) => dispatch(&pending, response),
+                                Err(e) => {
+                                    tracing::warn!(topic = publish.topic, "Invalid response: {e}");
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        });
+
+        Self {
+            client,
+            eventloop_task,
+            pending,
+            subscribed,
+            prefix,
+            transaction: AtomicU64::new(0),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Sends a request to `zigbee2mqtt/bridge/request/<path>` and waits for
+    /// its response, `payload` must be an object
+    pub async fn request(&self, path: &str, payload: Value) -> Result<Response> {
+        self.request_with_timeout(path, payload, self.timeout).await
+    }
+
+    async fn request_with_timeout(
+        &self,
+        path: &str,
+        mut payload: Value,
+        timeout: Duration,
+    ) -> Result<Response> {
+        let transaction =
+            format!("{}-{}", self.prefix, self.transaction.fetch_add(1, Ordering::Relaxed));
+        payload
+            .as_object_mut()
+            .context("Request payload must be an object")?
+            .insert("transaction".into(), transaction.clone().into());
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(transaction.clone(), tx);
+
+        let result = async {
+            let mut subscribed = self.subscribed.clone();
+            tokio::time::timeout(timeout, subscribed.wait_for(|subscribed| *subscribed))
+                .await
+                .context("Timed out connecting to the bridge")?
+                .context("Bridge connection closed")?;
+
+            self.client
+                .publish(
+                    format!("{REQUEST_TOPIC}/{path}"),
+                    QoS::AtLeastOnce,
+                    false,
+                    payload.to_string(),
+                )
+                .await?;
+
+            match tokio::time::timeout(timeout, rx).await {
+                Ok(response) => response.context("Bridge connection lost"),
+                Err(_) => Err(anyhow!("Timed out waiting for a response to {path}")),
+            }
+        }
+        .await;
+
+        self.pending.lock().unwrap().remove(&transaction);
+        result
+    }
+
+    async fn call<T: DeserializeOwned>(&self, path: &str, payload: Value) -> Result<T> {
+        self.request(path, payload)
+            .await?
+            .into_result()
+            .with_context(|| format!("Request {path} failed"))
+    }
+
+    /// Allows devices to join for `time` seconds, through `device` only if
+    /// given, 0 closes the network again
+    pub async fn permit_join(&self, time: u32, device: Option<&str>) -> Result<()> {
+        let mut payload = json!({"value": time > 0, "time": time});
+        if let Some(device) = device {
+            payload["device"] = device.into();
+        }
+
+        self.call::<Value>("permit_join", payload).await?;
+        Ok(())
+    }
+
+    pub async fn rename_device(&self, from: &str, to: &str) -> Result<()> {
+        self.call::<Value>("device/rename", json!({"from": from, "to": to}))
+            .await?;
+        Ok(())
+    }
+
+    /// Removes a device, `force` removes it from the database even when it
+    /// doesn't respond and `block` prevents it from joining again
+    pub async fn remove_device(&self, id: &str, force: bool, block: bool) -> Result<()> {
+        self.call::<Value>("device/remove", json!({"id": id, "force": force, "block": block}))
+            .await?;
+        Ok(())
+    }
+
+    /// Sets up reporting and bindings again
+    pub async fn configure_device(&self, id: &str) -> Result<()> {
+        self.call::<Value>("device/configure", json!({"id": id}))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn device_options(&self, id: &str, options: Value) -> Result<()> {
+        self.call::<Value>("device/options", json!({"id": id, "options": options}))
+            .await?;
+        Ok(())
+    }
+
+    /// Changes the zigbee2mqtt configuration, returns whether it has to be
+    /// restarted for the changes to apply
+    pub async fn options(&self, options: Value) -> Result<bool> {
+        #[derive(Deserialize)]
+        struct Data {
+            #[serde(default)]
+            restart_required: bool,
+        }
+
+        let data: Data = self.call("options", json!({"options": options})).await?;
+        Ok(data.restart_required)
+    }
+
+    /// Creates a group, returns its id
+    pub async fn add_group(&self, friendly_name: &str, id: Option<u16>) -> Result<u16> {
+        #[derive(Deserialize)]
+        struct Data {
+            id: u16,
+        }
+
+        let mut payload = json!({"friendly_name": friendly_name});
+        if let Some(id) = id {
+            payload["id"] = id.into();
+        }
+
+        let data: Data = self.call("group/add", payload).await?;
+        Ok(data.id)
+    }
+
+    pub async fn remove_group(&self, id: &str, force: bool) -> Result<()> {
+        self.call::<Value>("group/remove", json!({"id": id, "force": force}))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn add_group_member(
+        &self,
+        group: &str,
+        device: &str,
+        endpoint: Option<&str>,
+    ) -> Result<()> {
+        self.call::<Value>("group/members/add", member(group, device, endpoint))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_group_member(
+        &self,
+        group: &str,
+        device: &str,
+        endpoint: Option<&str>,
+    ) -> Result<()> {
+        self.call::<Value>("group/members/remove", member(group, device, endpoint))
+            .await?;
+        Ok(())
+    }
+
+    /// Asks the device whether a firmware update is available
+    pub async fn ota_check(&self, id: &str) -> Result<bool> {
+        #[derive(Deserialize)]
+        struct Data {
+            update_available: bool,
+        }
+
+        let data: Data = self
+            .call("device/ota_update/check", json!({"id": id}))
+            .await?;
+        Ok(data.update_available)
+    }
+
+    /// Updates the firmware of a device, waits for the update to finish
+    pub async fn ota_update(&self, id: &str) -> Result<OtaUpdate> {
+        let path = "device/ota_update/update";
+        self.request_with_timeout(path, json!({"id": id}), OTA_UPDATE_TIMEOUT)
+            .await?
+            .into_result()
+            .with_context(|| format!("Request {path} failed"))
+    }
+
+    /// The zigbee2mqtt data directory as a zip archive
+    pub async fn backup(&self) -> Result<Vec<u8>> {
+        #[derive(Deserialize)]
+        struct Data {
+            zip: String,
+        }
+
+        let data: Data = self.call("backup", json!({})).await?;
+        base64::engine::general_purpose::STANDARD
+            .decode(data.zip)
+            .context("Invalid backup archive")
+    }
+
+    pub async fn health_check(&self) -> Result<bool> {
+        #[derive(Deserialize)]
+        struct Data {
+            healthy: bool,
+        }
+
+        let data: Data = self.call("health_check", json!({})).await?;
+        Ok(data.healthy)
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.eventloop_task.abort();
+    }
+}
+
+/// Hands a response to the request with the same transaction
+fn dispatch(pending: &Pending, response: Response) {
+    // responses to other clients' requests
+    let Some(transaction) = &response.transaction else {
+        return;
+    };
+    if let Some(tx) = pending.lock().unwrap().remove(transaction) {
+        let _ = tx.send(response);
+    }
+}
+
+fn member(group: &str, device: &str, endpoint: Option<&str>) -> Value {
+    let mut payload = json!({"group": group, "device": device});
+    if let Some(endpoint) = endpoint {
+        payload["endpoint"] = endpoint.into();
+    }
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses() {
+        let response: Response = serde_json::from_value(json!({
+            "data": {"id": 3, "friendly_name": "kitchen"},
+            "status": "ok",
+            "transaction": "michiru-1"
+        }))
+        .unwrap();
+        assert_eq!(response.transaction.as_deref(), Some("michiru-1"));
+        assert_eq!(response.into_result::<Value>().unwrap()["id"], 3);
+
+        let response: Response = serde_json::from_value(json!({
+            "data": {},
+            "status": "error",
+            "error": "Group 'kitchen' does not exist"
+        }))
+        .unwrap();
+        let err = response.into_result::<Value>().unwrap_err();
+        assert_eq!(err.to_string(), "Group 'kitchen' does not exist");
+    }
+
+    #[test]
+    fn dispatch_by_transaction() {
+        let pending = Pending::default();
+        let (tx1, mut rx1) = oneshot::channel();
+        let (tx2, mut rx2) = oneshot::channel();
+        pending.lock().unwrap().insert("michiru-1".into(), tx1);
+        pending.lock().unwrap().insert("michiru-2".into(), tx2);
+
+        let response = |transaction: Option<&str>| Response {
+            data: json!({}),
+            status: "ok".into(),
+            error: None,
+            transaction: transaction.map(Into::into),
+        };
+
+        dispatch(&pending, response(Some("other-1")));
+        dispatch(&pending, response(None));
+        dispatch(&pending, response(Some("michiru-2")));
+
+        assert_eq!(rx2.try_recv().unwrap().transaction.as_deref(), Some("michiru-2"));
+        assert!(rx1.try_recv().is_err());
+        assert_eq!(pending.lock().unwrap().len(), 1);
+        assert!(pending.lock().unwrap().contains_key("michiru-1"));
+    }
+}
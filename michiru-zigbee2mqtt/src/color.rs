@@ -0,0 +1,84 @@
+//! Conversions between the CIE xy colors Zigbee lights use and sRGB
+//!
+//! Lights report chromaticity only, brightness is a separate attribute, so
+//! colors are converted at full brightness.
+
+/// Converts a CIE 1931 xy chromaticity to sRGB at full brightness
+pub fn xy_to_rgb(x: f64, y: f64) -> (u8, u8, u8) {
+    if y <= 0.0 {
+        return (0, 0, 0);
+    }
+
+    let (big_x, big_y, big_z) = (x / y, 1.0, (1.0 - x - y) / y);
+
+    let r = big_x * 3.2406 - big_y * 1.5372 - big_z * 0.4986;
+    let g = -big_x * 0.9689 + big_y * 1.8758 + big_z * 0.0415;
+    let b = big_x * 0.0557 - big_y * 0.2040 + big_z * 1.0570;
+
+    // outside the sRGB gamut, desaturate rather than clip a single channel
+    let (r, g, b) = (r.max(0.0), g.max(0.0), b.max(0.0));
+    let max = r.max(g).max(b);
+    if max <= 0.0 {
+        return (0, 0, 0);
+    }
+
+    let channel = |v: f64| (gamma(v / max) * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+/// Converts sRGB to a CIE 1931 xy chromaticity, black has none
+pub fn rgb_to_xy(r: u8, g: u8, b: u8) -> Option<(f64, f64)> {
+    let linear = |v: u8| inverse_gamma(v as f64 / 255.0);
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+
+    let big_x = r * 0.4124 + g * 0.3576 + b * 0.1805;
+    let big_y = r * 0.2126 + g * 0.7152 + b * 0.0722;
+    let big_z = r * 0.0193 + g * 0.1192 + b * 0.9505;
+
+    let sum = big_x + big_y + big_z;
+    if sum <= 0.0 {
+        return None;
+    }
+
+    // zigbee2mqtt reports four decimals
+    let round = |v: f64| (v * 10_000.0).round() / 10_000.0;
+    Some((round(big_x / sum), round(big_y / sum)))
+}
+
+fn gamma(v: f64) -> f64 {
+    match v {
+        v if v <= 0.003_130_8 => 12.92 * v,
+        v => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+fn inverse_gamma(v: f64) -> f64 {
+    match v {
+        v if v <= 0.040_45 => v / 12.92,
+        v => ((v + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        // D65 white point
+        assert_eq!(rgb_to_xy(255, 255, 255), Some((0.3127, 0.329)));
+        assert_eq!(xy_to_rgb(0.3127, 0.329), (255, 255, 255));
+        assert_eq!(rgb_to_xy(0, 0, 0), None);
+
+        for rgb in [(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 128, 0)] {
+            let (x, y) = rgb_to_xy(rgb.0, rgb.1, rgb.2).unwrap();
+            let (r, g, b) = xy_to_rgb(x, y);
+            let close = |a: u8, b: u8| a.abs_diff(b) <= 2;
+            assert!(
+                close(r, rgb.0) && close(g, rgb.1) && close(b, rgb.2),
+                "{rgb:?} -> {:?}",
+                (r, g, b)
+            );
+        }
+    }
+}
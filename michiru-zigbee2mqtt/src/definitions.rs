@@ -3,7 +3,7 @@ use std::fmt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct DeviceInfo {
     pub friendly_name: String,
     pub ieee_address: String,
@@ -17,7 +17,7 @@ pub struct DeviceInfo {
     pub definition: DeviceDefinition,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum ZigbeeDeviceType {
     Coordinator,
     Router,
@@ -34,7 +34,7 @@ impl fmt::Display for ZigbeeDeviceType {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct DeviceDefinition {
     pub description: String,
     pub exposes: Vec<Expose>,
@@ -59,11 +59,11 @@ impl DeviceDefinition {
             }
         }
 
-        todo!()
+        None
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[serde(untagged)]
 pub enum Expose {
@@ -95,7 +95,7 @@ impl Expose {
     // }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[serde(tag = "type")]
 pub enum Feature {
@@ -131,7 +131,13 @@ pub enum Feature {
         meta: FeatureMeta,
         features: Vec<Feature>,
     },
-    List,
+    List {
+        #[serde(flatten)]
+        meta: FeatureMeta,
+        /// Feature describing the items
+        #[serde(default)]
+        item_type: Option<Value>,
+    },
 }
 
 impl Feature {
@@ -142,7 +148,7 @@ impl Feature {
             Feature::Text { .. } => FeatureType::Text,
             Feature::Enum { .. } => FeatureType::Enum,
             Feature::Composite { .. } => FeatureType::Composite,
-            Feature::List => FeatureType::List,
+            Feature::List { .. } => FeatureType::List,
         }
     }
 
@@ -153,29 +159,37 @@ impl Feature {
             Feature::Text { meta, .. } => meta,
             Feature::Enum { meta, .. } => meta,
             Feature::Composite { meta, .. } => meta,
-            Feature::List => unimplemented!(),
+            Feature::List { meta, .. } => meta,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct FeatureMeta {
     pub access: FeatureAccess,
     pub name: String,
+    /// Human readable name, only sent by newer versions
+    #[serde(default)]
+    pub label: Option<String>,
     pub property: String,
     pub description: Option<String>,
+    /// Set when the device has the same feature on several endpoints
+    #[serde(default)]
+    pub endpoint: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[serde(tag = "type")]
 pub struct SpecificFeature {
     pub features: Vec<Feature>,
     #[serde(rename = "type")]
     pub ty: FeatureType,
+    #[serde(default)]
+    pub endpoint: Option<String>,
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FeatureType {
     Binary,
@@ -198,14 +212,14 @@ impl fmt::Display for FeatureType {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Preset {
     name: String,
     value: f64,
     description: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FeatureAccess {
     pub published: bool,
     pub settable: bool,
@@ -250,6 +264,148 @@ impl Serialize for FeatureAccess {
     }
 }
 
+/// `zigbee2mqtt/bridge/info`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BridgeInfo {
+    pub version: String,
+    #[serde(default)]
+    pub commit: Option<String>,
+    pub coordinator: CoordinatorInfo,
+    pub network: NetworkInfo,
+    #[serde(default)]
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub permit_join: bool,
+    #[serde(default)]
+    pub restart_required: bool,
+    /// The zigbee2mqtt configuration, its schema changes between versions
+    #[serde(default)]
+    pub config: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CoordinatorInfo {
+    pub ieee_address: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// Firmware details, depends on the adapter
+    #[serde(default)]
+    pub meta: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct NetworkInfo {
+    pub channel: u8,
+    pub pan_id: u16,
+    /// A byte array or hex string depending on the version
+    pub extended_pan_id: Value,
+}
+
+/// An entry of `zigbee2mqtt/bridge/groups`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Group {
+    pub id: u16,
+    pub friendly_name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub members: Vec<GroupMember>,
+    #[serde(default)]
+    pub scenes: Vec<Scene>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GroupMember {
+    pub ieee_address: String,
+    pub endpoint: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Scene {
+    pub id: u8,
+    pub name: String,
+}
+
+/// `zigbee2mqtt/bridge/event`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum BridgeEvent {
+    DeviceJoined(DeviceEvent),
+    DeviceInterview(InterviewEvent),
+    DeviceLeave(DeviceEvent),
+    DeviceAnnounce(DeviceEvent),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DeviceEvent {
+    pub friendly_name: String,
+    pub ieee_address: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InterviewEvent {
+    pub friendly_name: String,
+    pub ieee_address: String,
+    pub status: InterviewStatus,
+    /// Whether zigbee2mqtt knows the device, once the interview succeeded
+    #[serde(default)]
+    pub supported: Option<bool>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InterviewStatus {
+    Started,
+    Successful,
+    Failed,
+}
+
+impl BridgeEvent {
+    pub fn ieee_address(&self) -> &str {
+        match self {
+            BridgeEvent::DeviceJoined(device)
+            | BridgeEvent::DeviceLeave(device)
+            | BridgeEvent::DeviceAnnounce(device) => &device.ieee_address,
+            BridgeEvent::DeviceInterview(interview) => &interview.ieee_address,
+        }
+    }
+
+    pub fn friendly_name(&self) -> &str {
+        match self {
+            BridgeEvent::DeviceJoined(device)
+            | BridgeEvent::DeviceLeave(device)
+            | BridgeEvent::DeviceAnnounce(device) => &device.friendly_name,
+            BridgeEvent::DeviceInterview(interview) => &interview.friendly_name,
+        }
+    }
+}
+
+/// `zigbee2mqtt/bridge/logging`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LogMessage {
+    pub level: LogLevel,
+    pub message: String,
+    /// Only sent by newer versions
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    #[serde(alias = "warn")]
+    Warning,
+    Info,
+    Debug,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
@@ -276,7 +432,6 @@ mod tests {
         ));
 
         assert!(res.is_ok(), "{:#?}", res);
-        let res = res.unwrap();
 
         let res = serde_json::from_value::<Expose>(json!(
             {
@@ -293,6 +448,64 @@ mod tests {
         ));
 
         assert!(res.is_ok(), "{:#?}", res);
-        let res = res.unwrap();
+    }
+
+    #[test]
+    fn deserialize_bridge() {
+        let info = serde_json::from_value::<BridgeInfo>(json!({
+            "version": "1.35.1",
+            "commit": "9f5d9d6",
+            "coordinator": {"ieee_address": "0x00124b0018e2b7a1", "type": "zStack12",
+                            "meta": {"transportrev": 2, "product": 0, "revision": 20190608}},
+            "network": {"channel": 11, "pan_id": 6754, "extended_pan_id": [221, 221, 221]},
+            "log_level": "info",
+            "permit_join": false,
+            "restart_required": false,
+            "config": {"homeassistant": false},
+            "config_schema": {}
+        }))
+        .unwrap();
+        assert_eq!(info.coordinator.ty, "zStack12");
+        assert_eq!(info.network.pan_id, 6754);
+
+        let groups = serde_json::from_value::<Vec<Group>>(json!([{
+            "id": 1,
+            "friendly_name": "living_room",
+            "description": null,
+            "members": [{"ieee_address": "0x000b57fffec6a5b2", "endpoint": 1}],
+            "scenes": [{"id": 2, "name": "Evening"}]
+        }]))
+        .unwrap();
+        assert_eq!(groups[0].members[0].endpoint, 1);
+        assert_eq!(groups[0].scenes[0].name, "Evening");
+
+        let event = serde_json::from_value::<BridgeEvent>(json!({
+            "type": "device_interview",
+            "data": {"friendly_name": "0x90fd9ffffe6494fc", "ieee_address": "0x90fd9ffffe6494fc",
+                     "status": "successful", "supported": true, "definition": {}}
+        }))
+        .unwrap();
+        assert!(matches!(
+            &event,
+            BridgeEvent::DeviceInterview(InterviewEvent {
+                status: InterviewStatus::Successful,
+                ..
+            })
+        ));
+        assert_eq!(event.ieee_address(), "0x90fd9ffffe6494fc");
+
+        let event = serde_json::from_value::<BridgeEvent>(json!({
+            "type": "device_leave",
+            "data": {"friendly_name": "kitchen_plug", "ieee_address": "0x90fd9ffffe6494fc"}
+        }))
+        .unwrap();
+        assert_eq!(event.friendly_name(), "kitchen_plug");
+
+        let log = serde_json::from_value::<LogMessage>(json!({
+            "level": "warn",
+            "message": "Device '0x90fd9ffffe6494fc' left the network"
+        }))
+        .unwrap();
+        assert_eq!(log.level, LogLevel::Warning);
     }
 }
@@ -1,2 +0,0 @@
-pub mod innr;
-pub mod tradfri;
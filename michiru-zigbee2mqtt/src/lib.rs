@@ -1,23 +1,29 @@
+pub mod availability;
+pub mod bridge;
+pub mod color;
 pub mod definitions;
+pub mod mapping;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use rumqttc::{AsyncClient, ConnectionError, Event, EventLoop, MqttOptions, Packet, QoS};
+use serde::Deserialize;
 
-use crate::definitions::DeviceInfo;
+use crate::definitions::{BridgeEvent, BridgeInfo, DeviceInfo, Group, LogMessage};
 
 pub struct DefinitionStream {
+    client: AsyncClient,
     eventloop: EventLoop,
 }
 
 impl DefinitionStream {
+    pub const TOPIC: &'static str = "zigbee2mqtt/bridge/devices";
+
+    /// Subscribes once connected, and again after every reconnect as the
+    /// subscription is lost with the session
     pub async fn new(options: MqttOptions) -> Self {
         let (client, eventloop) = AsyncClient::new(options, 10);
-        client
-            .subscribe("zigbee2mqtt/bridge/devices", QoS::ExactlyOnce)
-            .await
-            .unwrap();
 
-        Self { eventloop }
+        Self { client, eventloop }
     }
 
     pub async fn next(&mut self) -> Result<Vec<DeviceInfo>, ConnectionError> {
@@ -26,6 +32,13 @@ impl DefinitionStream {
 
             tracing::trace!(?notification);
 
+            if let Event::Incoming(Packet::ConnAck(_)) = notification {
+                if let Err(e) = self.client.try_subscribe(Self::TOPIC, QoS::ExactlyOnce) {
+                    tracing::error!(?e, "Failed to subscribe to the device list");
+                }
+                continue;
+            }
+
             let Event::Incoming(Packet::Publish(obj)) = notification else {
                 continue;
             };
@@ -50,3 +63,82 @@ impl DefinitionStream {
         }
     }
 }
+
+/// A message published by zigbee2mqtt about itself
+#[derive(Debug, Clone)]
+pub enum BridgeMessage {
+    Info(BridgeInfo),
+    Groups(Vec<Group>),
+    Event(BridgeEvent),
+    Logging(LogMessage),
+}
+
+impl BridgeMessage {
+    pub const TOPICS: [&'static str; 4] = [
+        "zigbee2mqtt/bridge/info",
+        "zigbee2mqtt/bridge/groups",
+        "zigbee2mqtt/bridge/event",
+        "zigbee2mqtt/bridge/logging",
+    ];
+
+    /// Parses a message published on one of [`Self::TOPICS`], `None` for
+    /// other topics
+    pub fn parse(topic: &str, payload: &serde_json::Value) -> Option<Result<Self>> {
+        let message = match topic.strip_prefix("zigbee2mqtt/bridge/")? {
+            "info" => Deserialize::deserialize(payload).map(Self::Info),
+            "groups" => Deserialize::deserialize(payload).map(Self::Groups),
+            "event" => Deserialize::deserialize(payload).map(Self::Event),
+            "logging" => Deserialize::deserialize(payload).map(Self::Logging),
+            _ => return None,
+        };
+
+        Some(message.with_context(|| format!("Invalid {topic}")))
+    }
+}
+
+pub struct BridgeStream {
+    client: AsyncClient,
+    eventloop: EventLoop,
+    topics: Vec<String>,
+}
+
+impl BridgeStream {
+    /// Subscribes to `topics`, usually [`BridgeMessage::TOPICS`] or some of
+    /// them, once connected and again after every reconnect
+    pub async fn new(options: MqttOptions, topics: &[&str]) -> Self {
+        let (client, eventloop) = AsyncClient::new(options, 10);
+        let topics = topics.iter().map(|topic| topic.to_string()).collect();
+
+        Self { client, eventloop, topics }
+    }
+
+    pub async fn next(&mut self) -> Result<BridgeMessage, ConnectionError> {
+        loop {
+            let notification = self.eventloop.poll().await?;
+
+            // subscriptions are lost with the session
+            if let Event::Incoming(Packet::ConnAck(_)) = notification {
+                for topic in &self.topics {
+                    if let Err(e) = self.client.try_subscribe(topic, QoS::ExactlyOnce) {
+                        tracing::error!(?e, topic, "Failed to subscribe");
+                    }
+                }
+                continue;
+            }
+
+            let Event::Incoming(Packet::Publish(obj)) = notification else {
+                continue;
+            };
+
+            let Ok(payload) = serde_json::from_slice(&obj.payload) else {
+                continue;
+            };
+
+            match BridgeMessage::parse(&obj.topic, &payload) {
+                Some(Ok(message)) => return Ok(message),
+                Some(Err(e)) => tracing::warn!("{e:#}"),
+                None => {}
+            }
+        }
+    }
+}
@@ -1,19 +1,19 @@
-mod definitions;
-mod devices;
-
 use std::{collections::HashMap, time::Duration};
 
-use anyhow::Result;
-use michiru_device::{
-    DataType, DeviceBuilder, Format, MqttOptions, NodeAttributes, PropertyAttributes, Unit,
-};
+use anyhow::{Context, Result};
+use michiru_device::{DeviceBuilder, DeviceState, MqttOptions, Payload};
 use michiru_zigbee2mqtt::{
-    definitions::{DeviceDefinition, DeviceInfo, Expose, Feature, FeatureType},
-    DefinitionStream,
+    availability::{self, Availability, BRIDGE_STATE_TOPIC},
+    definitions::{BridgeEvent, DeviceInfo, InterviewEvent, InterviewStatus},
+    mapping::Mapping,
+    BridgeMessage, BridgeStream, DefinitionStream,
 };
 use rumqttc::{AsyncClient, Event, Packet, QoS};
-use serde::Deserialize;
-use tokio::task::JoinHandle;
+use serde_json::Value;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+const REMOVE_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -26,169 +26,259 @@ async fn main() -> Result<()> {
 
     let mut stream = DefinitionStream::new(mqttoptions).await;
 
-    let mut handles = Vec::<JoinHandle<()>>::new();
+    let options = MqttOptions::new("michiru-zigbee2mqtt-events", "michiru.fbk.red", 1883);
+    let mut events = BridgeStream::new(options, &["zigbee2mqtt/bridge/event"]).await;
+
+    // by IEEE address, friendly names can change
+    let mut bridged = HashMap::<String, Bridged>::new();
+    // last device list, events only carry the address
+    let mut known = HashMap::<String, DeviceInfo>::new();
 
     loop {
-        let devices = stream.next().await.unwrap();
+        tokio::select! {
+            devices = stream.next() => {
+                let devices = match devices {
+                    Ok(devices) => devices,
+                    Err(e) => {
+                        tracing::error!(?e, "Device list connection failed");
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                };
+                known = devices
+                    .iter()
+                    .map(|device| (device.ieee_address.clone(), device.clone()))
+                    .collect();
+                reconcile(&mut bridged, devices).await;
+            }
+            message = events.next() => match message {
+                // the device list follows, but only once zigbee2mqtt has cleaned up
+                Ok(BridgeMessage::Event(BridgeEvent::DeviceLeave(device))) => {
+                    if let Some(removed) = bridged.remove(&device.ieee_address) {
+                        tracing::info!(address = device.ieee_address, "Device left");
+                        removed.remove().await;
+                    }
+                }
+                // a rejoined or reinterviewed device starts over, new devices
+                // are added with the next device list
+                Ok(BridgeMessage::Event(
+                    event @ (BridgeEvent::DeviceJoined(_)
+                    | BridgeEvent::DeviceInterview(InterviewEvent {
+                        status: InterviewStatus::Successful,
+                        ..
+                    })),
+                )) => {
+                    let address = event.ieee_address();
+                    match known.get(address) {
+                        Some(device) => {
+                            tracing::info!(address, ?event, "Refreshing device");
+                            if let Some(removed) = bridged.remove(address) {
+                                removed.remove().await;
+                            }
+                            bridged.insert(address.to_string(), Bridged::spawn(device.clone()));
+                        }
+                        None => tracing::info!(address, ?event, "Waiting for the device list"),
+                    }
+                }
+                Ok(BridgeMessage::Event(event)) => tracing::info!(?event, "Bridge event"),
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!(?e, "Bridge event connection failed");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            },
+        }
+    }
+}
+
+async fn reconcile(bridged: &mut HashMap<String, Bridged>, devices: Vec<DeviceInfo>) {
+    let mut devices: HashMap<_, _> = devices
+        .into_iter()
+        .map(|device| (device.ieee_address.clone(), device))
+        .collect();
+
+    let addresses: Vec<_> = bridged.keys().cloned().collect();
+    for address in addresses {
+        match devices.remove(&address) {
+            Some(device) if device == bridged[&address].device => {}
+            Some(device) => {
+                tracing::info!(address, "Device changed, rebuilding");
+                bridged.remove(&address).unwrap().remove().await;
+                bridged.insert(address, Bridged::spawn(device));
+            }
+            None => {
+                tracing::info!(address, "Device removed");
+                bridged.remove(&address).unwrap().remove().await;
+            }
+        }
+    }
+
+    for (address, device) in devices {
+        tracing::info!(address, "Device added");
+        bridged.insert(address, Bridged::spawn(device));
+    }
+}
 
-        handles.drain(..).for_each(|h| h.abort());
+/// A zigbee2mqtt device published as a Homie device
+struct Bridged {
+    device: DeviceInfo,
+    remove: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Bridged {
+    fn spawn(device: DeviceInfo) -> Self {
+        let (remove, removed) = oneshot::channel();
+
+        let handle = tokio::spawn({
+            let device = device.clone();
+            async move {
+                let address = device.ieee_address.clone();
+                if let Err(e) = handle_device(device, removed).await {
+                    tracing::error!(address, "Bridging failed: {e:#}");
+                }
+            }
+        });
+
+        Self { device, remove, handle }
+    }
 
-        for device in devices {
-            handles.push(tokio::spawn(async move {
-                handle_device(device).await.unwrap();
-            }));
+    /// Removes the Homie device and waits for it to be gone, gives up on a
+    /// broker that doesn't answer
+    async fn remove(mut self) {
+        let _ = self.remove.send(());
+        if tokio::time::timeout(REMOVE_TIMEOUT, &mut self.handle)
+            .await
+            .is_err()
+        {
+            tracing::warn!(address = self.device.ieee_address, "Timed out removing device");
+            self.handle.abort();
         }
     }
 }
 
-async fn handle_device(device: DeviceInfo) -> Result<()> {
+async fn handle_device(device: DeviceInfo, mut removed: oneshot::Receiver<()>) -> Result<()> {
     let id = format!("zigbee2mqtt-{}", device.ieee_address);
-    let name = device.model_id;
-    let options = MqttOptions::new(format!("michiru-{}", id), "michiru.fbk.red", 1883);
-    let mut homie = DeviceBuilder::new(options, id.clone(), name.clone())
-        .await?
-        .node(NodeAttributes {
-            id: "link".to_string(),
-            name: "Link".to_string(),
-            type_: "Zigbee".to_string(),
-            properties: vec![PropertyAttributes {
-                id: "quality".to_string(),
-                name: "Quality".to_string(),
-                datatype: DataType::Integer,
-                settable: false,
-                retained: true,
-                unit: Some(Unit::Other("lqi".to_string())),
-                format: Some(Format::IntRange(0, 255)),
-            }],
-        })
-        .await?;
-
-    let options = MqttOptions::new(format!("michiru-{}-listener", id), "michiru.fbk.red", 1883);
-    let (listener_client, mut listener) = AsyncClient::new(options, 10);
-    listener_client
-        .subscribe(format!("zigbee2mqtt/{}", device.friendly_name), QoS::ExactlyOnce)
-        .await
-        .unwrap();
-
-    match name.as_str() {
-        "TRADFRI SHORTCUT Button" => {
-            let device = homie
-                .node(NodeAttributes {
-                    id: "battery".to_string(),
-                    name: "Battery".to_string(),
-                    type_: "CR2032".to_string(),
-                    properties: vec![PropertyAttributes {
-                        id: "level".to_string(),
-                        name: "Level".to_string(),
-                        datatype: DataType::Integer,
-                        settable: false,
-                        retained: true,
-                        unit: Some(Unit::Percent),
-                        format: Some(Format::IntRange(0, 100)),
-                    }],
-                })
-                .await?
-                .node(NodeAttributes {
-                    id: "input".to_string(),
-                    name: "Input".to_string(),
-                    type_: "Push button".to_string(),
-                    properties: vec![PropertyAttributes {
-                        id: "action".to_string(),
-                        name: "Action".to_string(),
-                        datatype: DataType::Enum,
-                        settable: false,
-                        retained: true,
-                        unit: Some(Unit::Percent),
-                        format: Some(Format::Enum(match device.definition.property("action") {
-                            Some(Feature::Enum { values, .. }) => values.clone(),
-                            _ => unreachable!("Invalid enum definition"),
-                        })),
-                    }],
-                })
-                .await?
-                .build()
-                .await
-                .unwrap();
-
-            #[derive(Debug, Deserialize)]
-            struct Data {
-                battery: u8,
-                linkquality: u8,
-                action: String,
+    let name = device.model_id.clone();
+    let mapping = Mapping::new(&device.definition);
+
+    let topic = format!("zigbee2mqtt/{}", device.friendly_name);
+    let availability_topic = format!("{topic}/availability");
+
+    let setup = async {
+        let options = MqttOptions::new(format!("michiru-{}", id), "michiru.fbk.red", 1883);
+        let mut homie = DeviceBuilder::new(options, id.clone(), name.clone()).await?;
+        for node in mapping.node_attributes() {
+            homie = homie.node(node).await?;
+        }
+        let mut homie = homie.build().await?;
+        let commands = homie.take_commands().context("Commands already taken")?;
+
+        let options = MqttOptions::new(format!("michiru-{}-listener", id), "michiru.fbk.red", 1883);
+        let (listener_client, listener) = AsyncClient::new(options, 10);
+
+        anyhow::Ok((homie, commands, listener_client, listener))
+    };
+
+    // setup waits for the broker, the device may be removed in the meantime
+    let (homie, mut commands, listener_client, mut listener) = tokio::select! {
+        _ = &mut removed => return Ok(()),
+        setup = setup => setup?,
+    };
+
+    // last state reported by zigbee2mqtt, completes partial composite sets
+    let mut state = Value::Null;
+    let mut bridge_availability = None;
+    let mut availability = None;
+    let mut homie_state = DeviceState::Ready;
+
+    loop {
+        tokio::select! {
+            _ = &mut removed => {
+                listener_client.disconnect().await?;
+                return homie.remove().await;
             }
+            event = listener.poll() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        tracing::error!(?e, address = device.ieee_address, "Listener connection failed");
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                };
+
+                // subscriptions are lost with the session
+                if let Event::Incoming(Packet::ConnAck(_)) = event {
+                    for topic in [&topic, &availability_topic, BRIDGE_STATE_TOPIC] {
+                        if let Err(e) = listener_client.try_subscribe(topic, QoS::ExactlyOnce) {
+                            tracing::error!(?e, topic, "Failed to subscribe");
+                        }
+                    }
+                    continue;
+                }
 
-            while let Ok(event) = listener.poll().await {
                 let Event::Incoming(Packet::Publish(obj)) = event else {
                     continue;
                 };
 
-                tracing::info!("{:#?}", obj.payload);
+                if obj.topic == availability_topic || obj.topic == BRIDGE_STATE_TOPIC {
+                    let Some(value) = Availability::parse(&obj.payload) else {
+                        tracing::warn!(topic = obj.topic, "Invalid availability {:?}", obj.payload);
+                        continue;
+                    };
+
+                    match obj.topic == BRIDGE_STATE_TOPIC {
+                        true => bridge_availability = Some(value),
+                        false => availability = Some(value),
+                    }
+
+                    let new_state = availability::homie_state(bridge_availability, availability);
+                    if new_state != homie_state {
+                        homie.set_state(new_state).await?;
+                        homie_state = new_state;
+                    }
+                    continue;
+                }
+
+                state = match serde_json::from_slice(&obj.payload) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        tracing::warn!("Invalid state for {}: {e}", device.friendly_name);
+                        continue;
+                    }
+                };
+
+                for update in mapping.updates(&state) {
+                    let Some(node) = homie.node(&update.node) else {
+                        continue;
+                    };
+                    let Some(property) = node.property(&update.property).await else {
+                        continue;
+                    };
+
+                    // an empty payload clears the retained value of unknown properties
+                    let payload = update.payload.unwrap_or(Payload::String(String::new()));
+                    property.send(payload).await?;
+                }
+            }
+            Some(command) = commands.recv() => {
+                let payload = match mapping.set_payload(&command, &state) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        tracing::warn!(
+                            node = command.node,
+                            property = command.property,
+                            "Ignoring set: {e:#}"
+                        );
+                        continue;
+                    }
+                };
+
+                listener_client
+                    .publish(format!("{topic}/set"), QoS::AtLeastOnce, false, payload.to_string())
+                    .await?;
             }
         }
-        _ => tracing::warn!("Unsupported device: {}", name),
     }
-
-    // for expose in device.definition.exposes {
-    //     match (&expose, expose.property()) {
-    //         (Expose::Generic(Feature::Numeric { .. }), "linkquality") => {
-    //             homie = homie
-    //                 .node(NodeAttributes {
-    //                     id: "link".to_string(),
-    //                     name: "Link".to_string(),
-    //                     type_: "Zigbee".to_string(),
-    //                     properties: vec![PropertyAttributes {
-    //                         id: "quality".to_string(),
-    //                         name: "Quality".to_string(),
-    //                         datatype: DataType::Integer,
-    //                         settable: false,
-    //                         retained: true,
-    //                         unit: Some(Unit::Other("lqi".to_string())),
-    //                         format: Some(Format::IntRange(0, 255)),
-    //                     }],
-    //                 })
-    //                 .await?;
-    //         }
-    //         (Expose::Generic(Feature::Numeric { .. }), "battery") => {
-    //             homie = homie
-    //                 .node(NodeAttributes {
-    //                     id: "battery".to_string(),
-    //                     name: "Battery".to_string(),
-    //                     type_: "Battery".to_string(),
-    //                     properties: vec![PropertyAttributes {
-    //                         id: "level".to_string(),
-    //                         name: "Level".to_string(),
-    //                         datatype: DataType::Integer,
-    //                         settable: false,
-    //                         retained: true,
-    //                         unit: Some(Unit::Other("lqi".to_string())),
-    //                         format: Some(Format::IntRange(0, 100)),
-    //                     }],
-    //                 })
-    //                 .await?;
-    //         }
-    //         (Expose::Generic(Feature::Enum { values, .. }), "action") => {
-    //             homie = homie
-    //                 .node(NodeAttributes {
-    //                     id: "action".to_string(),
-    //                     name: "Action".to_string(),
-    //                     type_: "Action".to_string(),
-    //                     properties: vec![PropertyAttributes {
-    //                         id: "action".to_string(),
-    //                         name: "Action".to_string(),
-    //                         datatype: DataType::Enum,
-    //                         settable: false,
-    //                         retained: false,
-    //                         unit: None,
-    //                         format: Some(Format::Enum(values.clone())),
-    //                     }],
-    //                 })
-    //                 .await?;
-    //         }
-    //         other => {
-    //             tracing::error!("Unsupported expose: {:?}", other);
-    //         }
-    //     }
-    // }
-
-    Ok(())
 }
@@ -0,0 +1,871 @@
+//! Turning zigbee2mqtt exposes into Homie nodes and properties
+//!
+//! Top level features end up as properties of a `state` node, except for
+//! the link quality which keeps its own `link` node. Composite features and
+//! specific exposes (lights, switches, ...) each get a node of their own,
+//! with nested composites flattened into its properties.
+//!
+//! Specific exposes use the same property ids whatever the vendor: endpoint
+//! suffixes are dropped, well known properties renamed, light brightness is
+//! a percentage and light colors a single Homie color property.
+
+use anyhow::{bail, ensure, Context, Result};
+use michiru_device::{
+    Color, DataType, Format, NodeAttributes, Payload, PropertyAttributes, SetCommand, Unit,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    color,
+    definitions::{DeviceDefinition, Expose, Feature, FeatureMeta, FeatureType, SpecificFeature},
+};
+
+pub const STATE_ID: &str = "state";
+pub const LINK_ID: &str = "link";
+pub const QUALITY_ID: &str = "quality";
+
+const LINKQUALITY: &str = "linkquality";
+
+/// How the exposes of a device are published over Homie
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pub nodes: Vec<MappedNode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MappedNode {
+    pub id: String,
+    pub name: String,
+    pub type_: String,
+    pub properties: Vec<MappedProperty>,
+}
+
+/// A property value found in a state message
+#[derive(Debug, Clone)]
+pub struct Update {
+    pub node: String,
+    pub property: String,
+    /// `None` when zigbee2mqtt reports the value as unknown
+    pub payload: Option<Payload>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MappedProperty {
+    pub attributes: PropertyAttributes,
+    /// Where the value lives in the device's state JSON
+    pub path: Vec<String>,
+    pub feature: Feature,
+    pub conversion: Conversion,
+}
+
+/// How values are translated between zigbee2mqtt and Homie
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conversion {
+    /// The value as is, typed according to the feature
+    Direct,
+    /// Scaled from `min..=max` to 0-100 %
+    Percent { min: f64, max: f64 },
+    /// zigbee2mqtt's `{x, y}` as an RGB color
+    ColorXy,
+    /// zigbee2mqtt's `{hue, saturation}` as an HSV color
+    ColorHs,
+}
+
+impl Mapping {
+    pub fn new(definition: &DeviceDefinition) -> Self {
+        let mut state = MappedNode::new(STATE_ID, "State", "State");
+        let mut nodes = vec![];
+
+        for expose in &definition.exposes {
+            match expose {
+                Expose::Generic(feature) if feature.meta().property == LINKQUALITY => {
+                    let mut link = MappedNode::new(LINK_ID, "Link", "Zigbee");
+                    link.add(
+                        feature,
+                        vec![LINKQUALITY.into()],
+                        QUALITY_ID.into(),
+                        "Quality".into(),
+                        Conversion::Direct,
+                    );
+                    nodes.push(link);
+                }
+                Expose::Generic(Feature::Composite { meta, features }) => {
+                    let mut node = MappedNode::new(&id(&meta.property), &name(meta), "Composite");
+                    node.add_all(features, std::slice::from_ref(&meta.property), "");
+                    nodes.push(node);
+                }
+                Expose::Generic(feature) => state.add_all(std::slice::from_ref(feature), &[], ""),
+                Expose::Specific(specific) => nodes.push(specific_node(specific)),
+            }
+        }
+
+        if !state.properties.is_empty() {
+            nodes.insert(0, state);
+        }
+
+        // ids must be unique within the device
+        let mut seen = std::collections::HashSet::new();
+        nodes.retain(|node| {
+            let unique = seen.insert(node.id.clone());
+            if !unique {
+                tracing::warn!(node = node.id, "Duplicate node, skipping");
+            }
+            unique
+        });
+
+        Self { nodes }
+    }
+
+    pub fn node_attributes(&self) -> Vec<NodeAttributes> {
+        self.nodes.iter().map(MappedNode::attributes).collect()
+    }
+
+    /// Extracts the values of all mapped properties from a
+    /// `zigbee2mqtt/<friendly_name>` message, fields it lacks are left out
+    pub fn updates(&self, state: &Value) -> Vec<Update> {
+        let mut updates = vec![];
+
+        for node in &self.nodes {
+            for property in &node.properties {
+                let Some(value) = property.path.iter().try_fold(state, |v, key| v.get(key)) else {
+                    continue;
+                };
+
+                let payload = match value {
+                    Value::Null => None,
+                    value => match property.payload(value) {
+                        Some(payload) => Some(payload),
+                        None => {
+                            tracing::warn!(path = ?property.path, %value, "Unexpected value");
+                            continue;
+                        }
+                    },
+                };
+
+                updates.push(Update {
+                    node: node.id.clone(),
+                    property: property.attributes.id.clone(),
+                    payload,
+                });
+            }
+        }
+
+        updates
+    }
+
+    /// Builds the `zigbee2mqtt/<friendly_name>/set` message for a Homie
+    /// command, the other fields of a composite are taken from the last known
+    /// `state` as zigbee2mqtt wants them set together
+    pub fn set_payload(&self, command: &SetCommand, state: &Value) -> Result<Value> {
+        let node = self
+            .nodes
+            .iter()
+            .find(|n| n.id == command.node)
+            .with_context(|| format!("Unknown node {}", command.node))?;
+        let property = node
+            .properties
+            .iter()
+            .find(|p| p.attributes.id == command.property)
+            .with_context(|| format!("Unknown property {}/{}", command.node, command.property))?;
+
+        let mut payload = Value::Object(Map::new());
+        let (_, parent) = property.path.split_last().context("Empty property path")?;
+
+        if !parent.is_empty() {
+            for sibling in &node.properties {
+                let Some((_, sibling_parent)) = sibling.path.split_last() else {
+                    continue;
+                };
+                if sibling_parent != parent || sibling.path == property.path {
+                    continue;
+                }
+
+                match sibling.path.iter().try_fold(state, |v, key| v.get(key)) {
+                    Some(Value::Null) | None => {}
+                    Some(value) => insert(&mut payload, &sibling.path, value.clone()),
+                }
+            }
+        }
+
+        insert(&mut payload, &property.path, property.value(&command.payload)?);
+
+        Ok(payload)
+    }
+
+    pub fn property(&self, node: &str, property: &str) -> Option<&MappedProperty> {
+        self.nodes
+            .iter()
+            .find(|n| n.id == node)?
+            .properties
+            .iter()
+            .find(|p| p.attributes.id == property)
+    }
+}
+
+impl MappedNode {
+    fn new(id: &str, name: &str, type_: &str) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            type_: type_.into(),
+            properties: vec![],
+        }
+    }
+
+    pub fn attributes(&self) -> NodeAttributes {
+        NodeAttributes {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            type_: self.type_.clone(),
+            properties: self
+                .properties
+                .iter()
+                .map(|p| p.attributes.clone())
+                .collect(),
+        }
+    }
+
+    /// Adds features found under `path`, composites are flattened with their
+    /// property as prefix
+    fn add_all(&mut self, features: &[Feature], path: &[String], prefix: &str) {
+        for feature in features {
+            let meta = feature.meta();
+            let mut path = path.to_vec();
+            path.push(meta.property.clone());
+
+            let id = match prefix {
+                "" => id(&meta.property),
+                prefix => format!("{prefix}-{}", id(&meta.property)),
+            };
+
+            match feature {
+                Feature::Composite { features, .. } => self.add_all(features, &path, &id),
+                feature => self.add(feature, path, id, name(meta), Conversion::Direct),
+            }
+        }
+    }
+
+    fn add(
+        &mut self,
+        feature: &Feature,
+        path: Vec<String>,
+        id: String,
+        name: String,
+        conversion: Conversion,
+    ) {
+        if self.properties.iter().any(|p| p.attributes.id == id) {
+            tracing::warn!(node = self.id, property = id, "Duplicate property, skipping");
+            return;
+        }
+
+        let Some(attributes) = property_attributes(feature, id, name, conversion) else {
+            tracing::debug!(property = feature.meta().property, "Unsupported feature, skipping");
+            return;
+        };
+
+        self.properties.push(MappedProperty {
+            attributes,
+            path,
+            feature: feature.clone(),
+            conversion,
+        });
+    }
+}
+
+impl MappedProperty {
+    /// Converts a zigbee2mqtt value to the Homie representation
+    pub fn payload(&self, value: &Value) -> Option<Payload> {
+        match self.conversion {
+            Conversion::Direct => {}
+            Conversion::Percent { min, max } => {
+                let percent = (value.as_f64()? - min) / (max - min) * 100.0;
+                return Some(Payload::Integer(percent.round().clamp(0.0, 100.0) as i64));
+            }
+            Conversion::ColorXy => {
+                let (x, y) = (value.get("x")?.as_f64()?, value.get("y")?.as_f64()?);
+                let (r, g, b) = color::xy_to_rgb(x, y);
+                return Some(Payload::Color(Color::Rgb(r, g, b)));
+            }
+            Conversion::ColorHs => {
+                let hue = value.get("hue")?.as_f64()?.round().clamp(0.0, 360.0);
+                let saturation = value.get("saturation")?.as_f64()?.round().clamp(0.0, 100.0);
+                return Some(Payload::Color(Color::Hsv(hue as u16, saturation as u8, 100)));
+            }
+        }
+
+        match &self.feature {
+            Feature::Binary { value_on, value_off, .. } => match value {
+                v if v == value_on => Some(Payload::Boolean(true)),
+                v if v == value_off => Some(Payload::Boolean(false)),
+                Value::Bool(v) => Some(Payload::Boolean(*v)),
+                _ => None,
+            },
+            Feature::Numeric { .. } => match self.attributes.datatype {
+                DataType::Integer => match value.as_i64() {
+                    Some(v) => Some(Payload::Integer(v)),
+                    // out of the advertised range, but better than nothing
+                    None => value.as_f64().map(|v| Payload::Integer(v.round() as i64)),
+                },
+                _ => value.as_f64().map(Payload::Float),
+            },
+            Feature::Enum { .. } => value.as_str().map(|v| Payload::Enum(v.into())),
+            Feature::Text { .. } => Some(Payload::String(match value {
+                Value::String(v) => v.clone(),
+                other => other.to_string(),
+            })),
+            Feature::Composite { .. } | Feature::List { .. } => None,
+        }
+    }
+
+    /// Converts a Homie `/set` payload to the zigbee2mqtt value
+    pub fn value(&self, payload: &str) -> Result<Value> {
+        let meta = self.feature.meta();
+        if !meta.access.settable {
+            bail!("{} is not settable", meta.property);
+        }
+
+        match self.conversion {
+            Conversion::Direct => {}
+            Conversion::Percent { min, max } => {
+                let percent: f64 = payload
+                    .parse()
+                    .with_context(|| format!("Invalid percentage {payload:?}"))?;
+                if !(0.0..=100.0).contains(&percent) {
+                    bail!("{percent} is out of range for {}", meta.property);
+                }
+
+                return Ok(Value::from((min + percent / 100.0 * (max - min)).round() as i64));
+            }
+            Conversion::ColorXy => {
+                let [r, g, b] = color_components(payload)?;
+                let (r, g, b) = (u8::try_from(r)?, u8::try_from(g)?, u8::try_from(b)?);
+                let (x, y) = color::rgb_to_xy(r, g, b).context("Black has no color")?;
+                return Ok(json!({"x": x, "y": y}));
+            }
+            // the value is left out, brightness is a property of its own
+            Conversion::ColorHs => {
+                let [hue, saturation, _] = color_components(payload)?;
+                if hue > 360 || saturation > 100 {
+                    bail!("Invalid HSV color {payload:?}");
+                }
+                return Ok(json!({"hue": hue, "saturation": saturation}));
+            }
+        }
+
+        Ok(match &self.feature {
+            Feature::Binary {
+                value_on, value_off, value_toggle, ..
+            } => match (payload, value_toggle) {
+                ("true", _) => value_on.clone(),
+                ("false", _) => value_off.clone(),
+                ("toggle", Some(toggle)) => toggle.clone(),
+                _ => bail!("Invalid boolean {payload:?}"),
+            },
+            Feature::Numeric { value_min, value_max, .. } => {
+                let value: f64 = payload
+                    .parse()
+                    .with_context(|| format!("Invalid number {payload:?}"))?;
+                // zigbee2mqtt would get a null
+                ensure!(value.is_finite(), "Invalid number {payload:?}");
+
+                if value_min.is_some_and(|min| value < min)
+                    || value_max.is_some_and(|max| value > max)
+                {
+                    bail!("{value} is out of range for {}", meta.property);
+                }
+
+                match self.attributes.datatype {
+                    DataType::Integer if value.fract() != 0.0 => bail!("{value} is not an integer"),
+                    DataType::Integer => Value::from(value as i64),
+                    _ => Value::from(value),
+                }
+            }
+            Feature::Enum { values, .. } => match values.iter().find(|v| *v == payload) {
+                Some(value) => Value::String(value.clone()),
+                None => bail!("{payload:?} is not one of {}", values.join(", ")),
+            },
+            Feature::Text { .. } => Value::String(payload.into()),
+            Feature::Composite { .. } | Feature::List { .. } => {
+                bail!("{} can't be set directly", meta.property)
+            }
+        })
+    }
+}
+
+/// Parses a Homie `r,g,b` or `h,s,v` color
+fn color_components(payload: &str) -> Result<[u16; 3]> {
+    let components = payload
+        .split(',')
+        .map(|v| v.trim().parse::<u16>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid color {payload:?}"))?;
+
+    components
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid color {payload:?}"))
+}
+
+/// Sets `value` at `path` in `object`, creating intermediate objects
+fn insert(object: &mut Value, path: &[String], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+
+    let mut object = object;
+    for key in parents {
+        object = object
+            .as_object_mut()
+            .expect("set payloads only contain objects")
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+
+    if let Some(object) = object.as_object_mut() {
+        object.insert(last.clone(), value);
+    }
+}
+
+fn specific_node(specific: &SpecificFeature) -> MappedNode {
+    let type_ = specific.ty.to_string();
+    let (node_id, node_name) = match &specific.endpoint {
+        Some(endpoint) => (id(&format!("{type_}-{endpoint}")), format!("{type_} ({endpoint})")),
+        None => (id(&type_), type_.clone()),
+    };
+
+    // lights exposing both color spaces get a single HSV property
+    let has_hs = specific.features.iter().any(|f| f.meta().name == COLOR_HS);
+
+    let mut node = MappedNode::new(&node_id, &node_name, &type_);
+    for feature in &specific.features {
+        let meta = feature.meta();
+        let path = vec![meta.property.clone()];
+        let property = match &specific.endpoint {
+            Some(endpoint) => meta
+                .property
+                .strip_suffix(&format!("_{endpoint}"))
+                .unwrap_or(&meta.property),
+            None => &meta.property,
+        };
+        let id = semantic_id(specific.ty, property).map_or_else(|| id(property), Into::into);
+
+        let conversion = match (specific.ty, feature) {
+            (FeatureType::Light, Feature::Composite { .. }) => match meta.name.as_str() {
+                COLOR_XY if has_hs => continue,
+                COLOR_XY => Some(Conversion::ColorXy),
+                COLOR_HS => Some(Conversion::ColorHs),
+                _ => None,
+            },
+            (FeatureType::Light, Feature::Numeric { value_min, value_max, .. })
+                if property == "brightness" =>
+            {
+                Some(Conversion::Percent {
+                    min: value_min.unwrap_or(0.0),
+                    max: value_max.unwrap_or(254.0),
+                })
+            }
+            _ => None,
+        };
+
+        match (conversion, feature) {
+            (Some(conversion), feature) => node.add(feature, path, id, name(meta), conversion),
+            (None, Feature::Composite { features, .. }) => node.add_all(features, &path, &id),
+            (None, feature) => node.add(feature, path, id, name(meta), Conversion::Direct),
+        }
+    }
+
+    node
+}
+
+const COLOR_XY: &str = "color_xy";
+const COLOR_HS: &str = "color_hs";
+
+/// Vendor independent ids for properties of specific exposes
+fn semantic_id(ty: FeatureType, property: &str) -> Option<&'static str> {
+    Some(match (ty, property) {
+        (FeatureType::Fan, "fan_state") => "state",
+        (FeatureType::Fan, "fan_mode") => "mode",
+        (FeatureType::Climate, "occupied_heating_setpoint" | "current_heating_setpoint") => {
+            "heating-setpoint"
+        }
+        (FeatureType::Climate, "occupied_cooling_setpoint") => "cooling-setpoint",
+        (FeatureType::Climate, "local_temperature") => "temperature",
+        (FeatureType::Climate, "system_mode") => "mode",
+        _ => return None,
+    })
+}
+
+fn property_attributes(
+    feature: &Feature,
+    id: String,
+    name: String,
+    conversion: Conversion,
+) -> Option<PropertyAttributes> {
+    let meta = feature.meta();
+
+    let (datatype, unit, format) = match (conversion, feature) {
+        (Conversion::Percent { .. }, _) => {
+            (DataType::Integer, Some(Unit::Percent), Some(Format::IntRange(0, 100)))
+        }
+        (Conversion::ColorXy, _) => (DataType::Color, None, Some(Format::ColorRgb)),
+        (Conversion::ColorHs, _) => (DataType::Color, None, Some(Format::ColorHsv)),
+        (Conversion::Direct, Feature::Binary { .. }) => (DataType::Boolean, None, None),
+        (
+            Conversion::Direct,
+            Feature::Numeric {
+                value_min,
+                value_max,
+                value_step,
+                unit,
+                ..
+            },
+        ) => {
+            let whole = |v: &f64| v.fract() == 0.0;
+            let integer = value_min.as_ref().is_some_and(whole)
+                && value_max.as_ref().is_some_and(whole)
+                && value_step.iter().all(whole);
+
+            let format = match (value_min, value_max) {
+                (Some(min), Some(max)) if integer => {
+                    Some(Format::IntRange(*min as i64, *max as i64))
+                }
+                (Some(min), Some(max)) => Some(Format::FloatRange(*min, *max)),
+                _ => None,
+            };
+            let datatype = match integer {
+                true => DataType::Integer,
+                false => DataType::Float,
+            };
+
+            (datatype, unit.as_deref().map(homie_unit), format)
+        }
+        (Conversion::Direct, Feature::Enum { values, .. }) => {
+            (DataType::Enum, None, Some(Format::Enum(values.clone())))
+        }
+        (Conversion::Direct, Feature::Text { .. }) => (DataType::String, None, None),
+        (Conversion::Direct, Feature::Composite { .. } | Feature::List { .. }) => return None,
+    };
+
+    Some(PropertyAttributes {
+        id,
+        name,
+        datatype,
+        settable: meta.access.settable,
+        // actions are momentary events, not state
+        retained: !matches!(meta.property.as_str(), "action" | "click"),
+        unit,
+        format,
+    })
+}
+
+pub fn homie_unit(unit: &str) -> Unit {
+    match unit {
+        "°C" => Unit::DegreeCelsius,
+        "°F" => Unit::DegreeFahrenheit,
+        "°" => Unit::Degree,
+        "L" => Unit::Liter,
+        "V" => Unit::Volts,
+        "W" => Unit::Watt,
+        "A" => Unit::Ampere,
+        "%" => Unit::Percent,
+        "m" => Unit::Meter,
+        "Pa" => Unit::Pascal,
+        "psi" => Unit::Psi,
+        other => Unit::Other(other.into()),
+    }
+}
+
+/// Homie ids only allow lowercase letters, digits and hyphens
+pub fn id(property: &str) -> String {
+    let id = property
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect::<String>();
+
+    id.split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn name(meta: &FeatureMeta) -> String {
+    meta.label.clone().unwrap_or_else(|| meta.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn light() -> DeviceDefinition {
+        serde_json::from_value(json!({
+            "description": "TRADFRI bulb E27, white spectrum, globe, opal, 1055/1100/1160 lm",
+            "exposes": [
+                {
+                    "type": "light",
+                    "features": [
+                        {"access": 7, "name": "state", "property": "state", "type": "binary",
+                         "value_on": "ON", "value_off": "OFF", "value_toggle": "TOGGLE"},
+                        {"access": 7, "name": "brightness", "property": "brightness",
+                         "type": "numeric", "value_min": 0, "value_max": 254},
+                        {"access": 7, "name": "color_temp", "property": "color_temp",
+                         "type": "numeric", "unit": "mired", "value_min": 250, "value_max": 454},
+                        {"access": 7, "name": "color_xy", "property": "color", "type": "composite",
+                         "features": [
+                            {"access": 7, "name": "x", "property": "x", "type": "numeric"},
+                            {"access": 7, "name": "y", "property": "y", "type": "numeric"}
+                         ]}
+                    ]
+                },
+                {"access": 7, "name": "level_config", "property": "level_config", "type": "composite",
+                 "features": [
+                    {"access": 7, "name": "on_off_transition_time",
+                     "property": "on_off_transition_time", "type": "numeric"},
+                    {"access": 7, "name": "on_level", "property": "on_level", "type": "numeric",
+                     "value_min": 1, "value_max": 254}
+                 ]},
+                {"access": 2, "name": "effect", "property": "effect", "type": "enum",
+                 "values": ["blink", "breathe", "okay"]},
+                {"access": 1, "name": "battery", "label": "Battery", "property": "battery",
+                 "type": "numeric", "unit": "%", "value_min": 0, "value_max": 100},
+                {"access": 1, "name": "temperature", "property": "local_temperature",
+                 "type": "numeric", "unit": "°C", "value_min": -10, "value_max": 50.5},
+                {"access": 1, "name": "action", "property": "action", "type": "enum",
+                 "values": ["on", "off"]},
+                {"access": 1, "name": "linkquality", "property": "linkquality", "type": "numeric",
+                 "unit": "lqi", "value_min": 0, "value_max": 255}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn generic_mapping() {
+        let mapping = Mapping::new(&light());
+
+        let ids: Vec<_> = mapping.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["state", "light", "level-config", "link"]);
+
+        let light = &mapping.nodes[1];
+        let ids: Vec<_> = light
+            .properties
+            .iter()
+            .map(|p| p.attributes.id.as_str())
+            .collect();
+        assert_eq!(ids, ["state", "brightness", "color-temp", "color"]);
+        assert!(light.properties[0].attributes.settable);
+
+        let brightness = &light.properties[1];
+        assert_eq!(brightness.conversion, Conversion::Percent { min: 0.0, max: 254.0 });
+        assert!(matches!(brightness.attributes.unit, Some(Unit::Percent)));
+
+        let color_temp = &light.properties[2];
+        assert!(matches!(color_temp.attributes.format, Some(Format::IntRange(250, 454))));
+        assert!(matches!(&color_temp.attributes.unit, Some(Unit::Other(u)) if u == "mired"));
+
+        let color = &light.properties[3];
+        assert_eq!(color.path, ["color"]);
+        assert!(matches!(color.attributes.datatype, DataType::Color));
+        assert!(matches!(color.attributes.format, Some(Format::ColorRgb)));
+
+        let level = &mapping.nodes[2];
+        assert_eq!(level.properties[1].attributes.id, "on-level");
+        assert_eq!(level.properties[1].path, ["level_config", "on_level"]);
+
+        let effect = mapping.property("state", "effect").unwrap();
+        assert!(matches!(effect.attributes.datatype, DataType::Enum));
+        assert!(matches!(&effect.attributes.format, Some(Format::Enum(v)) if v.len() == 3));
+        assert!(effect.attributes.settable);
+
+        let battery = mapping.property("state", "battery").unwrap();
+        assert_eq!(battery.attributes.name, "Battery");
+        assert!(matches!(battery.attributes.datatype, DataType::Integer));
+        assert!(matches!(battery.attributes.format, Some(Format::IntRange(0, 100))));
+        assert!(matches!(battery.attributes.unit, Some(Unit::Percent)));
+        assert!(!battery.attributes.settable);
+
+        let temperature = mapping.property("state", "local-temperature").unwrap();
+        assert!(matches!(temperature.attributes.datatype, DataType::Float));
+        assert!(matches!(temperature.attributes.unit, Some(Unit::DegreeCelsius)));
+        assert_eq!(temperature.path, ["local_temperature"]);
+
+        assert!(
+            !mapping
+                .property("state", "action")
+                .unwrap()
+                .attributes
+                .retained
+        );
+        assert_eq!(mapping.property("link", "quality").unwrap().path, ["linkquality"]);
+    }
+
+    #[test]
+    fn state_updates() {
+        let mapping = Mapping::new(&light());
+
+        let updates = mapping.updates(&json!({
+            "state": "ON",
+            "brightness": 200,
+            "color_temp": null,
+            "color": {"x": 0.4573, "y": 0.41},
+            "battery": 87.0,
+            "linkquality": 96,
+            "action": 3,
+            "unrelated": "field"
+        }));
+
+        let updates: Vec<_> = updates
+            .iter()
+            .map(|u| (u.node.as_str(), u.property.as_str(), format!("{:?}", u.payload)))
+            .collect();
+
+        assert_eq!(updates, [
+            ("state", "battery", "Some(Integer(87))".into()),
+            ("light", "state", "Some(Boolean(true))".into()),
+            ("light", "brightness", "Some(Integer(79))".into()),
+            ("light", "color-temp", "None".into()),
+            ("light", "color", "Some(Color(Rgb(255, 174, 91)))".into()),
+            ("link", "quality", "Some(Integer(96))".into()),
+        ]);
+    }
+
+    fn set(mapping: &Mapping, node: &str, property: &str, payload: &str) -> Result<Value> {
+        let command = SetCommand {
+            node: node.into(),
+            property: property.into(),
+            payload: payload.into(),
+        };
+        let state = json!({"level_config": {"on_off_transition_time": 5, "on_level": null}});
+        mapping.set_payload(&command, &state)
+    }
+
+    #[test]
+    fn set_payloads() {
+        let mapping = Mapping::new(&light());
+
+        assert_eq!(set(&mapping, "light", "state", "true").unwrap(), json!({"state": "ON"}));
+        assert_eq!(set(&mapping, "light", "state", "false").unwrap(), json!({"state": "OFF"}));
+        assert_eq!(set(&mapping, "light", "state", "toggle").unwrap(), json!({"state": "TOGGLE"}));
+        assert!(set(&mapping, "light", "state", "ON").is_err());
+
+        assert_eq!(
+            set(&mapping, "light", "color-temp", "454").unwrap(),
+            json!({"color_temp": 454})
+        );
+        assert!(set(&mapping, "light", "color-temp", "455").is_err());
+        assert!(set(&mapping, "light", "color-temp", "300.5").is_err());
+        assert!(set(&mapping, "light", "color-temp", "warm").is_err());
+        assert!(set(&mapping, "light", "color-temp", "NaN").is_err());
+
+        assert_eq!(set(&mapping, "light", "brightness", "50").unwrap(), json!({"brightness": 127}));
+        assert!(set(&mapping, "light", "brightness", "101").is_err());
+
+        assert_eq!(
+            set(&mapping, "light", "color", "255,0,0").unwrap(),
+            json!({"color": {"x": 0.6401, "y": 0.33}})
+        );
+        assert!(set(&mapping, "light", "color", "0,0,0").is_err());
+        assert!(set(&mapping, "light", "color", "255,0").is_err());
+
+        assert_eq!(set(&mapping, "state", "effect", "okay").unwrap(), json!({"effect": "okay"}));
+        assert!(set(&mapping, "state", "effect", "dance").is_err());
+
+        // unknown siblings are left out, known ones sent along
+        assert_eq!(
+            set(&mapping, "level-config", "on-off-transition-time", "2").unwrap(),
+            json!({"level_config": {"on_off_transition_time": 2.0}})
+        );
+        for payload in ["NaN", "inf", "-inf"] {
+            assert!(set(&mapping, "level-config", "on-off-transition-time", payload).is_err());
+        }
+        assert_eq!(
+            set(&mapping, "level-config", "on-level", "100").unwrap(),
+            json!({"level_config": {"on_off_transition_time": 5, "on_level": 100}})
+        );
+
+        assert!(set(&mapping, "state", "battery", "50").is_err());
+        assert!(set(&mapping, "light", "nope", "1").is_err());
+    }
+
+    #[test]
+    fn semantic_nodes() {
+        let definition: DeviceDefinition = serde_json::from_value(json!({
+            "description": "Thermostat with two relays and a color bulb",
+            "exposes": [
+                {"type": "switch", "endpoint": "l1", "features": [
+                    {"access": 7, "name": "state", "property": "state_l1", "type": "binary",
+                     "endpoint": "l1", "value_on": "ON", "value_off": "OFF"}
+                ]},
+                {"type": "switch", "endpoint": "l2", "features": [
+                    {"access": 7, "name": "state", "property": "state_l2", "type": "binary",
+                     "endpoint": "l2", "value_on": "ON", "value_off": "OFF"}
+                ]},
+                {"type": "climate", "features": [
+                    {"access": 7, "name": "current_heating_setpoint",
+                     "property": "current_heating_setpoint", "type": "numeric", "unit": "°C",
+                     "value_min": 5, "value_max": 30, "value_step": 0.5},
+                    {"access": 5, "name": "local_temperature", "property": "local_temperature",
+                     "type": "numeric", "unit": "°C"},
+                    {"access": 7, "name": "system_mode", "property": "system_mode", "type": "enum",
+                     "values": ["off", "heat", "auto"]}
+                ]},
+                {"type": "light", "features": [
+                    {"access": 7, "name": "color_xy", "property": "color", "type": "composite",
+                     "features": [
+                        {"access": 7, "name": "x", "property": "x", "type": "numeric"},
+                        {"access": 7, "name": "y", "property": "y", "type": "numeric"}
+                     ]},
+                    {"access": 7, "name": "color_hs", "property": "color", "type": "composite",
+                     "features": [
+                        {"access": 7, "name": "hue", "property": "hue", "type": "numeric"},
+                        {"access": 7, "name": "saturation", "property": "saturation",
+                         "type": "numeric"}
+                     ]}
+                ]}
+            ]
+        }))
+        .unwrap();
+        let mapping = Mapping::new(&definition);
+
+        let ids: Vec<_> = mapping.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["switch-l1", "switch-l2", "climate", "light"]);
+        assert_eq!(mapping.property("switch-l2", "state").unwrap().path, ["state_l2"]);
+
+        let climate: Vec<_> = mapping.nodes[2]
+            .properties
+            .iter()
+            .map(|p| p.attributes.id.as_str())
+            .collect();
+        assert_eq!(climate, ["heating-setpoint", "temperature", "mode"]);
+
+        let color = mapping.property("light", "color").unwrap();
+        assert_eq!(color.conversion, Conversion::ColorHs);
+        assert!(matches!(color.attributes.format, Some(Format::ColorHsv)));
+        assert_eq!(mapping.nodes[3].properties.len(), 1);
+
+        let state = json!({"color": {"hue": 29.6, "saturation": 70, "x": 0.5, "y": 0.4}});
+        let updates = mapping.updates(&state);
+        assert!(matches!(updates[0].payload, Some(Payload::Color(Color::Hsv(30, 70, 100)))));
+
+        let command = SetCommand {
+            node: "light".into(),
+            property: "color".into(),
+            payload: "240,100,50".into(),
+        };
+        assert_eq!(
+            mapping.set_payload(&command, &Value::Null).unwrap(),
+            json!({"color": {"hue": 240, "saturation": 100}})
+        );
+    }
+
+    #[test]
+    fn ids() {
+        assert_eq!(id("color_temp"), "color-temp");
+        assert_eq!(id("state_L1"), "state-l1");
+        assert_eq!(id("_weird__name_"), "weird-name");
+    }
+}
@@ -0,0 +1,650 @@
+//! Network backups in the open coordinator backup format
+//!
+//! The format is shared with zigpy and zigbee-herdsman, so a backup taken
+//! here can be restored by either and the other way around. Only the items
+//! michiru itself relies on are read: the network parameters come from the
+//! NV items written when commissioning, the current channel and NWK update
+//! id from the NIB, and the security level is always the default.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    client::ZStack,
+    commands::{sys, util},
+    coordinator::NetworkConfig,
+    database::{deserialize_ieee, serialize_ieee},
+    nv,
+};
+
+pub const FORMAT: &str = "zigpy/open-coordinator-backup";
+pub const VERSION: u32 = 1;
+
+/// Added to the backed up frame counter on restore, devices drop frames
+/// with a counter they have already seen
+pub const FRAME_COUNTER_MARGIN: u32 = 2500;
+
+const ADDRMGR_USER_ASSOC: u8 = 0x01;
+const ADDRMGR_USER_SECURITY: u8 = 0x02;
+
+/// Number of extended items probed for tables stored one entry per sub id
+const MAX_EX_ENTRIES: u16 = 256;
+
+/// Extended item holding the outgoing frame counters
+const EX_NWK_SEC_MATERIAL_TABLE: u16 = 0x0007;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backup {
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub stack_specific: StackSpecific,
+    #[serde(serialize_with = "serialize_ieee", deserialize_with = "deserialize_ieee")]
+    pub coordinator_ieee: u64,
+    #[serde(serialize_with = "serialize_u16", deserialize_with = "deserialize_u16")]
+    pub pan_id: u16,
+    #[serde(serialize_with = "serialize_ieee", deserialize_with = "deserialize_ieee")]
+    pub extended_pan_id: u64,
+    #[serde(default)]
+    pub nwk_update_id: u8,
+    #[serde(default = "default_security_level")]
+    pub security_level: u8,
+    pub channel: u8,
+    pub channel_mask: Vec<u8>,
+    pub network_key: NetworkKey,
+    #[serde(default)]
+    pub devices: Vec<BackupDevice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub format: String,
+    pub version: u32,
+    pub source: String,
+    #[serde(default)]
+    pub internal: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackSpecific {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zstack: Option<ZStackSpecific>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZStackSpecific {
+    /// Seed the trust center derives the devices' link keys from
+    #[serde(default, with = "key_opt", skip_serializing_if = "Option::is_none")]
+    pub tclk_seed: Option<[u8; 16]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkKey {
+    #[serde(with = "key")]
+    pub key: [u8; 16],
+    pub sequence_number: u8,
+    pub frame_counter: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupDevice {
+    #[serde(serialize_with = "serialize_u16", deserialize_with = "deserialize_u16")]
+    pub nwk_address: u16,
+    #[serde(serialize_with = "serialize_ieee", deserialize_with = "deserialize_ieee")]
+    pub ieee_address: u64,
+    #[serde(default)]
+    pub is_child: bool,
+    /// Kept as is, michiru doesn't use unique link keys
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_key: Option<serde_json::Value>,
+}
+
+/// An address manager entry, packed on the CC2530/CC2531 and aligned in the
+/// extended NV of newer firmwares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AddrMgrEntry {
+    user: u8,
+    nwk_addr: u16,
+    ieee_addr: u64,
+}
+
+impl AddrMgrEntry {
+    const EMPTY: Self = Self {
+        user: 0,
+        nwk_addr: 0xffff,
+        ieee_addr: u64::MAX,
+    };
+
+    fn parse(src: &[u8], aligned: bool) -> Option<Self> {
+        let src = match aligned {
+            true => [src.get(..1)?, src.get(2..)?].concat(),
+            false => src.to_vec(),
+        };
+        if src.len() < 11 {
+            return None;
+        }
+
+        Some(Self {
+            user: src[0],
+            nwk_addr: u16::from_le_bytes([src[1], src[2]]),
+            ieee_addr: u64::from_le_bytes(src[3..11].try_into().unwrap()),
+        })
+    }
+
+    fn serialize(&self, aligned: bool) -> Vec<u8> {
+        let mut buf = vec![self.user];
+        if aligned {
+            buf.push(0);
+        }
+        buf.extend_from_slice(&self.nwk_addr.to_le_bytes());
+        buf.extend_from_slice(&self.ieee_addr.to_le_bytes());
+        buf
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self.nwk_addr, 0xfffe | 0xffff) || matches!(self.ieee_addr, 0 | u64::MAX)
+    }
+
+    fn to_device(self) -> BackupDevice {
+        BackupDevice {
+            nwk_address: self.nwk_addr,
+            ieee_address: self.ieee_addr,
+            is_child: self.user & ADDRMGR_USER_ASSOC != 0,
+            link_key: None,
+        }
+    }
+
+    fn from_device(device: &BackupDevice) -> Self {
+        Self {
+            user: match device.is_child {
+                true => ADDRMGR_USER_ASSOC,
+                false => ADDRMGR_USER_SECURITY,
+            },
+            nwk_addr: device.nwk_address,
+            ieee_addr: device.ieee_address,
+        }
+    }
+}
+
+/// The parts of the network information base a backup needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Nib {
+    channel: u8,
+    nwk_update_id: u8,
+}
+
+impl Nib {
+    /// Packed on the CC2530/CC2531, aligned on newer firmwares
+    const PACKED_LEN: usize = 110;
+    const ALIGNED_LEN: usize = 116;
+
+    fn parse(src: &[u8]) -> Result<Self> {
+        // offsets of nwkLogicalChannel and nwkUpdateId
+        let (channel, nwk_update_id) = match src.len() {
+            Self::PACKED_LEN => (22, 109),
+            Self::ALIGNED_LEN => (24, 114),
+            len => bail!("Unknown NIB layout ({len} bytes)"),
+        };
+
+        Ok(Self {
+            channel: src[channel],
+            nwk_update_id: src[nwk_update_id],
+        })
+    }
+}
+
+impl Backup {
+    /// Checks that the configuration describes the backed up network, the
+    /// coordinator would otherwise form a new network on its next start
+    pub fn check_config(&self, config: &NetworkConfig) -> Result<()> {
+        let mut mismatches = vec![];
+
+        if config.pan_id != self.pan_id {
+            mismatches.push(format!("pan_id = {:#06x}", self.pan_id));
+        }
+        if config.extended_pan_id != self.extended_pan_id {
+            mismatches.push(format!("extended_pan_id = \"{:016x}\"", self.extended_pan_id));
+        }
+        if config.channels != [self.channel] {
+            mismatches.push(format!("channels = [{}]", self.channel));
+        }
+        if config.network_key != self.network_key.key {
+            mismatches.push(format!("network_key = \"{}\"", hex::encode(self.network_key.key)));
+        }
+
+        if !mismatches.is_empty() {
+            bail!(
+                "The network configuration doesn't match the backup, set these in [network]:\n{}",
+                mismatches.join("\n")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the network state from the device without changing it
+pub async fn backup(zstack: &ZStack) -> Result<Backup> {
+    let version = zstack.request(&sys::Version {}).await?;
+
+    let coordinator_ieee = match nv::read(zstack, nv::EXTADDR).await? {
+        // all ones when the factory address is used
+        Some(value) if value.len() == 8 && value != [0xff; 8] => {
+            u64::from_le_bytes(value.try_into().unwrap())
+        }
+        _ => zstack.request(&util::GetDeviceInfo {}).await?.ieee_addr,
+    };
+
+    let pan_id = read_item(zstack, nv::PANID, "PAN id").await?;
+    let pan_id = u16::from_le_bytes(pan_id.get(..2).context("Invalid PAN id")?.try_into()?);
+
+    let extended_pan_id = read_item(zstack, nv::EXTENDED_PAN_ID, "extended PAN id").await?;
+    let extended_pan_id = u64::from_le_bytes(
+        extended_pan_id
+            .get(..8)
+            .context("Invalid extended PAN id")?
+            .try_into()?,
+    );
+
+    let channels = read_item(zstack, nv::CHANLIST, "channel list").await?;
+    let channels = u32::from_le_bytes(
+        channels
+            .get(..4)
+            .context("Invalid channel list")?
+            .try_into()?,
+    );
+    let channel_mask: Vec<u8> = (11..=26).filter(|c| channels & 1 << c != 0).collect();
+
+    // the network may have formed on any channel of the mask
+    let nib = Nib::parse(&read_item(zstack, nv::NIB, "NIB").await?)?;
+
+    let network_key = network_key(zstack, extended_pan_id).await?;
+
+    let tclk_seed = nv::read(zstack, nv::TCLK_SEED)
+        .await?
+        .and_then(|value| value.get(..16)?.try_into().ok());
+
+    let devices = address_manager(zstack)
+        .await?
+        .0
+        .into_iter()
+        .filter(|entry| !entry.is_empty())
+        .map(AddrMgrEntry::to_device)
+        .collect();
+
+    let mut internal = serde_json::Map::new();
+    internal.insert(
+        "zstack_version".into(),
+        format!(
+            "{}.{}.{} (product {}, revision {})",
+            version.major_rel,
+            version.minor_rel,
+            version.maint_rel,
+            version.product,
+            version.revision.unwrap_or_default()
+        )
+        .into(),
+    );
+
+    Ok(Backup {
+        metadata: Metadata {
+            format: FORMAT.into(),
+            version: VERSION,
+            source: concat!("michiru-zstack@", env!("CARGO_PKG_VERSION")).into(),
+            internal,
+        },
+        stack_specific: StackSpecific {
+            zstack: Some(ZStackSpecific { tclk_seed }),
+        },
+        coordinator_ieee,
+        pan_id,
+        extended_pan_id,
+        nwk_update_id: nib.nwk_update_id,
+        security_level: default_security_level(),
+        channel: nib.channel,
+        channel_mask,
+        network_key,
+        devices,
+    })
+}
+
+/// Writes the coordinator address before commissioning, the other items
+/// only exist once the network is formed
+pub async fn restore_address(zstack: &ZStack, backup: &Backup) -> Result<()> {
+    nv::write(zstack, nv::EXTADDR, &backup.coordinator_ieee.to_le_bytes()).await?;
+
+    // have the next start commission the device from scratch
+    nv::write(zstack, nv::MICHIRU_CONFIGURED, &[0x00]).await
+}
+
+/// Writes the key, frame counters, trust center seed and known devices of a
+/// freshly formed network, the device must be reset afterwards
+pub async fn restore(zstack: &ZStack, backup: &Backup) -> Result<()> {
+    let key = &backup.network_key;
+    let frame_counter = key.frame_counter.saturating_add(FRAME_COUNTER_MARGIN);
+
+    let mut active_key = vec![key.sequence_number];
+    active_key.extend_from_slice(&key.key);
+    nv::write(zstack, nv::NWK_ACTIVE_KEY_INFO, &active_key).await?;
+
+    let mut nwk_key = active_key;
+    nwk_key.extend_from_slice(&frame_counter.to_le_bytes());
+    nv::write(zstack, nv::NWKKEY, &nwk_key).await?;
+
+    // Z-Stack 3.x counts frames per extended PAN id
+    let mut material = frame_counter.to_le_bytes().to_vec();
+    material.extend_from_slice(&backup.extended_pan_id.to_le_bytes());
+    if nv::read(zstack, nv::LEGACY_NWK_SEC_MATERIAL_TABLE_START)
+        .await?
+        .is_some()
+    {
+        nv::write(zstack, nv::LEGACY_NWK_SEC_MATERIAL_TABLE_START, &material).await?;
+    } else if nv::read_ex(zstack, EX_NWK_SEC_MATERIAL_TABLE, 0)
+        .await?
+        .is_some()
+    {
+        nv::write_ex(zstack, EX_NWK_SEC_MATERIAL_TABLE, 0, &material).await?;
+    }
+
+    if let Some(seed) = backup
+        .stack_specific
+        .zstack
+        .as_ref()
+        .and_then(|z| z.tclk_seed)
+    {
+        nv::write(zstack, nv::TCLK_SEED, &seed).await?;
+    }
+
+    let (current, aligned) = address_manager(zstack).await?;
+    if backup.devices.len() > current.len() {
+        tracing::warn!(
+            devices = backup.devices.len(),
+            capacity = current.len(),
+            "Address manager is too small, dropping devices"
+        );
+    }
+
+    let entries: Vec<AddrMgrEntry> = backup
+        .devices
+        .iter()
+        .map(AddrMgrEntry::from_device)
+        .chain(std::iter::repeat(AddrMgrEntry::EMPTY))
+        .take(current.len())
+        .collect();
+
+    if aligned {
+        for (i, entry) in entries.iter().enumerate() {
+            nv::write_ex(zstack, nv::EX_ADDRMGR, i as u16, &entry.serialize(true)).await?;
+        }
+    } else {
+        let table: Vec<u8> = entries.iter().flat_map(|e| e.serialize(false)).collect();
+        nv::write(zstack, nv::ADDRMGR, &table).await?;
+    }
+
+    Ok(())
+}
+
+async fn read_item(zstack: &ZStack, id: u16, name: &str) -> Result<Vec<u8>> {
+    nv::read(zstack, id)
+        .await?
+        .with_context(|| format!("No {name} in NV, is the network configured?"))
+}
+
+/// Reads the active network key and the highest outgoing frame counter
+async fn network_key(zstack: &ZStack, extended_pan_id: u64) -> Result<NetworkKey> {
+    // sequence number, key and, in Z-Stack 1.2, the frame counter
+    let nwk_key = nv::read(zstack, nv::NWKKEY).await?;
+    let active_key = match nv::read(zstack, nv::NWK_ACTIVE_KEY_INFO).await? {
+        Some(value) if value.len() >= 17 => Some(value),
+        _ => nwk_key.clone().filter(|value| value.len() >= 17),
+    };
+
+    let (sequence_number, key) = match active_key {
+        Some(value) => (value[0], value[1..17].try_into()?),
+        None => {
+            let key = read_item(zstack, nv::PRECFGKEY, "network key").await?;
+            (0, key.get(..16).context("Invalid network key")?.try_into()?)
+        }
+    };
+
+    let mut frame_counter = nwk_key
+        .and_then(|value| Some(u32::from_le_bytes(value.get(17..21)?.try_into().ok()?)))
+        .unwrap_or_default();
+
+    // Z-Stack 3.x entries are the frame counter followed by the extended PAN
+    // id it applies to, all ones for the generic entry
+    let mut materials = vec![];
+    for id in nv::LEGACY_NWK_SEC_MATERIAL_TABLE_START..=nv::LEGACY_NWK_SEC_MATERIAL_TABLE_END {
+        materials.extend(nv::read(zstack, id).await?);
+    }
+    if materials.is_empty() {
+        for sub_id in 0..MAX_EX_ENTRIES {
+            match nv::read_ex(zstack, EX_NWK_SEC_MATERIAL_TABLE, sub_id).await? {
+                Some(value) => materials.push(value),
+                None => break,
+            }
+        }
+    }
+
+    for material in materials.iter().filter(|m| m.len() >= 12) {
+        let counter = u32::from_le_bytes(material[..4].try_into()?);
+        let epid = u64::from_le_bytes(material[4..12].try_into()?);
+        if epid == extended_pan_id || epid == u64::MAX {
+            frame_counter = frame_counter.max(counter);
+        }
+    }
+
+    Ok(NetworkKey { key, sequence_number, frame_counter })
+}
+
+/// Reads the address manager table, and whether its entries are aligned
+async fn address_manager(zstack: &ZStack) -> Result<(Vec<AddrMgrEntry>, bool)> {
+    if let Some(table) = nv::read(zstack, nv::ADDRMGR).await? {
+        let entries = table
+            .chunks_exact(11)
+            .filter_map(|entry| AddrMgrEntry::parse(entry, false))
+            .collect();
+        return Ok((entries, false));
+    }
+
+    let mut entries = vec![];
+    for sub_id in 0..MAX_EX_ENTRIES {
+        match nv::read_ex(zstack, nv::EX_ADDRMGR, sub_id).await? {
+            Some(value) => entries.extend(AddrMgrEntry::parse(&value, true)),
+            None => break,
+        }
+    }
+
+    Ok((entries, true))
+}
+
+fn default_security_level() -> u8 {
+    5
+}
+
+fn serialize_u16<S: Serializer>(value: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{value:04x}"))
+}
+
+fn deserialize_u16<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    u16::from_str_radix(value.trim_start_matches("0x"), 16).map_err(serde::de::Error::custom)
+}
+
+mod key {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8; 16], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 16], D::Error> {
+        let value = String::deserialize(deserializer)?;
+
+        let mut key = [0; 16];
+        hex::decode_to_slice(value.replace(':', ""), &mut key).map_err(serde::de::Error::custom)?;
+
+        Ok(key)
+    }
+}
+
+mod key_opt {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<[u8; 16]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::key::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<[u8; 16]>, D::Error> {
+        #[derive(Deserialize)]
+        struct Key(#[serde(with = "super::key")] [u8; 16]);
+
+        Ok(Option::<Key>::deserialize(deserializer)?.map(|Key(key)| key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::transport::simulator::Simulator;
+
+    #[test]
+    fn open_coordinator_backup() {
+        let json = r#"{
+            "metadata": {
+                "version": 1,
+                "format": "zigpy/open-coordinator-backup",
+                "source": "zigpy-znp@0.9.0",
+                "internal": {"creation_time": "2022-01-01T00:00:00+00:00"}
+            },
+            "stack_specific": {"zstack": {"tclk_seed": "c04884427c8a1ed7bb8412815ccce7aa"}},
+            "coordinator_ieee": "00124b0012345678",
+            "pan_id": "1a62",
+            "extended_pan_id": "dddddddddddddddd",
+            "nwk_update_id": 0,
+            "security_level": 5,
+            "channel": 11,
+            "channel_mask": [11],
+            "network_key": {
+                "key": "01030507090b0d0f00020406080a0c0d",
+                "sequence_number": 0,
+                "frame_counter": 4321
+            },
+            "devices": [
+                {"nwk_address": "c3a2", "ieee_address": "00158d0001a2b3c4", "is_child": true},
+                {
+                    "nwk_address": "8a2e",
+                    "ieee_address": "00158d0004567890",
+                    "is_child": false,
+                    "link_key": {"key": "00000000000000000000000000000000", "tx_counter": 0, "rx_counter": 0}
+                }
+            ]
+        }"#;
+
+        let backup: Backup = serde_json::from_str(json).unwrap();
+        assert_eq!(backup.coordinator_ieee, 0x00124b0012345678);
+        assert_eq!(backup.pan_id, 0x1a62);
+        assert_eq!(backup.network_key.key[..3], [0x01, 0x03, 0x05]);
+        assert_eq!(backup.devices[0].nwk_address, 0xc3a2);
+        assert!(backup.devices[1].link_key.is_some());
+
+        let reparsed: Backup =
+            serde_json::from_str(&serde_json::to_string(&backup).unwrap()).unwrap();
+        assert_eq!(reparsed, backup);
+
+        let value = serde_json::to_value(&backup).unwrap();
+        assert_eq!(value["pan_id"], "1a62");
+        assert_eq!(value["extended_pan_id"], "dddddddddddddddd");
+        assert_eq!(
+            value["stack_specific"]["zstack"]["tclk_seed"],
+            "c04884427c8a1ed7bb8412815ccce7aa"
+        );
+    }
+
+    #[test]
+    fn address_manager_entries() {
+        let packed = [0x01, 0xa2, 0xc3, 0xc4, 0xb3, 0xa2, 0x01, 0x00, 0x8d, 0x15, 0x00];
+        let entry = AddrMgrEntry::parse(&packed, false).unwrap();
+        assert_eq!(entry.nwk_addr, 0xc3a2);
+        assert_eq!(entry.ieee_addr, 0x00158d0001a2b3c4);
+        assert!(entry.to_device().is_child);
+        assert_eq!(entry.serialize(false), packed);
+
+        let aligned = entry.serialize(true);
+        assert_eq!(aligned.len(), 12);
+        assert_eq!(AddrMgrEntry::parse(&aligned, true), Some(entry));
+
+        assert!(AddrMgrEntry::parse(&AddrMgrEntry::EMPTY.serialize(false), false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn backup_from_nv() {
+        let extended_pan_id: u64 = 0xdddd_dddd_dddd_dddd;
+        let key = [0x01, 0x03, 0x05, 0x07, 0x09, 0x0b, 0x0d, 0x0f, 0, 2, 4, 6, 8, 10, 12, 13];
+
+        // packed CC2531 layout, formed on the second channel of the mask
+        let mut nib = vec![0; Nib::PACKED_LEN];
+        nib[22] = 15;
+        nib[109] = 3;
+
+        let mut active_key = vec![0x00];
+        active_key.extend_from_slice(&key);
+        let mut material = 4321u32.to_le_bytes().to_vec();
+        material.extend_from_slice(&extended_pan_id.to_le_bytes());
+        let device = AddrMgrEntry {
+            user: ADDRMGR_USER_ASSOC,
+            nwk_addr: 0xc3a2,
+            ieee_addr: 0x00158d0001a2b3c4,
+        };
+        let addrmgr = [device.serialize(false), AddrMgrEntry::EMPTY.serialize(false)].concat();
+
+        let items = HashMap::from([
+            (nv::EXTADDR, 0x00124b0012345678u64.to_le_bytes().to_vec()),
+            (nv::PANID, 0x1a62u16.to_le_bytes().to_vec()),
+            (nv::EXTENDED_PAN_ID, extended_pan_id.to_le_bytes().to_vec()),
+            (nv::CHANLIST, (1u32 << 11 | 1 << 15 | 1 << 26).to_le_bytes().to_vec()),
+            (nv::NIB, nib),
+            (nv::NWK_ACTIVE_KEY_INFO, active_key),
+            (nv::LEGACY_NWK_SEC_MATERIAL_TABLE_START, material),
+            (nv::ADDRMGR, addrmgr),
+        ]);
+        let simulator = Simulator::new().respond(|_: sys::Version| sys::VersionResponse {
+            transport_rev: 2,
+            product: 0,
+            major_rel: 2,
+            minor_rel: 6,
+            maint_rel: 3,
+            revision: Some(20190608),
+        });
+        let (transport, _) = nv::simulate(simulator, Arc::new(Mutex::new(items))).start();
+        let zstack = ZStack::new(transport);
+
+        let backup = backup(&zstack).await.unwrap();
+        assert_eq!(backup.coordinator_ieee, 0x00124b0012345678);
+        assert_eq!(backup.pan_id, 0x1a62);
+        assert_eq!(backup.extended_pan_id, extended_pan_id);
+        assert_eq!(backup.channel, 15);
+        assert_eq!(backup.channel_mask, [11, 15, 26]);
+        assert_eq!(backup.nwk_update_id, 3);
+        assert_eq!(backup.network_key, NetworkKey {
+            key,
+            sequence_number: 0,
+            frame_counter: 4321,
+        });
+        assert_eq!(backup.devices, [device.to_device()]);
+    }
+}
@@ -0,0 +1,434 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use michiru_device::{
+    DataType, Device, DeviceBuilder, Format, MqttConfig, NodeAttributes, Payload,
+    PropertyAttributes, SetCommand, Unit,
+};
+use michiru_zstack::{
+    client::ZStack,
+    commands::af,
+    database, network,
+    zcl::{
+        clusters::{level_control, on_off, power_configuration, temperature_measurement},
+        global::{Attribute, ReadAttributeStatus},
+        Frame, FrameType, GlobalCommand, Value,
+    },
+};
+use tokio::sync::mpsc;
+
+const LINK_ID: &str = "link";
+const QUALITY_ID: &str = "quality";
+const CONFIGURE_ID: &str = "configure";
+const SWITCH_ID: &str = "switch";
+const ON_ID: &str = "on";
+const DIMMER_ID: &str = "dimmer";
+const LEVEL_ID: &str = "level";
+const THERMOMETER_ID: &str = "thermometer";
+const TEMPERATURE_ID: &str = "temperature";
+const BATTERY_ID: &str = "battery";
+
+/// Publishes interviewed devices as Homie devices
+pub struct Bridge {
+    zstack: Arc<ZStack>,
+    mqtt: MqttConfig,
+    devices: HashMap<u64, BridgedDevice>,
+    commands: mpsc::UnboundedSender<(u64, SetCommand)>,
+}
+
+struct BridgedDevice {
+    device: Device,
+    nwk_addr: u16,
+    /// Endpoint implementing each server cluster
+    endpoints: HashMap<u16, u8>,
+}
+
+impl Bridge {
+    /// Also returns the `/set` commands of all bridged devices, tagged with
+    /// their IEEE address
+    pub fn new(
+        zstack: Arc<ZStack>,
+        mqtt: MqttConfig,
+    ) -> (Self, mpsc::UnboundedReceiver<(u64, SetCommand)>) {
+        let (commands, rx) = mpsc::unbounded_channel();
+
+        let bridge = Self {
+            zstack,
+            mqtt,
+            devices: HashMap::new(),
+            commands,
+        };
+
+        (bridge, rx)
+    }
+
+    /// Publishes an interviewed device, replacing an earlier version of it
+    pub async fn add(&mut self, device: &database::Device) -> Result<()> {
+        if let Some(old) = self.devices.remove(&device.ieee_addr) {
+            old.device.disconnect().await?;
+        }
+
+        let id = format!("zigbee-{:016x}", device.ieee_addr);
+        let name = device.model.clone().unwrap_or_else(|| id.clone());
+
+        let mut builder = DeviceBuilder::new(self.mqtt.options(id.clone()), id, name).await?;
+        for node in nodes(device) {
+            builder = builder.node(node).await?;
+        }
+        let mut homie = builder.build().await?;
+
+        if let Some(mut commands) = homie.take_commands() {
+            let ieee_addr = device.ieee_addr;
+            let tx = self.commands.clone();
+
+            tokio::spawn(async move {
+                while let Some(command) = commands.recv().await {
+                    if tx.send((ieee_addr, command)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let mut endpoints = HashMap::new();
+        for endpoint in &device.endpoints {
+            for &cluster in &endpoint.in_clusters {
+                endpoints.entry(cluster).or_insert(endpoint.endpoint);
+            }
+        }
+
+        self.devices.insert(device.ieee_addr, BridgedDevice {
+            device: homie,
+            nwk_addr: device.nwk_addr,
+            endpoints,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_address(&mut self, ieee_addr: u64, nwk_addr: u16) {
+        if let Some(bridged) = self.devices.get_mut(&ieee_addr) {
+            bridged.nwk_addr = nwk_addr;
+        }
+    }
+
+    /// Publishes the attributes carried by an incoming ZCL frame
+    pub async fn handle_message(&mut self, message: &af::IncomingMsg) -> Result<()> {
+        let Some(bridged) = self
+            .devices
+            .values()
+            .find(|d| d.nwk_addr == message.src_addr)
+        else {
+            tracing::debug!(nwk_addr = format!("{:#06x}", message.src_addr), "Unknown device");
+            return Ok(());
+        };
+
+        send(&bridged.device, LINK_ID, QUALITY_ID, Payload::Integer(message.link_quality as i64))
+            .await?;
+
+        let frame = Frame::parse(&message.data)?;
+        if frame.header.frame_type != FrameType::Global {
+            return Ok(());
+        }
+
+        let attributes = match frame.global_command()? {
+            GlobalCommand::ReportAttributes(records) => records,
+            GlobalCommand::ReadAttributesResponse(records) => records
+                .into_iter()
+                .filter_map(|ReadAttributeStatus { attribute, value, .. }| {
+                    Some(Attribute { attribute, value: value? })
+                })
+                .collect(),
+            _ => return Ok(()),
+        };
+
+        for Attribute { attribute, value } in attributes {
+            if let Some((node, property, payload)) = report(message.cluster_id, attribute, &value) {
+                send(&bridged.device, node, property, payload).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Translates a `/set` command to the ZCL command forwarding it, which
+    /// is sent apart as delivery can take a while
+    pub fn prepare_set(&self, ieee_addr: u64, command: SetCommand) -> Result<PendingSet> {
+        let bridged = self
+            .devices
+            .get(&ieee_addr)
+            .with_context(|| format!("Unknown device {ieee_addr:016x}"))?;
+
+        let (cluster_id, frame, payload) = set_frame(&command, network::next_sequence())?;
+
+        let endpoint = *bridged
+            .endpoints
+            .get(&cluster_id)
+            .with_context(|| format!("Device has no endpoint for cluster {cluster_id:#06x}"))?;
+
+        Ok(PendingSet {
+            zstack: self.zstack.clone(),
+            ieee_addr,
+            nwk_addr: bridged.nwk_addr,
+            endpoint,
+            cluster_id,
+            frame,
+            command,
+            payload,
+        })
+    }
+
+    /// Publishes the value of a delivered `/set` command
+    pub async fn set_delivered(&self, set: PendingSet) -> Result<()> {
+        let bridged = self
+            .devices
+            .get(&set.ieee_addr)
+            .with_context(|| format!("Unknown device {:016x}", set.ieee_addr))?;
+
+        // devices without reporting configured won't tell us
+        send(&bridged.device, &set.command.node, &set.command.property, set.payload).await
+    }
+}
+
+/// A `/set` command waiting to be delivered
+pub struct PendingSet {
+    zstack: Arc<ZStack>,
+    pub ieee_addr: u64,
+    nwk_addr: u16,
+    endpoint: u8,
+    cluster_id: u16,
+    frame: Frame,
+    command: SetCommand,
+    payload: Payload,
+}
+
+impl PendingSet {
+    /// Sends the ZCL command and waits for it to be delivered
+    pub async fn send(&self) -> Result<()> {
+        network::send_zcl(&self.zstack, self.nwk_addr, self.endpoint, self.cluster_id, &self.frame)
+            .await
+    }
+}
+
+async fn send(device: &Device, node: &str, property: &str, payload: Payload) -> Result<()> {
+    let node = device
+        .node(node)
+        .ok_or_else(|| anyhow!("Device has no node {node}"))?;
+    let property = node
+        .property(property)
+        .await
+        .ok_or_else(|| anyhow!("Device has no property {property}"))?;
+
+    property.send(payload).await
+}
+
+/// Whether the command asks to redo bindings and reporting configuration
+pub fn is_configure(command: &SetCommand) -> bool {
+    command.node == LINK_ID && command.property == CONFIGURE_ID && command.payload.trim() == "true"
+}
+
+/// Homie nodes for the clusters a device implements
+fn nodes(device: &database::Device) -> Vec<NodeAttributes> {
+    let mut nodes = vec![NodeAttributes {
+        id: LINK_ID.into(),
+        name: "Link".into(),
+        type_: "Zigbee".into(),
+        properties: vec![
+            PropertyAttributes {
+                id: QUALITY_ID.into(),
+                name: "Quality".into(),
+                datatype: DataType::Integer,
+                settable: false,
+                retained: true,
+                unit: Some(Unit::Other("lqi".into())),
+                format: Some(Format::IntRange(0, 255)),
+            },
+            PropertyAttributes {
+                id: CONFIGURE_ID.into(),
+                name: "Configure reporting".into(),
+                datatype: DataType::Boolean,
+                settable: true,
+                retained: false,
+                unit: None,
+                format: None,
+            },
+        ],
+    }];
+
+    let mut clusters: Vec<_> = device
+        .endpoints
+        .iter()
+        .flat_map(|e| e.in_clusters.iter().copied())
+        .collect();
+    clusters.sort_unstable();
+    clusters.dedup();
+
+    for cluster in clusters {
+        let node = match cluster {
+            on_off::ID => NodeAttributes {
+                id: SWITCH_ID.into(),
+                name: "Switch".into(),
+                type_: "On/Off".into(),
+                properties: vec![PropertyAttributes {
+                    id: ON_ID.into(),
+                    name: "On".into(),
+                    datatype: DataType::Boolean,
+                    settable: true,
+                    retained: true,
+                    unit: None,
+                    format: None,
+                }],
+            },
+            level_control::ID => NodeAttributes {
+                id: DIMMER_ID.into(),
+                name: "Dimmer".into(),
+                type_: "Level".into(),
+                properties: vec![PropertyAttributes {
+                    id: LEVEL_ID.into(),
+                    name: "Level".into(),
+                    datatype: DataType::Integer,
+                    settable: true,
+                    retained: true,
+                    unit: None,
+                    format: Some(Format::IntRange(0, level_control::MAX_LEVEL as i64)),
+                }],
+            },
+            temperature_measurement::ID => NodeAttributes {
+                id: THERMOMETER_ID.into(),
+                name: "Thermometer".into(),
+                type_: "Thermometer".into(),
+                properties: vec![PropertyAttributes {
+                    id: TEMPERATURE_ID.into(),
+                    name: "Temperature".into(),
+                    datatype: DataType::Float,
+                    settable: false,
+                    retained: true,
+                    unit: Some(Unit::DegreeCelsius),
+                    format: None,
+                }],
+            },
+            power_configuration::ID => NodeAttributes {
+                id: BATTERY_ID.into(),
+                name: "Battery".into(),
+                type_: "Battery".into(),
+                properties: vec![PropertyAttributes {
+                    id: BATTERY_ID.into(),
+                    name: "Battery".into(),
+                    datatype: DataType::Float,
+                    settable: false,
+                    retained: true,
+                    unit: Some(Unit::Percent),
+                    format: Some(Format::FloatRange(0., 100.)),
+                }],
+            },
+            _ => continue,
+        };
+
+        nodes.push(node);
+    }
+
+    nodes
+}
+
+/// Homie property and payload for a reported attribute
+fn report(
+    cluster_id: u16,
+    attribute: u16,
+    value: &Value,
+) -> Option<(&'static str, &'static str, Payload)> {
+    Some(match (cluster_id, attribute) {
+        (on_off::ID, on_off::ON_OFF) => (SWITCH_ID, ON_ID, Payload::Boolean(value.as_bool()?)),
+        (level_control::ID, level_control::CURRENT_LEVEL) => {
+            (DIMMER_ID, LEVEL_ID, Payload::Integer(value.as_i64()?))
+        }
+        (temperature_measurement::ID, temperature_measurement::MEASURED_VALUE) => (
+            THERMOMETER_ID,
+            TEMPERATURE_ID,
+            Payload::Float(temperature_measurement::celsius(value)?),
+        ),
+        (power_configuration::ID, power_configuration::BATTERY_PERCENTAGE_REMAINING) => (
+            BATTERY_ID,
+            BATTERY_ID,
+            Payload::Float(power_configuration::battery_percentage(value)?),
+        ),
+        _ => return None,
+    })
+}
+
+/// ZCL frame for a `/set` command, along with the state to publish once sent
+fn set_frame(command: &SetCommand, sequence: u8) -> Result<(u16, Frame, Payload)> {
+    let payload = command.payload.trim();
+
+    Ok(match (command.node.as_str(), command.property.as_str()) {
+        (SWITCH_ID, ON_ID) => {
+            let on = match payload {
+                "true" => true,
+                "false" => false,
+                _ => bail!("Invalid boolean {payload:?}"),
+            };
+
+            let frame = if on {
+                Frame::cluster(sequence, &on_off::On {})
+            } else {
+                Frame::cluster(sequence, &on_off::Off {})
+            };
+
+            (on_off::ID, frame, Payload::Boolean(on))
+        }
+        (DIMMER_ID, LEVEL_ID) => {
+            let level: u8 = payload
+                .parse()
+                .ok()
+                .filter(|&l| l <= level_control::MAX_LEVEL)
+                .with_context(|| format!("Invalid level {payload:?}"))?;
+
+            let frame = Frame::cluster(sequence, &level_control::MoveToLevelWithOnOff {
+                level,
+                transition_time: 0,
+            });
+
+            (level_control::ID, frame, Payload::Integer(level as i64))
+        }
+        (node, property) => bail!("Property {node}/{property} is not settable"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(node: &str, property: &str, payload: &str) -> SetCommand {
+        SetCommand {
+            node: node.into(),
+            property: property.into(),
+            payload: payload.into(),
+        }
+    }
+
+    #[test]
+    fn set_frames() {
+        let (cluster, frame, _) = set_frame(&command(SWITCH_ID, ON_ID, "true"), 1).unwrap();
+        assert_eq!(cluster, on_off::ID);
+        assert_eq!(frame.serialize(), vec![0x01, 0x01, 0x01]);
+
+        let (cluster, frame, _) = set_frame(&command(DIMMER_ID, LEVEL_ID, "128"), 2).unwrap();
+        assert_eq!(cluster, level_control::ID);
+        assert_eq!(frame.serialize(), vec![0x01, 0x02, 0x04, 0x80, 0x00, 0x00]);
+
+        assert!(set_frame(&command(DIMMER_ID, LEVEL_ID, "255"), 3).is_err());
+        assert!(set_frame(&command(SWITCH_ID, ON_ID, "on"), 3).is_err());
+        assert!(set_frame(&command(THERMOMETER_ID, TEMPERATURE_ID, "20"), 3).is_err());
+    }
+
+    #[test]
+    fn reports() {
+        let (_, _, payload) =
+            report(temperature_measurement::ID, 0x0000, &Value::Int16(2150)).unwrap();
+        assert!(matches!(payload, Payload::Float(v) if v == 21.5));
+
+        let (_, _, payload) = report(on_off::ID, on_off::ON_OFF, &Value::Bool(true)).unwrap();
+        assert!(matches!(payload, Payload::Boolean(true)));
+
+        assert!(report(on_off::ID, 0x4000, &Value::Bool(true)).is_none());
+    }
+}
@@ -0,0 +1,507 @@
+//! Capturing the MT traffic to a file and dissecting it afterwards
+//!
+//! [`Capture`] wraps a transport and logs the raw bytes going each way, one
+//! line per read or write:
+//!
+//! ```text
+//! 1700000000.123456 > fe00210120
+//! 1700000000.125012 < fe02610159066e
+//! ```
+//!
+//! Logging bytes rather than parsed frames keeps the noise on the line, the
+//! [`Dissector`] reassembles frames from it and reports invalid FCSs and
+//! discarded bytes.
+
+use std::{
+    fmt::{self, Write as _},
+    fs::File,
+    io::{self, LineWriter, Write as _},
+    path::Path,
+    pin::Pin,
+    str::FromStr,
+    task::{ready, Context as TaskContext, Poll},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc,
+};
+
+use crate::{
+    commands::{self, af, Command},
+    message::{CmdType, InvalidFcs, Message, MAX_DATA_LEN, SOF},
+    zcl::{self, Frame, FrameType},
+};
+
+const HEADER: &str = "# michiru-zstack MT capture";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the host to the device
+    Sent,
+    /// From the device to the host
+    Received,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Since the Unix epoch
+    pub timestamp: Duration,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// A transport that logs everything going through it, records are written
+/// on a blocking thread to keep polling the transport cheap
+pub struct Capture<T> {
+    inner: T,
+    records: mpsc::UnboundedSender<Record>,
+}
+
+/// What the dissector made of the bytes going one way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Frame(Message),
+    InvalidFcs(Message, InvalidFcs),
+    /// Bytes outside of any frame
+    Discarded(Vec<u8>),
+}
+
+/// Reassembles frames from the bytes going one way
+#[derive(Debug, Default)]
+pub struct Dissector {
+    buf: Vec<u8>,
+}
+
+impl Direction {
+    pub fn symbol(self) -> char {
+        match self {
+            Self::Sent => '>',
+            Self::Received => '<',
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:06} {} {}",
+            self.timestamp.as_secs(),
+            self.timestamp.subsec_micros(),
+            self.direction.symbol(),
+            hex::encode(&self.data)
+        )
+    }
+}
+
+impl FromStr for Record {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let mut fields = line.split_whitespace();
+        let (Some(timestamp), Some(direction), data) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            bail!("Missing fields");
+        };
+
+        let (secs, fraction) = timestamp.split_once('.').unwrap_or((timestamp, ""));
+        // the fraction is truncated to microseconds by byte
+        ensure!(fraction.bytes().all(|b| b.is_ascii_digit()), "Invalid timestamp");
+        let micros = format!("{fraction:0<6}");
+        let timestamp = Duration::from_secs(secs.parse().context("Invalid timestamp")?)
+            + Duration::from_micros(micros[..6].parse().context("Invalid timestamp")?);
+
+        let direction = match direction {
+            ">" => Direction::Sent,
+            "<" => Direction::Received,
+            other => bail!("Invalid direction {other:?}"),
+        };
+
+        let data = hex::decode(data.unwrap_or_default()).context("Invalid data")?;
+
+        Ok(Self { timestamp, direction, data })
+    }
+}
+
+/// Reads all records of a capture file
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<Record>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            line.parse()
+                .with_context(|| format!("{}:{}: invalid record", path.display(), i + 1))
+        })
+        .collect()
+}
+
+impl<T> Capture<T> {
+    /// Wraps a transport, truncating the capture file
+    pub fn create(inner: T, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = LineWriter::new(
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?,
+        );
+        writeln!(file, "{HEADER}")?;
+
+        let (records, mut rx) = mpsc::unbounded_channel::<Record>();
+        tokio::task::spawn_blocking(move || {
+            while let Some(record) = rx.blocking_recv() {
+                // a failing capture shouldn't take the connection down
+                if let Err(e) = writeln!(file, "{record}") {
+                    tracing::warn!(?e, "Failed to write capture");
+                }
+            }
+        });
+
+        Ok(Self { inner, records })
+    }
+
+    fn log(&self, direction: Direction, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let record = Record {
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default(),
+            direction,
+            data: data.to_vec(),
+        };
+
+        // the writer only stops along with the capture
+        let _ = self.records.send(record);
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Capture<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+
+        self.log(Direction::Received, &buf.filled()[filled..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Capture<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+
+        self.log(Direction::Sent, &buf[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl Dissector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds bytes and returns the events they complete, resyncing like
+    /// the codec does
+    pub fn push(&mut self, data: &[u8]) -> Vec<Event> {
+        self.buf.extend_from_slice(data);
+
+        let mut events = vec![];
+        let mut discarded = vec![];
+
+        loop {
+            let start = self
+                .buf
+                .iter()
+                .position(|&b| b == SOF)
+                .unwrap_or(self.buf.len());
+            discarded.extend(self.buf.drain(..start));
+
+            if self.buf.len() > 1 && self.buf[1] as usize > MAX_DATA_LEN {
+                discarded.push(self.buf.remove(0));
+                continue;
+            }
+
+            let (consumed, message) = match Message::parse(&self.buf) {
+                Ok((rest, message)) => (self.buf.len() - rest.len(), message),
+                Err(nom::Err::Incomplete(_)) => break,
+                Err(_) => {
+                    discarded.push(self.buf.remove(0));
+                    continue;
+                }
+            };
+
+            if let Err(e) = message.verify() {
+                // the start of frame may have been noise, resync after it
+                flush_discarded(&mut discarded, &mut events);
+                events.push(Event::InvalidFcs(message, e));
+                self.buf.remove(0);
+                continue;
+            }
+
+            flush_discarded(&mut discarded, &mut events);
+            self.buf.drain(..consumed);
+            events.push(Event::Frame(message));
+        }
+
+        flush_discarded(&mut discarded, &mut events);
+        events
+    }
+
+    /// Bytes of an incomplete frame left at the end of the capture
+    pub fn finish(self) -> Option<Event> {
+        (!self.buf.is_empty()).then_some(Event::Discarded(self.buf))
+    }
+}
+
+/// Dissects both directions of a capture, each event is stamped with the
+/// record that completed it
+pub fn dissect(records: &[Record]) -> Vec<(Duration, Direction, Event)> {
+    let mut sent = Dissector::new();
+    let mut received = Dissector::new();
+    let mut events = vec![];
+
+    for record in records {
+        let dissector = match record.direction {
+            Direction::Sent => &mut sent,
+            Direction::Received => &mut received,
+        };
+
+        events.extend(
+            dissector
+                .push(&record.data)
+                .into_iter()
+                .map(|event| (record.timestamp, record.direction, event)),
+        );
+    }
+
+    let end = records.last().map(|r| r.timestamp).unwrap_or_default();
+    events.extend(sent.finish().map(|event| (end, Direction::Sent, event)));
+    events.extend(
+        received
+            .finish()
+            .map(|event| (end, Direction::Received, event)),
+    );
+
+    events
+}
+
+fn flush_discarded(discarded: &mut Vec<u8>, events: &mut Vec<Event>) {
+    if !discarded.is_empty() {
+        events.push(Event::Discarded(std::mem::take(discarded)));
+    }
+}
+
+/// Human readable description of an event, possibly spanning several lines
+pub fn describe(event: &Event) -> String {
+    match event {
+        Event::Frame(message) => describe_message(message),
+        Event::InvalidFcs(message, e) => {
+            format!("{}: {e}, data {}", command_name(message), hex::encode(message.data()))
+        }
+        Event::Discarded(data) => format!("discarded {} bytes: {}", data.len(), hex::encode(data)),
+    }
+}
+
+fn describe_message(message: &Message) -> String {
+    let mut out = command_name(message);
+
+    match commands::decode(message) {
+        Some(Ok(command)) => {
+            let _ = write!(out, "\n    {command:?}");
+        }
+        Some(Err(e)) => {
+            let _ = write!(out, ": {e:#}, data {}", hex::encode(message.data()));
+        }
+        None if message.is_rpc_error() => {
+            let _ = write!(out, ": RPC error, data {}", hex::encode(message.data()));
+        }
+        None => {
+            let _ = write!(out, ": unknown command, data {}", hex::encode(message.data()));
+        }
+    }
+
+    let zcl = if let Ok(request) = af::DataRequest::from_message(message) {
+        Some((request.cluster_id, request.data))
+    } else if let Ok(incoming) = af::IncomingMsg::from_message(message) {
+        Some((incoming.cluster_id, incoming.data))
+    } else {
+        None
+    };
+
+    if let Some((cluster_id, data)) = zcl {
+        let _ = write!(out, "\n{}", indent(&describe_zcl(cluster_id, &data)));
+    }
+
+    out
+}
+
+fn describe_zcl(cluster_id: u16, data: &[u8]) -> String {
+    let cluster = match zcl::clusters::name(cluster_id) {
+        Some(name) => format!("{name} ({cluster_id:#06x})"),
+        None => format!("{cluster_id:#06x}"),
+    };
+
+    let frame = match Frame::parse(data) {
+        Ok(frame) => frame,
+        Err(e) => return format!("ZCL {cluster}: {e:#}"),
+    };
+
+    let header = &frame.header;
+    let mut out = format!("ZCL {cluster}, sequence {}, {:?}", header.sequence, header.direction);
+    if let Some(code) = header.manufacturer_code {
+        let _ = write!(out, ", manufacturer {code:#06x}");
+    }
+
+    let _ = match header.frame_type {
+        FrameType::Global => match frame.global_command() {
+            Ok(command) => write!(out, "\n{command:?}"),
+            Err(e) => write!(
+                out,
+                "\nglobal command {:#04x}: {e:#}, payload {}",
+                header.command_id,
+                hex::encode(&frame.payload)
+            ),
+        },
+        FrameType::ClusterSpecific => write!(
+            out,
+            "\ncluster command {:#04x}, payload {}",
+            header.command_id,
+            hex::encode(&frame.payload)
+        ),
+    };
+
+    out
+}
+
+fn command_name(message: &Message) -> String {
+    let cmd_type = match message.cmd_type() {
+        Ok(CmdType::Poll) => "POLL".into(),
+        Ok(CmdType::SyncRequest) => "SREQ".into(),
+        Ok(CmdType::AsyncRequest) => "AREQ".into(),
+        Ok(CmdType::SyncResponse) => "SRSP".into(),
+        Err(e) => format!("type {:#04x}", e.0),
+    };
+    let subsystem = match message.subsystem() {
+        Ok(subsystem) => format!("{subsystem:?}").to_uppercase(),
+        Err(e) => format!("subsystem {:#04x}", e.0),
+    };
+
+    format!("{cmd_type} {subsystem} {:#04x}", message.cmd_id())
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::ZStack, commands::sys, transport::simulator::Simulator};
+
+    #[test]
+    fn records() {
+        let record = Record {
+            timestamp: Duration::from_micros(1_700_000_000_012_345),
+            direction: Direction::Received,
+            data: vec![0xfe, 0x00, 0x21, 0x01, 0x20],
+        };
+
+        assert_eq!(record.to_string(), "1700000000.012345 < fe00210120");
+        assert_eq!(record.to_string().parse::<Record>().unwrap(), record);
+        assert_eq!("1.5 > fe".parse::<Record>().unwrap().timestamp, Duration::from_millis(1500));
+        assert!("1700000000.0 ? fe".parse::<Record>().is_err());
+        assert!("1700000000.00000é > fe".parse::<Record>().is_err());
+    }
+
+    #[test]
+    fn dissector() {
+        let ping = sys::Ping {}.to_message();
+        let mut bytes = vec![0x00, 0x11];
+        bytes.extend_from_slice(&[SOF, 0x00, 0x21, 0x01, 0x20]);
+        // same frame with a broken FCS
+        bytes.extend_from_slice(&[SOF, 0x00, 0x21, 0x01, 0x21]);
+        // unknown command
+        bytes.extend_from_slice(&[SOF, 0x01, 0x45, 0x7f, 0x00, 0x3b]);
+
+        let mut dissector = Dissector::new();
+        let mut events = dissector.push(&bytes[..4]);
+        events.extend(dissector.push(&bytes[4..]));
+        events.extend(dissector.push(&[SOF, 0x02]));
+        events.extend(dissector.finish());
+
+        assert_eq!(events[0], Event::Discarded(vec![0x00, 0x11]));
+        assert_eq!(events[1], Event::Frame(ping));
+        assert!(matches!(
+            events[2],
+            Event::InvalidFcs(_, InvalidFcs { expected: 0x20, actual: 0x21 })
+        ));
+        // what's left of the broken frame after resyncing
+        assert_eq!(events[3], Event::Discarded(vec![0x00, 0x21, 0x01, 0x21]));
+        assert!(describe(&events[4]).contains("unknown command"));
+        assert_eq!(events[5], Event::Discarded(vec![SOF, 0x02]));
+        assert_eq!(events.len(), 6);
+
+        assert_eq!(describe(&events[1]), "SREQ SYS 0x01\n    Ping");
+    }
+
+    #[tokio::test]
+    async fn capture() {
+        let path =
+            std::env::temp_dir().join(format!("michiru-zstack-capture-{}.log", std::process::id()));
+
+        let (transport, _) = Simulator::new()
+            .respond(|_: sys::Ping| sys::PingResponse { capabilities: 0x0659 })
+            .start();
+        let zstack = ZStack::new(Capture::create(transport, &path).unwrap());
+        zstack.request(&sys::Ping {}).await.unwrap();
+
+        // records are written in the background
+        for _ in 0..100 {
+            if read(&path).unwrap().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let events: Vec<_> = dissect(&read(&path).unwrap())
+            .into_iter()
+            .map(|(_, direction, event)| (direction, event))
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(events, [
+            (Direction::Sent, Event::Frame(sys::Ping {}.to_message())),
+            (
+                Direction::Received,
+                Event::Frame(sys::PingResponse { capabilities: 0x0659 }.to_message())
+            ),
+        ]);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    any::type_name,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::{stream::BoxStream, SinkExt, StreamExt};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{broadcast, mpsc, oneshot, Mutex},
+};
+use tokio_util::codec::Framed;
+
+use crate::{
+    codec::MtCodec,
+    commands::{Command, Request},
+    message::{CmdType, Message, Subsystem},
+};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// Async client for a Z-Stack device
+///
+/// Only one SREQ may be outstanding at a time, so requests are serialized.
+/// AREQs coming from the device are broadcast to all subscribers.
+pub struct ZStack {
+    outgoing: mpsc::Sender<Message>,
+    pending: Arc<StdMutex<Option<Pending>>>,
+    request_lock: Mutex<()>,
+    indications: broadcast::Sender<Message>,
+    timeout: Duration,
+}
+
+struct Pending {
+    subsystem: Subsystem,
+    cmd_id: u8,
+    /// Command id of the SREQ, echoed by RPC errors
+    request: [u8; 2],
+    tx: oneshot::Sender<Message>,
+}
+
+impl ZStack {
+    pub fn new<T>(transport: T) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (mut sink, mut stream) = Framed::new(transport, MtCodec).split();
+
+        let (outgoing, mut outgoing_rx) = mpsc::channel::<Message>(16);
+        let (indications, _) = broadcast::channel(64);
+        let pending = Arc::new(StdMutex::new(None::<Pending>));
+
+        tokio::spawn(async move {
+            while let Some(message) = outgoing_rx.recv().await {
+                tracing::trace!(?message, "Sending");
+
+                if let Err(e) = sink.send(message).await {
+                    tracing::error!(?e, "Failed to write to Z-Stack");
+                    break;
+                }
+            }
+        });
+
+        tokio::spawn({
+            let pending = pending.clone();
+            let indications = indications.clone();
+
+            async move {
+                while let Some(message) = stream.next().await {
+                    let message = match message {
+                        Ok(message) => message,
+                        Err(e) => {
+                            tracing::error!(?e, "Failed to read from Z-Stack");
+                            break;
+                        }
+                    };
+
+                    tracing::trace!(?message, "Received");
+
+                    match message.cmd_type() {
+                        Ok(CmdType::SyncResponse) => {
+                            let mut pending = pending.lock().unwrap();
+
+                            let matches = pending.as_ref().is_some_and(|p| {
+                                match message.rpc_error_request() {
+                                    Some(request) => request == p.request,
+                                    None => {
+                                        message.subsystem() == Ok(p.subsystem)
+                                            && message.cmd_id() == p.cmd_id
+                                    }
+                                }
+                            });
+
+                            if matches {
+                                let _ = pending.take().unwrap().tx.send(message);
+                            } else {
+                                tracing::warn!(?message, "Unexpected SRSP");
+                            }
+                        }
+                        Ok(CmdType::AsyncRequest) => {
+                            // no subscribers is fine
+                            let _ = indications.send(message);
+                        }
+                        _ => tracing::warn!(?message, "Unexpected message type"),
+                    }
+                }
+
+                // fail any request that is still waiting
+                pending.lock().unwrap().take();
+            }
+        });
+
+        Self {
+            outgoing,
+            pending,
+            request_lock: Mutex::new(()),
+            indications,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Sends a synchronous request and waits for its response
+    pub async fn request<R: Request>(&self, request: &R) -> Result<R::Response> {
+        let _guard = self.request_lock.lock().await;
+
+        let message = request.to_message();
+        let (tx, rx) = oneshot::channel();
+        *self.pending.lock().unwrap() = Some(Pending {
+            subsystem: R::SUBSYSTEM,
+            cmd_id: R::CMD_ID,
+            request: message.command_id(),
+            tx,
+        });
+
+        self.outgoing
+            .send(message)
+            .await
+            .map_err(|_| anyhow!("Z-Stack connection closed"))?;
+
+        let response = match tokio::time::timeout(self.timeout, rx).await {
+            Ok(response) => response.context("Z-Stack connection closed")?,
+            Err(_) => {
+                self.pending.lock().unwrap().take();
+                bail!("Timed out waiting for response to {}", type_name::<R>());
+            }
+        };
+
+        if response.is_rpc_error() {
+            bail!(
+                "Z-Stack rejected {} with error {:#04x}",
+                type_name::<R>(),
+                response.data().first().copied().unwrap_or_default()
+            );
+        }
+
+        R::Response::from_message(&response)
+    }
+
+    /// Sends a command without waiting for a response
+    pub async fn send<C: Command>(&self, command: &C) -> Result<()> {
+        self.outgoing
+            .send(command.to_message())
+            .await
+            .map_err(|_| anyhow!("Z-Stack connection closed"))
+    }
+
+    /// All AREQs sent by the device
+    pub fn subscribe(&self) -> broadcast::Receiver<Message> {
+        self.indications.subscribe()
+    }
+
+    /// AREQs of a single type
+    ///
+    /// The subscription starts when this is called, so it can be set up
+    /// before sending the request that triggers the indication.
+    pub fn indications<C>(&self) -> BoxStream<'static, C>
+    where
+        C: Command + Send + 'static,
+    {
+        futures::stream::unfold(self.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(message) if C::matches(&message) => match C::from_message(&message) {
+                        Ok(command) => return Some((command, rx)),
+                        Err(e) => tracing::warn!(?e, "Failed to parse indication"),
+                    },
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!(n, "Indication subscriber lagged behind");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::DuplexStream;
+
+    use super::*;
+    use crate::commands::{sys, zdo};
+
+    fn device() -> (ZStack, Framed<DuplexStream, MtCodec>) {
+        let (a, b) = tokio::io::duplex(256);
+        (ZStack::new(a), Framed::new(b, MtCodec))
+    }
+
+    #[tokio::test]
+    async fn request_response() {
+        let (zstack, mut device) = device();
+
+        let request = tokio::spawn(async move { zstack.request(&sys::Ping {}).await });
+
+        let message = device.next().await.unwrap().unwrap();
+        assert!(sys::Ping::matches(&message));
+
+        // an unrelated indication in between shouldn't confuse the client
+        device
+            .send(zdo::StateChangeInd { state: zdo::DeviceState::Coordinator }.to_message())
+            .await
+            .unwrap();
+        device
+            .send(sys::PingResponse { capabilities: 0x0659 }.to_message())
+            .await
+            .unwrap();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.capabilities, 0x0659);
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let (mut zstack, mut device) = device();
+        zstack.set_timeout(Duration::from_millis(50));
+
+        assert!(zstack.request(&sys::Version {}).await.is_err());
+        assert!(sys::Version::matches(&device.next().await.unwrap().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn rpc_error() {
+        let (mut zstack, mut device) = device();
+        zstack.set_timeout(Duration::from_millis(50));
+        let zstack = Arc::new(zstack);
+
+        let request = tokio::spawn({
+            let zstack = zstack.clone();
+            async move { zstack.request(&sys::Version {}).await }
+        });
+        let message = device.next().await.unwrap().unwrap();
+
+        // rejecting some other request is not an answer
+        device
+            .send(Message::rpc_error(0x02, [0x21, 0x01]))
+            .await
+            .unwrap();
+        device
+            .send(Message::rpc_error(0x02, message.command_id()))
+            .await
+            .unwrap();
+
+        let err = request.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("rejected"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn indications() {
+        let (zstack, mut device) = device();
+
+        let mut states = zstack.indications::<zdo::StateChangeInd>();
+
+        device
+            .send(
+                sys::ResetInd {
+                    reason: 0,
+                    transport_rev: 2,
+                    product_id: 0,
+                    major_rel: 2,
+                    minor_rel: 6,
+                    hw_rev: 3,
+                }
+                .to_message(),
+            )
+            .await
+            .unwrap();
+        device
+            .send(zdo::StateChangeInd { state: zdo::DeviceState::Coordinator }.to_message())
+            .await
+            .unwrap();
+
+        assert_eq!(states.next().await.unwrap().state, zdo::DeviceState::Coordinator);
+    }
+}
@@ -0,0 +1,178 @@
+use std::io;
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::message::{Message, MAX_DATA_LEN, SOF};
+
+/// Frames MT messages out of a byte stream
+///
+/// Bytes before a start of frame are discarded, and frames with an invalid
+/// FCS are dropped by resyncing on the next start of frame.
+#[derive(Debug, Default)]
+pub struct MtCodec;
+
+impl Decoder for MtCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, io::Error> {
+        loop {
+            match src.iter().position(|&b| b == SOF) {
+                Some(0) => {}
+                Some(n) => {
+                    tracing::warn!(skipped = ?&src[..n], "Discarding bytes before start of frame");
+                    src.advance(n);
+                }
+                None => {
+                    if !src.is_empty() {
+                        tracing::warn!(skipped = ?&src[..], "Discarding bytes without start of frame");
+                        src.clear();
+                    }
+                    return Ok(None);
+                }
+            }
+
+            if src.len() > 1 && src[1] as usize > MAX_DATA_LEN {
+                tracing::warn!(len = src[1], "Invalid frame length");
+                src.advance(1);
+                continue;
+            }
+
+            let (consumed, message) = match Message::parse(src) {
+                Ok((rest, message)) => (src.len() - rest.len(), message),
+                Err(nom::Err::Incomplete(_)) => return Ok(None),
+                Err(e) => {
+                    tracing::warn!(?e, "Failed to parse frame");
+                    src.advance(1);
+                    continue;
+                }
+            };
+
+            if message.verify().is_err() {
+                tracing::warn!(?message, "Invalid FCS");
+                src.advance(1);
+                continue;
+            }
+
+            src.advance(consumed);
+            return Ok(Some(message));
+        }
+    }
+}
+
+impl Encoder<Message> for MtCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, message: Message, dst: &mut BytesMut) -> Result<(), io::Error> {
+        self.encode(&message, dst)
+    }
+}
+
+impl Encoder<&Message> for MtCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, message: &Message, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let data = message.data();
+
+        if data.len() > MAX_DATA_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame data too long ({} bytes)", data.len()),
+            ));
+        }
+
+        dst.reserve(data.len() + 5);
+        dst.put_u8(SOF);
+        dst.put_u8(data.len() as u8);
+        dst.put_slice(&message.command_id());
+        dst.put_slice(data);
+        dst.put_u8(message.fcs());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{CmdType, Subsystem};
+
+    const PING: [u8; 5] = [0xfe, 0x00, 0x21, 0x01, 0x20];
+    const PING_RSP: [u8; 7] = [0xfe, 0x02, 0x61, 0x01, 0x59, 0x06, 0x3d];
+
+    fn decode_all(codec: &mut MtCodec, buf: &mut BytesMut) -> Vec<Message> {
+        let mut out = vec![];
+        while let Some(message) = codec.decode(buf).unwrap() {
+            out.push(message);
+        }
+        out
+    }
+
+    #[test]
+    fn encode_ping() {
+        let message = Message::new(CmdType::SyncRequest, Subsystem::Sys, 0x01, vec![]);
+
+        let mut buf = BytesMut::new();
+        MtCodec.encode(message, &mut buf).unwrap();
+
+        assert_eq!(&buf[..], &PING);
+    }
+
+    #[test]
+    fn partial_frame() {
+        let mut codec = MtCodec;
+        let mut buf = BytesMut::from(&PING_RSP[..3]);
+
+        assert!(decode_all(&mut codec, &mut buf).is_empty());
+
+        buf.extend_from_slice(&PING_RSP[3..]);
+        let messages = decode_all(&mut codec, &mut buf);
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].cmd_type(), Ok(CmdType::SyncResponse));
+        assert_eq!(messages[0].subsystem(), Ok(Subsystem::Sys));
+        assert_eq!(messages[0].data(), &[0x59, 0x06]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn concatenated_frames() {
+        let mut codec = MtCodec;
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&PING);
+        buf.extend_from_slice(&PING_RSP);
+        buf.extend_from_slice(&PING[..2]);
+
+        let messages = decode_all(&mut codec, &mut buf);
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].cmd_id(), 0x01);
+        assert_eq!(messages[1].data(), &[0x59, 0x06]);
+        assert_eq!(&buf[..], &PING[..2]);
+    }
+
+    #[test]
+    fn resync_after_garbage_and_bad_fcs() {
+        let mut codec = MtCodec;
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&[0x00, 0x12]);
+        buf.extend_from_slice(&[0xfe, 0x00, 0x21, 0x01, 0x21]);
+        buf.extend_from_slice(&PING_RSP);
+
+        let messages = decode_all(&mut codec, &mut buf);
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].data(), &[0x59, 0x06]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let message = Message::new(CmdType::AsyncRequest, Subsystem::Zdo, 0xc0, vec![0x09]);
+
+        let mut buf = BytesMut::new();
+        MtCodec.encode(&message, &mut buf).unwrap();
+
+        assert_eq!(MtCodec.decode(&mut buf).unwrap(), Some(message));
+    }
+}
@@ -0,0 +1,72 @@
+//! AF subsystem: endpoint registration and application data
+
+use super::{command, request, Status};
+
+command! {
+    /// AF_REGISTER
+    pub struct Register: SyncRequest, Af, 0x00 {
+        pub endpoint: u8,
+        pub profile_id: u16,
+        pub device_id: u16,
+        pub device_version: u8,
+        pub latency: u8,
+        pub in_clusters: Vec<u16>,
+        pub out_clusters: Vec<u16>,
+    }
+}
+
+command! {
+    pub struct RegisterResponse: SyncResponse, Af, 0x00 {
+        pub status: Status,
+    }
+}
+
+request!(Register => RegisterResponse);
+
+command! {
+    /// AF_DATA_REQUEST, confirmed with a [`DataConfirm`] once sent
+    pub struct DataRequest: SyncRequest, Af, 0x01 {
+        pub dst_addr: u16,
+        pub dst_endpoint: u8,
+        pub src_endpoint: u8,
+        pub cluster_id: u16,
+        pub trans_id: u8,
+        pub options: u8,
+        pub radius: u8,
+        pub data: Vec<u8>,
+    }
+}
+
+command! {
+    pub struct DataRequestResponse: SyncResponse, Af, 0x01 {
+        pub status: Status,
+    }
+}
+
+request!(DataRequest => DataRequestResponse);
+
+command! {
+    /// AF_DATA_CONFIRM
+    pub struct DataConfirm: AsyncRequest, Af, 0x80 {
+        pub status: Status,
+        pub endpoint: u8,
+        pub trans_id: u8,
+    }
+}
+
+command! {
+    /// AF_INCOMING_MSG
+    pub struct IncomingMsg: AsyncRequest, Af, 0x81 {
+        pub group_id: u16,
+        pub cluster_id: u16,
+        pub src_addr: u16,
+        pub src_endpoint: u8,
+        pub dst_endpoint: u8,
+        pub was_broadcast: bool,
+        pub link_quality: u8,
+        pub security_use: bool,
+        pub timestamp: u32,
+        pub trans_seq: u8,
+        pub data: Vec<u8>,
+    }
+}
@@ -0,0 +1,66 @@
+//! APP_CNF subsystem: base device behaviour (BDB) commissioning
+
+use super::{command, request, Status};
+
+pub const MODE_TOUCHLINK: u8 = 0x01;
+pub const MODE_NWK_STEERING: u8 = 0x02;
+pub const MODE_NWK_FORMATION: u8 = 0x04;
+pub const MODE_FINDING_BINDING: u8 = 0x08;
+pub const MODE_INITIALIZATION: u8 = 0x10;
+pub const MODE_PARENT_LOST: u8 = 0x20;
+
+command! {
+    /// APP_CNF_BDB_START_COMMISSIONING
+    pub struct BdbStartCommissioning: SyncRequest, AppConfig, 0x05 {
+        pub mode: u8,
+    }
+}
+
+command! {
+    pub struct BdbStartCommissioningResponse: SyncResponse, AppConfig, 0x05 {
+        pub status: Status,
+    }
+}
+
+request!(BdbStartCommissioning => BdbStartCommissioningResponse);
+
+command! {
+    /// APP_CNF_BDB_SET_CHANNEL
+    pub struct BdbSetChannel: SyncRequest, AppConfig, 0x08 {
+        pub is_primary: bool,
+        pub channel_mask: u32,
+    }
+}
+
+command! {
+    pub struct BdbSetChannelResponse: SyncResponse, AppConfig, 0x08 {
+        pub status: Status,
+    }
+}
+
+request!(BdbSetChannel => BdbSetChannelResponse);
+
+command! {
+    /// APP_CNF_BDB_SET_TC_REQUIRE_KEY_EXCHANGE
+    pub struct BdbSetTcRequireKeyExchange: SyncRequest, AppConfig, 0x09 {
+        pub required: bool,
+    }
+}
+
+command! {
+    pub struct BdbSetTcRequireKeyExchangeResponse: SyncResponse, AppConfig, 0x09 {
+        pub status: Status,
+    }
+}
+
+request!(BdbSetTcRequireKeyExchange => BdbSetTcRequireKeyExchangeResponse);
+
+command! {
+    /// APP_CNF_BDB_COMMISSIONING_NOTIFICATION
+    pub struct BdbCommissioningNotification: AsyncRequest, AppConfig, 0x80 {
+        /// 0 on success, 1 while in progress, anything else is an error
+        pub status: u8,
+        pub mode: u8,
+        pub remaining_modes: u8,
+    }
+}
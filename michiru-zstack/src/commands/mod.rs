@@ -0,0 +1,557 @@
+//! Typed MT commands
+//!
+//! Every command knows its own type, subsystem and command id, and can be
+//! converted to and from a [`Message`]. Synchronous requests are linked to
+//! their response through [`Request`].
+
+use std::{any::type_name, fmt};
+
+use anyhow::{anyhow, ensure, Result};
+use nom::{
+    bytes::complete::take,
+    multi::count,
+    number::complete::{le_u16, le_u32, le_u64, u8},
+    IResult,
+};
+
+use crate::message::{CmdType, Message, Subsystem};
+
+pub mod af;
+pub mod app_cnf;
+pub mod sys;
+pub mod util;
+pub mod zdo;
+
+pub trait Command: Sized {
+    const CMD_TYPE: CmdType;
+    const SUBSYSTEM: Subsystem;
+    const CMD_ID: u8;
+
+    fn serialize(&self, buf: &mut Vec<u8>);
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self>;
+
+    fn matches(message: &Message) -> bool {
+        message.cmd_type() == Ok(Self::CMD_TYPE)
+            && message.subsystem() == Ok(Self::SUBSYSTEM)
+            && message.cmd_id() == Self::CMD_ID
+    }
+
+    fn to_message(&self) -> Message {
+        let mut data = vec![];
+        self.serialize(&mut data);
+
+        Message::new(Self::CMD_TYPE, Self::SUBSYSTEM, Self::CMD_ID, data)
+    }
+
+    fn from_message(message: &Message) -> Result<Self> {
+        ensure!(Self::matches(message), "message is not a {}", type_name::<Self>());
+
+        // newer firmware may append fields, so trailing data is ignored
+        let (_, command) = Self::parse(message.data())
+            .map_err(|e| anyhow!("failed to parse {}: {e:?}", type_name::<Self>()))?;
+
+        Ok(command)
+    }
+}
+
+/// A synchronous request, answered by a synchronous response
+pub trait Request: Command {
+    type Response: Command;
+}
+
+/// Wire format of a single field in a command
+pub trait Field: Sized {
+    fn write(&self, buf: &mut Vec<u8>);
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self>;
+}
+
+impl Field for u8 {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.push(*self);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        u8(src)
+    }
+}
+
+impl Field for bool {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, v) = u8(src)?;
+        Ok((src, v != 0))
+    }
+}
+
+impl Field for u16 {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        le_u16(src)
+    }
+}
+
+impl Field for u32 {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        le_u32(src)
+    }
+}
+
+impl Field for u64 {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        le_u64(src)
+    }
+}
+
+impl<const N: usize> Field for [u8; N] {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, bytes) = take(N)(src)?;
+        Ok((src, bytes.try_into().unwrap()))
+    }
+}
+
+/// Lists are prefixed with a one byte element count
+impl<T: Field> Field for Vec<T> {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.push(self.len() as u8);
+        for item in self {
+            item.write(buf);
+        }
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, len) = u8(src)?;
+        count(T::parse, len as usize)(src)
+    }
+}
+
+/// Bytes prefixed with a two byte length
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LongBytes(pub Vec<u8>);
+
+impl Field for LongBytes {
+    fn write(&self, buf: &mut Vec<u8>) {
+        (self.0.len() as u16).write(buf);
+        buf.extend_from_slice(&self.0);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, len) = le_u16(src)?;
+        let (src, bytes) = take(len)(src)?;
+        Ok((src, LongBytes(bytes.to_vec())))
+    }
+}
+
+/// Optional fields may only appear at the end of a command
+impl<T: Field> Field for Option<T> {
+    fn write(&self, buf: &mut Vec<u8>) {
+        if let Some(v) = self {
+            v.write(buf);
+        }
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        if src.is_empty() {
+            return Ok((src, None));
+        }
+
+        let (src, v) = T::parse(src)?;
+        Ok((src, Some(v)))
+    }
+}
+
+/// Generic Z-Stack status code
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Status(pub u8);
+
+impl Status {
+    pub const SUCCESS: Status = Status(0x00);
+    pub const FAILURE: Status = Status(0x01);
+    pub const INVALID_PARAMETER: Status = Status(0x02);
+    pub const NV_ITEM_UNINIT: Status = Status(0x09);
+    pub const NV_OPER_FAILED: Status = Status(0x0a);
+    pub const NV_BAD_ITEM_LEN: Status = Status(0x0c);
+    pub const MEM_ERROR: Status = Status(0x10);
+    pub const BUFFER_FULL: Status = Status(0x11);
+    pub const UNSUPPORTED_MODE: Status = Status(0x12);
+    pub const MAC_MEM_ERROR: Status = Status(0x13);
+    pub const APS_NO_ACK: Status = Status(0xb7);
+    pub const NWK_NO_ROUTE: Status = Status(0xcd);
+    pub const MAC_NO_ACK: Status = Status(0xe9);
+    pub const MAC_TRANSACTION_EXPIRED: Status = Status(0xf0);
+
+    pub fn is_success(self) -> bool {
+        self == Self::SUCCESS
+    }
+
+    pub fn ok(self) -> Result<()> {
+        ensure!(self.is_success(), "command failed with status {self}");
+        Ok(())
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            Self::SUCCESS => "success",
+            Self::FAILURE => "failure",
+            Self::INVALID_PARAMETER => "invalid parameter",
+            Self::NV_ITEM_UNINIT => "NV item uninitialized",
+            Self::NV_OPER_FAILED => "NV operation failed",
+            Self::NV_BAD_ITEM_LEN => "NV bad item length",
+            Self::MEM_ERROR => "memory error",
+            Self::BUFFER_FULL => "buffer full",
+            Self::UNSUPPORTED_MODE => "unsupported mode",
+            Self::MAC_MEM_ERROR => "MAC memory error",
+            Self::APS_NO_ACK => "APS no ack",
+            Self::NWK_NO_ROUTE => "NWK no route",
+            Self::MAC_NO_ACK => "MAC no ack",
+            Self::MAC_TRANSACTION_EXPIRED => "MAC transaction expired",
+            _ => return write!(f, "{:#04x}", self.0),
+        };
+
+        write!(f, "{name} ({:#04x})", self.0)
+    }
+}
+
+impl fmt::Debug for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Status({self})")
+    }
+}
+
+impl Field for Status {
+    fn write(&self, buf: &mut Vec<u8>) {
+        self.0.write(buf);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, v) = u8(src)?;
+        Ok((src, Status(v)))
+    }
+}
+
+/// Declares a command struct along with its [`Command`] implementation
+///
+/// Fields are (de)serialized in declaration order using their [`Field`]
+/// implementation.
+macro_rules! command {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident: $cmd_type:ident, $subsystem:ident, $cmd_id:literal {
+            $(
+                $(#[$field_meta:meta])*
+                pub $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $name {
+            $(
+                $(#[$field_meta])*
+                pub $field: $ty,
+            )*
+        }
+
+        impl $crate::commands::Command for $name {
+            const CMD_TYPE: $crate::message::CmdType = $crate::message::CmdType::$cmd_type;
+            const SUBSYSTEM: $crate::message::Subsystem = $crate::message::Subsystem::$subsystem;
+            const CMD_ID: u8 = $cmd_id;
+
+            #[allow(unused_variables)]
+            fn serialize(&self, buf: &mut Vec<u8>) {
+                $( $crate::commands::Field::write(&self.$field, buf); )*
+            }
+
+            fn parse(src: &[u8]) -> nom::IResult<&[u8], Self> {
+                $( let (src, $field) = <$ty as $crate::commands::Field>::parse(src)?; )*
+                Ok((src, Self { $($field),* }))
+            }
+        }
+    };
+}
+
+/// Links a synchronous request to its response
+macro_rules! request {
+    ($request:ident => $response:ident) => {
+        impl $crate::commands::Request for $request {
+            type Response = $response;
+        }
+    };
+}
+
+pub(crate) use command;
+pub(crate) use request;
+
+/// Generates [`decode`] from the list of known commands
+macro_rules! catalogue {
+    ($($module:ident::{$($name:ident),* $(,)?}),* $(,)?) => {
+        /// Parses a message as whichever known command it is, `None` if it
+        /// isn't one
+        pub fn decode(message: &Message) -> Option<Result<Box<dyn fmt::Debug + Send>>> {
+            $($(
+                if <$module::$name as Command>::matches(message) {
+                    return Some(
+                        $module::$name::from_message(message)
+                            .map(|command| Box::new(command) as Box<dyn fmt::Debug + Send>),
+                    );
+                }
+            )*)*
+
+            None
+        }
+    };
+}
+
+catalogue! {
+    sys::{
+        ResetReq, ResetInd, Ping, PingResponse, Version, VersionResponse,
+        OsalNvItemInit, OsalNvItemInitResponse, OsalNvRead, OsalNvReadResponse,
+        OsalNvWrite, OsalNvWriteResponse, OsalNvLength, OsalNvLengthResponse,
+        OsalNvReadExt, OsalNvReadExtResponse, OsalNvWriteExt, OsalNvWriteExtResponse,
+        NvCreate, NvCreateResponse, NvLength, NvLengthResponse, NvRead, NvReadResponse,
+        NvWrite, NvWriteResponse,
+    },
+    util::{GetDeviceInfo, GetDeviceInfoResponse},
+    af::{Register, RegisterResponse, DataRequest, DataRequestResponse, DataConfirm, IncomingMsg},
+    zdo::{
+        NodeDescReq, NodeDescReqResponse, SimpleDescReq, SimpleDescReqResponse,
+        ActiveEpReq, ActiveEpReqResponse, BindReq, BindReqResponse, MgmtLqiReq,
+        MgmtLqiReqResponse, MgmtRtgReq, MgmtRtgReqResponse, MgmtLeaveReq, MgmtLeaveReqResponse,
+        MgmtPermitJoinReq, MgmtPermitJoinReqResponse, StartupFromApp, StartupFromAppResponse,
+        StateChangeInd, EndDeviceAnnceInd, NodeDescRsp, SimpleDescRsp, ActiveEpRsp, BindRsp,
+        MgmtLqiRsp, MgmtRtgRsp, MgmtLeaveRsp, MgmtPermitJoinRsp,
+    },
+    app_cnf::{
+        BdbStartCommissioning, BdbStartCommissioningResponse, BdbSetChannel,
+        BdbSetChannelResponse, BdbSetTcRequireKeyExchange, BdbSetTcRequireKeyExchangeResponse,
+        BdbCommissioningNotification,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(cmd_type: CmdType, subsystem: Subsystem, cmd_id: u8, data: &[u8]) -> Message {
+        Message::new(cmd_type, subsystem, cmd_id, data.to_vec())
+    }
+
+    #[test]
+    fn sys_ping() {
+        assert_eq!(sys::Ping {}.to_message().data(), &[] as &[u8]);
+
+        let rsp = message(CmdType::SyncResponse, Subsystem::Sys, 0x01, &[0x59, 0x06]);
+        assert_eq!(sys::PingResponse::from_message(&rsp).unwrap(), sys::PingResponse {
+            capabilities: 0x0659,
+        });
+        assert!(sys::VersionResponse::from_message(&rsp).is_err());
+    }
+
+    #[test]
+    fn sys_version() {
+        let data = [0x02, 0x00, 0x02, 0x06, 0x03];
+        let rsp = message(CmdType::SyncResponse, Subsystem::Sys, 0x02, &data);
+        assert_eq!(sys::VersionResponse::from_message(&rsp).unwrap().revision, None);
+
+        let data = [0x02, 0x01, 0x02, 0x07, 0x01, 0xd9, 0x14, 0x34, 0x01];
+        let rsp = message(CmdType::SyncResponse, Subsystem::Sys, 0x02, &data);
+        assert_eq!(sys::VersionResponse::from_message(&rsp).unwrap(), sys::VersionResponse {
+            transport_rev: 2,
+            product: 1,
+            major_rel: 2,
+            minor_rel: 7,
+            maint_rel: 1,
+            revision: Some(20190425),
+        });
+    }
+
+    #[test]
+    fn sys_nv() {
+        let req = sys::OsalNvRead { id: 0x0083, offset: 0 }.to_message();
+        assert_eq!(req.command_id(), [0x21, 0x08]);
+        assert_eq!(req.data(), &[0x83, 0x00, 0x00]);
+
+        let rsp = message(CmdType::SyncResponse, Subsystem::Sys, 0x08, &[0x00, 0x02, 0x62, 0x1a]);
+        assert_eq!(sys::OsalNvReadResponse::from_message(&rsp).unwrap(), sys::OsalNvReadResponse {
+            status: Status::SUCCESS,
+            value: vec![0x62, 0x1a],
+        });
+
+        let req = sys::OsalNvWrite {
+            id: 0x0087,
+            offset: 0,
+            value: vec![0x00],
+        }
+        .to_message();
+        assert_eq!(req.data(), &[0x87, 0x00, 0x00, 0x01, 0x00]);
+
+        let truncated = message(CmdType::SyncResponse, Subsystem::Sys, 0x08, &[0x00, 0x02, 0x62]);
+        assert!(sys::OsalNvReadResponse::from_message(&truncated).is_err());
+    }
+
+    #[test]
+    fn af_data_request() {
+        let req = af::DataRequest {
+            dst_addr: 0x1234,
+            dst_endpoint: 1,
+            src_endpoint: 1,
+            cluster_id: 0x0006,
+            trans_id: 7,
+            options: 0,
+            radius: 30,
+            data: vec![0x01, 0x02, 0x01],
+        };
+
+        let message = req.to_message();
+        assert_eq!(message.command_id(), [0x24, 0x01]);
+        assert_eq!(message.data(), &[
+            0x34, 0x12, 0x01, 0x01, 0x06, 0x00, 0x07, 0x00, 0x1e, 0x03, 0x01, 0x02, 0x01
+        ]);
+        assert_eq!(af::DataRequest::from_message(&message).unwrap(), req);
+    }
+
+    #[test]
+    fn af_incoming_msg() {
+        let data = [
+            0x00, 0x00, 0x02, 0x04, 0x3e, 0xa5, 0x01, 0x01, 0x00, 0x73, 0x00, 0x10, 0x27, 0x00,
+            0x00, 0x2a, 0x05, 0x18, 0x01, 0x0a, 0x00, 0x00, // trailing fields
+            0x3e, 0xa5, 0x1d,
+        ];
+        let ind = message(CmdType::AsyncRequest, Subsystem::Af, 0x81, &data);
+
+        let msg = af::IncomingMsg::from_message(&ind).unwrap();
+        assert_eq!(msg.cluster_id, 0x0402);
+        assert_eq!(msg.src_addr, 0xa53e);
+        assert_eq!(msg.link_quality, 0x73);
+        assert_eq!(msg.timestamp, 10000);
+        assert_eq!(msg.trans_seq, 0x2a);
+        assert_eq!(msg.data, vec![0x18, 0x01, 0x0a, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn util_get_device_info() {
+        let data = [
+            0x00, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00, 0x00, 0x07, 0x09, 0x02,
+            0x3e, 0xa5, 0x01, 0x00,
+        ];
+        let rsp = message(CmdType::SyncResponse, Subsystem::Util, 0x00, &data);
+
+        let info = util::GetDeviceInfoResponse::from_message(&rsp).unwrap();
+        assert_eq!(info.ieee_addr, 0x1122334455667788);
+        assert_eq!(info.short_addr, 0x0000);
+        assert_eq!(info.device_state, zdo::DeviceState::Coordinator);
+        assert_eq!(info.assoc_devices, vec![0xa53e, 0x0001]);
+    }
+
+    #[test]
+    fn zdo_and_app_cnf() {
+        let ind = message(CmdType::AsyncRequest, Subsystem::Zdo, 0xc0, &[0x09]);
+        assert_eq!(
+            zdo::StateChangeInd::from_message(&ind).unwrap().state,
+            zdo::DeviceState::Coordinator
+        );
+
+        let req = app_cnf::BdbSetChannel {
+            is_primary: true,
+            channel_mask: 1 << 11,
+        }
+        .to_message();
+        assert_eq!(req.command_id(), [0x2f, 0x08]);
+        assert_eq!(req.data(), &[0x01, 0x00, 0x08, 0x00, 0x00]);
+
+        let req = zdo::StartupFromApp { start_delay: 100 }.to_message();
+        assert_eq!(req.command_id(), [0x25, 0x40]);
+        assert_eq!(req.data(), &[0x64, 0x00]);
+    }
+
+    #[test]
+    fn zdo_descriptors() {
+        let data = [
+            0x3e, 0xa5, 0x00, 0x3e, 0xa5, 0x02, 0x40, 0x80, 0x7c, 0x11, 0x52, 0x52, 0x00, 0x00,
+            0x2c, 0x52, 0x00, 0x00,
+        ];
+        let rsp = message(CmdType::AsyncRequest, Subsystem::Zdo, 0x82, &data);
+        let descriptor = zdo::NodeDescRsp::from_message(&rsp)
+            .unwrap()
+            .descriptor
+            .unwrap();
+        assert_eq!(descriptor.logical_type(), zdo::LogicalType::EndDevice);
+        assert_eq!(descriptor.manufacturer_code, 0x117c);
+
+        let data = [
+            0x3e, 0xa5, 0x00, 0x3e, 0xa5, 0x0e, 0x01, 0x04, 0x01, 0x02, 0x03, 0x01, 0x03, 0x00,
+            0x00, 0x02, 0x04, 0x01, 0x00, 0x01, 0x19, 0x00,
+        ];
+        let rsp = message(CmdType::AsyncRequest, Subsystem::Zdo, 0x84, &data);
+        assert_eq!(
+            zdo::SimpleDescRsp::from_message(&rsp).unwrap().descriptor,
+            Some(zdo::SimpleDescriptor {
+                endpoint: 1,
+                profile_id: 0x0104,
+                device_id: 0x0302,
+                device_version: 1,
+                in_clusters: vec![0x0000, 0x0402, 0x0001],
+                out_clusters: vec![0x0019],
+            })
+        );
+
+        let failed = [0x3e, 0xa5, 0x83, 0x3e, 0xa5, 0x00];
+        let rsp = message(CmdType::AsyncRequest, Subsystem::Zdo, 0x84, &failed);
+        assert_eq!(zdo::SimpleDescRsp::from_message(&rsp).unwrap().descriptor, None);
+
+        let req = zdo::BindReq {
+            dst_addr: 0xa53e,
+            src_address: 0x00158d0001020304,
+            src_endpoint: 1,
+            cluster_id: 0x0402,
+            dst_addr_mode: zdo::ADDR_MODE_IEEE,
+            dst_address: 0x00124b0001abcdef,
+            dst_endpoint: 1,
+        }
+        .to_message();
+        assert_eq!(req.command_id(), [0x25, 0x21]);
+        assert_eq!(req.data(), &[
+            0x3e, 0xa5, 0x04, 0x03, 0x02, 0x01, 0x00, 0x8d, 0x15, 0x00, 0x01, 0x02, 0x04, 0x03,
+            0xef, 0xcd, 0xab, 0x01, 0x00, 0x4b, 0x12, 0x00, 0x01
+        ]);
+
+        let data = [
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x01, // one of two neighbors
+            0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0xdd, 0x04, 0x03, 0x02, 0x01, 0x00, 0x8d,
+            0x15, 0x00, 0x3e, 0xa5, 0x16, 0x02, 0x01, 0x73,
+        ];
+        let rsp = message(CmdType::AsyncRequest, Subsystem::Zdo, 0xb1, &data);
+        let rsp = zdo::MgmtLqiRsp::from_message(&rsp).unwrap();
+        assert_eq!(rsp.neighbor_table_entries, 2);
+        assert_eq!(rsp.neighbors[0].ieee_addr, 0x00158d0001020304);
+        assert_eq!(rsp.neighbors[0].nwk_addr, 0xa53e);
+        assert_eq!(rsp.neighbors[0].logical_type(), zdo::LogicalType::EndDevice);
+        assert_eq!(rsp.neighbors[0].relationship(), 1);
+        assert_eq!(rsp.neighbors[0].lqi, 0x73);
+
+        let data = [0x3e, 0xa5, 0x00, 0x3e, 0xa5, 0x02, 0x01, 0xf2];
+        let rsp = message(CmdType::AsyncRequest, Subsystem::Zdo, 0x85, &data);
+        assert_eq!(zdo::ActiveEpRsp::from_message(&rsp).unwrap().active_eps, vec![1, 0xf2]);
+    }
+}
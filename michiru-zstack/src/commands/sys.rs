@@ -0,0 +1,236 @@
+//! SYS subsystem: reset, version and non-volatile memory access
+
+use super::{command, request, LongBytes, Status};
+
+pub const RESET_HARD: u8 = 0x00;
+pub const RESET_SOFT: u8 = 0x01;
+
+command! {
+    /// SYS_RESET_REQ, answered by a [`ResetInd`] once the device is back up
+    pub struct ResetReq: AsyncRequest, Sys, 0x00 {
+        pub reset_type: u8,
+    }
+}
+
+command! {
+    /// SYS_RESET_IND
+    pub struct ResetInd: AsyncRequest, Sys, 0x80 {
+        pub reason: u8,
+        pub transport_rev: u8,
+        pub product_id: u8,
+        pub major_rel: u8,
+        pub minor_rel: u8,
+        pub hw_rev: u8,
+    }
+}
+
+command! {
+    /// SYS_PING
+    pub struct Ping: SyncRequest, Sys, 0x01 {}
+}
+
+command! {
+    pub struct PingResponse: SyncResponse, Sys, 0x01 {
+        /// Bitmask of the supported subsystems
+        pub capabilities: u16,
+    }
+}
+
+request!(Ping => PingResponse);
+
+command! {
+    /// SYS_VERSION
+    pub struct Version: SyncRequest, Sys, 0x02 {}
+}
+
+command! {
+    pub struct VersionResponse: SyncResponse, Sys, 0x02 {
+        pub transport_rev: u8,
+        pub product: u8,
+        pub major_rel: u8,
+        pub minor_rel: u8,
+        pub maint_rel: u8,
+        /// Firmware build date, only sent by Z-Stack 3.x
+        pub revision: Option<u32>,
+    }
+}
+
+request!(Version => VersionResponse);
+
+command! {
+    /// SYS_OSAL_NV_ITEM_INIT
+    pub struct OsalNvItemInit: SyncRequest, Sys, 0x07 {
+        pub id: u16,
+        pub item_len: u16,
+        pub init_data: Vec<u8>,
+    }
+}
+
+command! {
+    pub struct OsalNvItemInitResponse: SyncResponse, Sys, 0x07 {
+        /// [`Status::SUCCESS`] if the item already existed, [`Status::NV_ITEM_UNINIT`] if it
+        /// was created
+        pub status: Status,
+    }
+}
+
+request!(OsalNvItemInit => OsalNvItemInitResponse);
+
+command! {
+    /// SYS_OSAL_NV_READ
+    pub struct OsalNvRead: SyncRequest, Sys, 0x08 {
+        pub id: u16,
+        pub offset: u8,
+    }
+}
+
+command! {
+    pub struct OsalNvReadResponse: SyncResponse, Sys, 0x08 {
+        pub status: Status,
+        pub value: Vec<u8>,
+    }
+}
+
+request!(OsalNvRead => OsalNvReadResponse);
+
+command! {
+    /// SYS_OSAL_NV_WRITE
+    pub struct OsalNvWrite: SyncRequest, Sys, 0x09 {
+        pub id: u16,
+        pub offset: u8,
+        pub value: Vec<u8>,
+    }
+}
+
+command! {
+    pub struct OsalNvWriteResponse: SyncResponse, Sys, 0x09 {
+        pub status: Status,
+    }
+}
+
+request!(OsalNvWrite => OsalNvWriteResponse);
+
+command! {
+    /// SYS_OSAL_NV_LENGTH
+    pub struct OsalNvLength: SyncRequest, Sys, 0x13 {
+        pub id: u16,
+    }
+}
+
+command! {
+    pub struct OsalNvLengthResponse: SyncResponse, Sys, 0x13 {
+        /// Zero if the item doesn't exist
+        pub length: u16,
+    }
+}
+
+request!(OsalNvLength => OsalNvLengthResponse);
+
+command! {
+    /// SYS_OSAL_NV_READ_EXT, [`OsalNvRead`] with a 16-bit offset for long items
+    pub struct OsalNvReadExt: SyncRequest, Sys, 0x1c {
+        pub id: u16,
+        pub offset: u16,
+    }
+}
+
+command! {
+    pub struct OsalNvReadExtResponse: SyncResponse, Sys, 0x1c {
+        pub status: Status,
+        pub value: Vec<u8>,
+    }
+}
+
+request!(OsalNvReadExt => OsalNvReadExtResponse);
+
+command! {
+    /// SYS_OSAL_NV_WRITE_EXT, [`OsalNvWrite`] with a 16-bit offset for long items
+    pub struct OsalNvWriteExt: SyncRequest, Sys, 0x1d {
+        pub id: u16,
+        pub offset: u16,
+        pub value: LongBytes,
+    }
+}
+
+command! {
+    pub struct OsalNvWriteExtResponse: SyncResponse, Sys, 0x1d {
+        pub status: Status,
+    }
+}
+
+request!(OsalNvWriteExt => OsalNvWriteExtResponse);
+
+command! {
+    /// SYS_NV_CREATE, extended NV items of Z-Stack 3.x on CC13xx/CC26xx
+    pub struct NvCreate: SyncRequest, Sys, 0x30 {
+        pub sys_id: u8,
+        pub item_id: u16,
+        pub sub_id: u16,
+        pub length: u32,
+    }
+}
+
+command! {
+    pub struct NvCreateResponse: SyncResponse, Sys, 0x30 {
+        pub status: Status,
+    }
+}
+
+request!(NvCreate => NvCreateResponse);
+
+command! {
+    /// SYS_NV_LENGTH
+    pub struct NvLength: SyncRequest, Sys, 0x32 {
+        pub sys_id: u8,
+        pub item_id: u16,
+        pub sub_id: u16,
+    }
+}
+
+command! {
+    pub struct NvLengthResponse: SyncResponse, Sys, 0x32 {
+        /// Zero if the item doesn't exist
+        pub length: u32,
+    }
+}
+
+request!(NvLength => NvLengthResponse);
+
+command! {
+    /// SYS_NV_READ
+    pub struct NvRead: SyncRequest, Sys, 0x33 {
+        pub sys_id: u8,
+        pub item_id: u16,
+        pub sub_id: u16,
+        pub offset: u16,
+        pub length: u8,
+    }
+}
+
+command! {
+    pub struct NvReadResponse: SyncResponse, Sys, 0x33 {
+        pub status: Status,
+        pub value: Vec<u8>,
+    }
+}
+
+request!(NvRead => NvReadResponse);
+
+command! {
+    /// SYS_NV_WRITE
+    pub struct NvWrite: SyncRequest, Sys, 0x34 {
+        pub sys_id: u8,
+        pub item_id: u16,
+        pub sub_id: u16,
+        pub offset: u16,
+        pub value: Vec<u8>,
+    }
+}
+
+command! {
+    pub struct NvWriteResponse: SyncResponse, Sys, 0x34 {
+        pub status: Status,
+    }
+}
+
+request!(NvWrite => NvWriteResponse);
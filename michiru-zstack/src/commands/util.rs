@@ -0,0 +1,22 @@
+//! UTIL subsystem
+
+use super::{command, request, zdo::DeviceState, Status};
+
+command! {
+    /// UTIL_GET_DEVICE_INFO
+    pub struct GetDeviceInfo: SyncRequest, Util, 0x00 {}
+}
+
+command! {
+    pub struct GetDeviceInfoResponse: SyncResponse, Util, 0x00 {
+        pub status: Status,
+        pub ieee_addr: u64,
+        pub short_addr: u16,
+        /// Bitmask of the device types the firmware supports
+        pub device_type: u8,
+        pub device_state: DeviceState,
+        pub assoc_devices: Vec<u16>,
+    }
+}
+
+request!(GetDeviceInfo => GetDeviceInfoResponse);
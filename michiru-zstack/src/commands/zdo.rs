@@ -0,0 +1,539 @@
+//! ZDO subsystem: network startup, device state, announcements and
+//! descriptor discovery
+//!
+//! Requests to remote devices are acknowledged with a [`Status`] SRSP, the
+//! actual answer arrives later as an AREQ carrying the source address.
+
+use nom::{
+    number::complete::{le_u16, u8},
+    IResult,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{command, request, Field, Status};
+
+command! {
+    /// ZDO_NODE_DESC_REQ, answered with a [`NodeDescRsp`]
+    pub struct NodeDescReq: SyncRequest, Zdo, 0x02 {
+        pub dst_addr: u16,
+        pub nwk_addr_of_interest: u16,
+    }
+}
+
+command! {
+    pub struct NodeDescReqResponse: SyncResponse, Zdo, 0x02 {
+        pub status: Status,
+    }
+}
+
+request!(NodeDescReq => NodeDescReqResponse);
+
+command! {
+    /// ZDO_SIMPLE_DESC_REQ, answered with a [`SimpleDescRsp`]
+    pub struct SimpleDescReq: SyncRequest, Zdo, 0x04 {
+        pub dst_addr: u16,
+        pub nwk_addr_of_interest: u16,
+        pub endpoint: u8,
+    }
+}
+
+command! {
+    pub struct SimpleDescReqResponse: SyncResponse, Zdo, 0x04 {
+        pub status: Status,
+    }
+}
+
+request!(SimpleDescReq => SimpleDescReqResponse);
+
+command! {
+    /// ZDO_ACTIVE_EP_REQ, answered with an [`ActiveEpRsp`]
+    pub struct ActiveEpReq: SyncRequest, Zdo, 0x05 {
+        pub dst_addr: u16,
+        pub nwk_addr_of_interest: u16,
+    }
+}
+
+command! {
+    pub struct ActiveEpReqResponse: SyncResponse, Zdo, 0x05 {
+        pub status: Status,
+    }
+}
+
+request!(ActiveEpReq => ActiveEpReqResponse);
+
+command! {
+    /// ZDO_BIND_REQ, answered with a [`BindRsp`]
+    pub struct BindReq: SyncRequest, Zdo, 0x21 {
+        /// Device holding the binding table
+        pub dst_addr: u16,
+        pub src_address: u64,
+        pub src_endpoint: u8,
+        pub cluster_id: u16,
+        pub dst_addr_mode: u8,
+        /// Always eight bytes, group addresses only use the first two
+        pub dst_address: u64,
+        pub dst_endpoint: u8,
+    }
+}
+
+command! {
+    pub struct BindReqResponse: SyncResponse, Zdo, 0x21 {
+        pub status: Status,
+    }
+}
+
+request!(BindReq => BindReqResponse);
+
+command! {
+    /// ZDO_MGMT_LQI_REQ, answered with a [`MgmtLqiRsp`]
+    pub struct MgmtLqiReq: SyncRequest, Zdo, 0x31 {
+        pub dst_addr: u16,
+        pub start_index: u8,
+    }
+}
+
+command! {
+    pub struct MgmtLqiReqResponse: SyncResponse, Zdo, 0x31 {
+        pub status: Status,
+    }
+}
+
+request!(MgmtLqiReq => MgmtLqiReqResponse);
+
+command! {
+    /// ZDO_MGMT_RTG_REQ, answered with a [`MgmtRtgRsp`]
+    pub struct MgmtRtgReq: SyncRequest, Zdo, 0x32 {
+        pub dst_addr: u16,
+        pub start_index: u8,
+    }
+}
+
+command! {
+    pub struct MgmtRtgReqResponse: SyncResponse, Zdo, 0x32 {
+        pub status: Status,
+    }
+}
+
+request!(MgmtRtgReq => MgmtRtgReqResponse);
+
+command! {
+    /// ZDO_MGMT_LEAVE_REQ, answered with a [`MgmtLeaveRsp`]
+    pub struct MgmtLeaveReq: SyncRequest, Zdo, 0x34 {
+        pub dst_addr: u16,
+        pub device_address: u64,
+        /// Bit 0: rejoin, bit 1: remove children
+        pub remove_children_rejoin: u8,
+    }
+}
+
+command! {
+    pub struct MgmtLeaveReqResponse: SyncResponse, Zdo, 0x34 {
+        pub status: Status,
+    }
+}
+
+request!(MgmtLeaveReq => MgmtLeaveReqResponse);
+
+command! {
+    /// ZDO_MGMT_PERMIT_JOIN_REQ
+    pub struct MgmtPermitJoinReq: SyncRequest, Zdo, 0x36 {
+        pub addr_mode: u8,
+        pub dst_addr: u16,
+        /// In seconds, 0 closes the network and 0xff opens it indefinitely
+        pub duration: u8,
+        pub tc_significance: u8,
+    }
+}
+
+command! {
+    pub struct MgmtPermitJoinReqResponse: SyncResponse, Zdo, 0x36 {
+        pub status: Status,
+    }
+}
+
+request!(MgmtPermitJoinReq => MgmtPermitJoinReqResponse);
+
+command! {
+    /// ZDO_STARTUP_FROM_APP
+    pub struct StartupFromApp: SyncRequest, Zdo, 0x40 {
+        /// Delay in milliseconds
+        pub start_delay: u16,
+    }
+}
+
+command! {
+    pub struct StartupFromAppResponse: SyncResponse, Zdo, 0x40 {
+        /// 0 if the network was restored, 1 for a new network, 2 if leaving and not started
+        pub status: u8,
+    }
+}
+
+request!(StartupFromApp => StartupFromAppResponse);
+
+command! {
+    /// ZDO_STATE_CHANGE_IND
+    pub struct StateChangeInd: AsyncRequest, Zdo, 0xc0 {
+        pub state: DeviceState,
+    }
+}
+
+command! {
+    /// ZDO_END_DEVICE_ANNCE_IND, sent when a device joins or rejoins the network
+    pub struct EndDeviceAnnceInd: AsyncRequest, Zdo, 0xc1 {
+        pub src_addr: u16,
+        pub nwk_addr: u16,
+        pub ieee_addr: u64,
+        pub capabilities: u8,
+    }
+}
+
+command! {
+    /// ZDO_NODE_DESC_RSP
+    pub struct NodeDescRsp: AsyncRequest, Zdo, 0x82 {
+        pub src_addr: u16,
+        pub status: Status,
+        pub nwk_addr: u16,
+        /// Only present on success
+        pub descriptor: Option<NodeDescriptor>,
+    }
+}
+
+command! {
+    /// ZDO_SIMPLE_DESC_RSP
+    pub struct SimpleDescRsp: AsyncRequest, Zdo, 0x84 {
+        pub src_addr: u16,
+        pub status: Status,
+        pub nwk_addr: u16,
+        /// Length of the descriptor, 0 on failure
+        pub len: u8,
+        pub descriptor: Option<SimpleDescriptor>,
+    }
+}
+
+command! {
+    /// ZDO_ACTIVE_EP_RSP
+    pub struct ActiveEpRsp: AsyncRequest, Zdo, 0x85 {
+        pub src_addr: u16,
+        pub status: Status,
+        pub nwk_addr: u16,
+        pub active_eps: Vec<u8>,
+    }
+}
+
+command! {
+    /// ZDO_BIND_RSP
+    pub struct BindRsp: AsyncRequest, Zdo, 0xa1 {
+        pub src_addr: u16,
+        pub status: Status,
+    }
+}
+
+command! {
+    /// ZDO_MGMT_LQI_RSP, one page of the neighbor table
+    pub struct MgmtLqiRsp: AsyncRequest, Zdo, 0xb1 {
+        pub src_addr: u16,
+        pub status: Status,
+        pub neighbor_table_entries: u8,
+        pub start_index: u8,
+        pub neighbors: Vec<Neighbor>,
+    }
+}
+
+command! {
+    /// ZDO_MGMT_RTG_RSP, one page of the routing table
+    pub struct MgmtRtgRsp: AsyncRequest, Zdo, 0xb2 {
+        pub src_addr: u16,
+        pub status: Status,
+        pub routing_table_entries: u8,
+        pub start_index: u8,
+        pub routes: Vec<Route>,
+    }
+}
+
+command! {
+    /// ZDO_MGMT_LEAVE_RSP
+    pub struct MgmtLeaveRsp: AsyncRequest, Zdo, 0xb4 {
+        pub src_addr: u16,
+        pub status: Status,
+    }
+}
+
+command! {
+    /// ZDO_MGMT_PERMIT_JOIN_RSP
+    pub struct MgmtPermitJoinRsp: AsyncRequest, Zdo, 0xb6 {
+        pub src_addr: u16,
+        pub status: Status,
+    }
+}
+
+pub const ADDR_MODE_GROUP: u8 = 0x01;
+pub const ADDR_MODE_SHORT: u8 = 0x02;
+pub const ADDR_MODE_IEEE: u8 = 0x03;
+pub const ADDR_MODE_BROADCAST: u8 = 0x0f;
+
+/// All routers and the coordinator
+pub const BROADCAST_ROUTERS: u16 = 0xfffc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbor {
+    pub extended_pan_id: u64,
+    pub ieee_addr: u64,
+    pub nwk_addr: u16,
+    /// Device type in bits 0-1, receiver on when idle in bits 2-3 and the
+    /// relationship in bits 4-6
+    pub flags: u8,
+    pub permit_joining: u8,
+    pub depth: u8,
+    pub lqi: u8,
+}
+
+impl Neighbor {
+    pub fn logical_type(&self) -> LogicalType {
+        match self.flags & 0x03 {
+            0 => LogicalType::Coordinator,
+            1 => LogicalType::Router,
+            2 => LogicalType::EndDevice,
+            other => LogicalType::Other(other),
+        }
+    }
+
+    /// 0 = parent, 1 = child, 2 = sibling, 3 = none, 4 = previous child
+    pub fn relationship(&self) -> u8 {
+        (self.flags >> 4) & 0x07
+    }
+}
+
+impl Field for Neighbor {
+    fn write(&self, buf: &mut Vec<u8>) {
+        self.extended_pan_id.write(buf);
+        self.ieee_addr.write(buf);
+        self.nwk_addr.write(buf);
+        self.flags.write(buf);
+        self.permit_joining.write(buf);
+        self.depth.write(buf);
+        self.lqi.write(buf);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, extended_pan_id) = u64::parse(src)?;
+        let (src, ieee_addr) = u64::parse(src)?;
+        let (src, nwk_addr) = le_u16(src)?;
+        let (src, flags) = u8(src)?;
+        let (src, permit_joining) = u8(src)?;
+        let (src, depth) = u8(src)?;
+        let (src, lqi) = u8(src)?;
+
+        Ok((src, Self {
+            extended_pan_id,
+            ieee_addr,
+            nwk_addr,
+            flags,
+            permit_joining,
+            depth,
+            lqi,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub dst_addr: u16,
+    /// 0 = active, 1 = discovery underway, 2 = discovery failed, 3 = inactive
+    pub status: u8,
+    pub next_hop: u16,
+}
+
+impl Field for Route {
+    fn write(&self, buf: &mut Vec<u8>) {
+        self.dst_addr.write(buf);
+        self.status.write(buf);
+        self.next_hop.write(buf);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, dst_addr) = le_u16(src)?;
+        let (src, status) = u8(src)?;
+        let (src, next_hop) = le_u16(src)?;
+
+        Ok((src, Self { dst_addr, status, next_hop }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogicalType {
+    Coordinator,
+    Router,
+    EndDevice,
+    Other(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDescriptor {
+    /// Logical type in the low three bits
+    pub flags: u8,
+    pub aps_flags: u8,
+    pub mac_capabilities: u8,
+    pub manufacturer_code: u16,
+    pub max_buffer_size: u8,
+    pub max_in_transfer_size: u16,
+    pub server_mask: u16,
+    pub max_out_transfer_size: u16,
+    pub descriptor_capabilities: u8,
+}
+
+impl NodeDescriptor {
+    pub const CAPABILITY_MAINS_POWERED: u8 = 0x04;
+    pub const CAPABILITY_RX_ON_WHEN_IDLE: u8 = 0x08;
+
+    pub fn logical_type(&self) -> LogicalType {
+        match self.flags & 0x07 {
+            0 => LogicalType::Coordinator,
+            1 => LogicalType::Router,
+            2 => LogicalType::EndDevice,
+            other => LogicalType::Other(other),
+        }
+    }
+}
+
+impl Field for NodeDescriptor {
+    fn write(&self, buf: &mut Vec<u8>) {
+        self.flags.write(buf);
+        self.aps_flags.write(buf);
+        self.mac_capabilities.write(buf);
+        self.manufacturer_code.write(buf);
+        self.max_buffer_size.write(buf);
+        self.max_in_transfer_size.write(buf);
+        self.server_mask.write(buf);
+        self.max_out_transfer_size.write(buf);
+        self.descriptor_capabilities.write(buf);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, flags) = u8(src)?;
+        let (src, aps_flags) = u8(src)?;
+        let (src, mac_capabilities) = u8(src)?;
+        let (src, manufacturer_code) = le_u16(src)?;
+        let (src, max_buffer_size) = u8(src)?;
+        let (src, max_in_transfer_size) = le_u16(src)?;
+        let (src, server_mask) = le_u16(src)?;
+        let (src, max_out_transfer_size) = le_u16(src)?;
+        let (src, descriptor_capabilities) = u8(src)?;
+
+        Ok((src, Self {
+            flags,
+            aps_flags,
+            mac_capabilities,
+            manufacturer_code,
+            max_buffer_size,
+            max_in_transfer_size,
+            server_mask,
+            max_out_transfer_size,
+            descriptor_capabilities,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleDescriptor {
+    pub endpoint: u8,
+    pub profile_id: u16,
+    pub device_id: u16,
+    pub device_version: u8,
+    pub in_clusters: Vec<u16>,
+    pub out_clusters: Vec<u16>,
+}
+
+impl Field for SimpleDescriptor {
+    fn write(&self, buf: &mut Vec<u8>) {
+        self.endpoint.write(buf);
+        self.profile_id.write(buf);
+        self.device_id.write(buf);
+        self.device_version.write(buf);
+        self.in_clusters.write(buf);
+        self.out_clusters.write(buf);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, endpoint) = u8(src)?;
+        let (src, profile_id) = le_u16(src)?;
+        let (src, device_id) = le_u16(src)?;
+        let (src, device_version) = u8(src)?;
+        let (src, in_clusters) = Vec::parse(src)?;
+        let (src, out_clusters) = Vec::parse(src)?;
+
+        Ok((src, Self {
+            endpoint,
+            profile_id,
+            device_id,
+            device_version,
+            in_clusters,
+            out_clusters,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceState {
+    Hold,
+    Init,
+    NwkDiscovering,
+    NwkJoining,
+    NwkRejoining,
+    EndDeviceUnauthenticated,
+    EndDevice,
+    Router,
+    CoordinatorStarting,
+    Coordinator,
+    NwkOrphan,
+    Other(u8),
+}
+
+impl From<u8> for DeviceState {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => DeviceState::Hold,
+            0x01 => DeviceState::Init,
+            0x02 => DeviceState::NwkDiscovering,
+            0x03 => DeviceState::NwkJoining,
+            0x04 => DeviceState::NwkRejoining,
+            0x05 => DeviceState::EndDeviceUnauthenticated,
+            0x06 => DeviceState::EndDevice,
+            0x07 => DeviceState::Router,
+            0x08 => DeviceState::CoordinatorStarting,
+            0x09 => DeviceState::Coordinator,
+            0x0a => DeviceState::NwkOrphan,
+            other => DeviceState::Other(other),
+        }
+    }
+}
+
+impl From<DeviceState> for u8 {
+    fn from(value: DeviceState) -> Self {
+        match value {
+            DeviceState::Hold => 0x00,
+            DeviceState::Init => 0x01,
+            DeviceState::NwkDiscovering => 0x02,
+            DeviceState::NwkJoining => 0x03,
+            DeviceState::NwkRejoining => 0x04,
+            DeviceState::EndDeviceUnauthenticated => 0x05,
+            DeviceState::EndDevice => 0x06,
+            DeviceState::Router => 0x07,
+            DeviceState::CoordinatorStarting => 0x08,
+            DeviceState::Coordinator => 0x09,
+            DeviceState::NwkOrphan => 0x0a,
+            DeviceState::Other(other) => other,
+        }
+    }
+}
+
+impl Field for DeviceState {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.push((*self).into());
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, v) = u8(src)?;
+        Ok((src, v.into()))
+    }
+}
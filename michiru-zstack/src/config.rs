@@ -0,0 +1,59 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use michiru_device::MqttConfig;
+use michiru_zstack::{
+    coordinator::NetworkConfig,
+    reporting::Reporting,
+    transport::{SerialConfig, TcpConfig, TransportConfig},
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub serial: SerialConfig,
+    /// Connect to a device shared over TCP instead of the serial port
+    pub tcp: Option<TcpConfig>,
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    /// Per-model settings, keyed by the Basic cluster model identifier
+    #[serde(default)]
+    pub models: HashMap<String, ModelConfig>,
+    /// Where joined devices are remembered
+    #[serde(default = "default_database")]
+    pub database: PathBuf,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ModelConfig {
+    /// Replaces the default reporting of the same attribute
+    pub reporting: Vec<Reporting>,
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    pub fn transport(&self) -> TransportConfig {
+        match &self.tcp {
+            Some(tcp) => TransportConfig::Tcp(tcp.clone()),
+            None => TransportConfig::Serial(self.serial.clone()),
+        }
+    }
+}
+
+fn default_database() -> PathBuf {
+    "michiru-zstack-devices.json".into()
+}
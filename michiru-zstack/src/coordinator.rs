@@ -0,0 +1,334 @@
+//! Bringing up a Z-Stack device as the network coordinator
+
+use std::{ops::RangeInclusive, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    client::ZStack,
+    commands::{af, app_cnf, sys, util, zdo, Status},
+    nv,
+};
+
+const RESET_TIMEOUT: Duration = Duration::from_secs(15);
+const COMMISSIONING_TIMEOUT: Duration = Duration::from_secs(60);
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Z-Stack replies with this when registering an endpoint that already exists
+const APS_DUPLICATE_ENTRY: Status = Status(0xb8);
+
+pub const HA_PROFILE_ID: u16 = 0x0104;
+
+/// 2.4 GHz channels available to Zigbee
+pub const CHANNELS: RangeInclusive<u8> = 11..=26;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NetworkConfig {
+    pub pan_id: u16,
+    #[serde(deserialize_with = "deserialize_u64_hex")]
+    pub extended_pan_id: u64,
+    #[serde(default = "default_channels", deserialize_with = "deserialize_channels")]
+    pub channels: Vec<u8>,
+    #[serde(deserialize_with = "deserialize_key")]
+    pub network_key: [u8; 16],
+    #[serde(default = "default_endpoints")]
+    pub endpoints: Vec<EndpointConfig>,
+    /// Soft reset the device before configuring it
+    #[serde(default = "default_true")]
+    pub reset: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EndpointConfig {
+    pub endpoint: u8,
+    #[serde(default = "default_profile_id")]
+    pub profile_id: u16,
+    #[serde(default)]
+    pub device_id: u16,
+    #[serde(default)]
+    pub in_clusters: Vec<u16>,
+    #[serde(default)]
+    pub out_clusters: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Coordinator {
+    pub version: sys::VersionResponse,
+    pub ieee_addr: u64,
+    pub nwk_addr: u16,
+}
+
+impl NetworkConfig {
+    pub fn channel_mask(&self) -> u32 {
+        self.channels
+            .iter()
+            .fold(0, |mask, channel| mask | 1 << channel)
+    }
+
+    /// NV items that make up the network configuration
+    fn nv_items(&self) -> Vec<(u16, Vec<u8>)> {
+        vec![
+            (nv::LOGICAL_TYPE, vec![nv::LOGICAL_TYPE_COORDINATOR]),
+            (nv::PANID, self.pan_id.to_le_bytes().to_vec()),
+            (nv::EXTENDED_PAN_ID, self.extended_pan_id.to_le_bytes().to_vec()),
+            (nv::CHANLIST, self.channel_mask().to_le_bytes().to_vec()),
+            (nv::PRECFGKEY, self.network_key.to_vec()),
+            (nv::PRECFGKEYS_ENABLE, vec![0x00]),
+            (nv::ZDO_DIRECT_CB, vec![0x01]),
+        ]
+    }
+}
+
+/// Resets the device, (re)writes the network configuration if it differs,
+/// forms or resumes the network and registers the application endpoints
+pub async fn start(zstack: &ZStack, config: &NetworkConfig) -> Result<Coordinator> {
+    if config.reset {
+        reset(zstack).await?;
+    }
+
+    let version = zstack.request(&sys::Version {}).await?;
+    tracing::info!(?version, "Z-Stack version");
+
+    if needs_commissioning(zstack, config).await? {
+        tracing::info!("Writing network configuration");
+        commission(zstack, config, &version).await?;
+    }
+
+    startup(zstack).await?;
+
+    for endpoint in &config.endpoints {
+        let response = zstack
+            .request(&af::Register {
+                endpoint: endpoint.endpoint,
+                profile_id: endpoint.profile_id,
+                device_id: endpoint.device_id,
+                device_version: 0,
+                latency: 0,
+                in_clusters: endpoint.in_clusters.clone(),
+                out_clusters: endpoint.out_clusters.clone(),
+            })
+            .await?;
+
+        if response.status != APS_DUPLICATE_ENTRY {
+            response
+                .status
+                .ok()
+                .with_context(|| format!("Failed to register endpoint {}", endpoint.endpoint))?;
+        }
+    }
+
+    let info = zstack.request(&util::GetDeviceInfo {}).await?;
+    info.status.ok()?;
+
+    tracing::info!(ieee_addr = format!("{:016x}", info.ieee_addr), "Coordinator started");
+
+    Ok(Coordinator {
+        version,
+        ieee_addr: info.ieee_addr,
+        nwk_addr: info.short_addr,
+    })
+}
+
+pub async fn reset(zstack: &ZStack) -> Result<sys::ResetInd> {
+    let mut resets = zstack.indications::<sys::ResetInd>();
+
+    zstack
+        .send(&sys::ResetReq { reset_type: sys::RESET_SOFT })
+        .await?;
+
+    tokio::time::timeout(RESET_TIMEOUT, resets.next())
+        .await
+        .context("Timed out waiting for reset")?
+        .context("Z-Stack connection closed")
+}
+
+async fn needs_commissioning(zstack: &ZStack, config: &NetworkConfig) -> Result<bool> {
+    if nv::read(zstack, nv::MICHIRU_CONFIGURED).await? != Some(vec![nv::MICHIRU_CONFIGURED_VALUE]) {
+        return Ok(true);
+    }
+
+    for (id, value) in config.nv_items() {
+        if nv::read(zstack, id).await?.as_ref() != Some(&value) {
+            tracing::info!(id = format!("{id:#06x}"), "NV item differs from configuration");
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+async fn commission(
+    zstack: &ZStack,
+    config: &NetworkConfig,
+    version: &sys::VersionResponse,
+) -> Result<()> {
+    // start from a clean network state
+    nv::write(zstack, nv::STARTUP_OPTION, &[nv::STARTUP_OPTION_CLEAR_STATE]).await?;
+    reset(zstack).await?;
+
+    for (id, value) in config.nv_items() {
+        nv::write(zstack, id, &value).await?;
+    }
+
+    // Z-Stack 1.2 forms the network on startup, 3.x needs BDB commissioning
+    if version.product != 0 {
+        zstack
+            .request(&app_cnf::BdbSetChannel {
+                is_primary: true,
+                channel_mask: config.channel_mask(),
+            })
+            .await?
+            .status
+            .ok()?;
+        zstack
+            .request(&app_cnf::BdbSetChannel { is_primary: false, channel_mask: 0 })
+            .await?
+            .status
+            .ok()?;
+
+        zstack
+            .request(&zdo::StartupFromApp { start_delay: 100 })
+            .await?;
+
+        let mut notifications = zstack.indications::<app_cnf::BdbCommissioningNotification>();
+
+        zstack
+            .request(&app_cnf::BdbStartCommissioning { mode: app_cnf::MODE_NWK_FORMATION })
+            .await?
+            .status
+            .ok()?;
+
+        tokio::time::timeout(COMMISSIONING_TIMEOUT, async {
+            while let Some(notification) = notifications.next().await {
+                match notification.status {
+                    0 => return Ok(()),
+                    1 => continue,
+                    status => bail!("Network formation failed with status {status}"),
+                }
+            }
+
+            bail!("Z-Stack connection closed")
+        })
+        .await
+        .context("Timed out waiting for network formation")??;
+    }
+
+    nv::write(zstack, nv::MICHIRU_CONFIGURED, &[nv::MICHIRU_CONFIGURED_VALUE]).await
+}
+
+/// Starts the network stack and waits until the device is the coordinator
+async fn startup(zstack: &ZStack) -> Result<()> {
+    let info = zstack.request(&util::GetDeviceInfo {}).await?;
+    if info.device_state == zdo::DeviceState::Coordinator {
+        return Ok(());
+    }
+
+    let mut states = zstack.indications::<zdo::StateChangeInd>();
+
+    let response = zstack
+        .request(&zdo::StartupFromApp { start_delay: 100 })
+        .await?;
+    tracing::debug!(?response, "Startup");
+
+    tokio::time::timeout(STARTUP_TIMEOUT, async {
+        while let Some(ind) = states.next().await {
+            tracing::debug!(state = ?ind.state, "State changed");
+
+            if ind.state == zdo::DeviceState::Coordinator {
+                return Ok(());
+            }
+        }
+
+        bail!("Z-Stack connection closed")
+    })
+    .await
+    .context("Timed out waiting for coordinator state")?
+}
+
+fn default_channels() -> Vec<u8> {
+    vec![11]
+}
+
+fn default_endpoints() -> Vec<EndpointConfig> {
+    vec![EndpointConfig {
+        endpoint: 1,
+        profile_id: HA_PROFILE_ID,
+        device_id: 0x0005,
+        in_clusters: vec![],
+        out_clusters: vec![],
+    }]
+}
+
+fn default_profile_id() -> u16 {
+    HA_PROFILE_ID
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn deserialize_u64_hex<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    u64::from_str_radix(&value.replace(':', ""), 16).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_channels<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let channels = Vec::<u8>::deserialize(deserializer)?;
+    if channels.is_empty() {
+        return Err(serde::de::Error::custom("at least one channel is needed"));
+    }
+
+    match channels.iter().find(|channel| !CHANNELS.contains(channel)) {
+        Some(channel) => Err(serde::de::Error::custom(format!(
+            "invalid channel {channel}, must be in {}..={}",
+            CHANNELS.start(),
+            CHANNELS.end()
+        ))),
+        None => Ok(channels),
+    }
+}
+
+fn deserialize_key<'de, D>(deserializer: D) -> Result<[u8; 16], D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+
+    let mut key = [0; 16];
+    hex::decode_to_slice(value.replace(':', ""), &mut key).map_err(serde::de::Error::custom)?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels() {
+        let config = |channels: &str| {
+            toml::from_str::<NetworkConfig>(&format!(
+                r#"
+                pan_id = 0x1a62
+                extended_pan_id = "dd:dd:dd:dd:dd:dd:dd:dd"
+                network_key = "01030507090b0d0f00020406080a0c0d"
+                channels = {channels}
+                "#
+            ))
+        };
+
+        assert_eq!(config("[11, 15, 26]").unwrap().channel_mask(), 0x0400_8800);
+        assert!(config("[]").is_err());
+        assert!(config("[10]").is_err());
+        assert!(config("[27]").is_err());
+        assert!(config("[255]").is_err());
+    }
+}
@@ -0,0 +1,229 @@
+//! Persistent record of the devices that joined the network
+//!
+//! Devices are keyed by their IEEE address, the network address changes
+//! whenever a device rejoins.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::commands::zdo::LogicalType;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Device {
+    #[serde(serialize_with = "serialize_ieee", deserialize_with = "deserialize_ieee")]
+    pub ieee_addr: u64,
+    pub nwk_addr: u16,
+    /// Set once the interview completed, the fields below are only
+    /// meaningful afterwards
+    #[serde(default)]
+    pub interviewed: bool,
+    /// Set once bindings and attribute reporting are configured
+    #[serde(default)]
+    pub configured: bool,
+    #[serde(default)]
+    pub logical_type: Option<LogicalType>,
+    #[serde(default)]
+    pub manufacturer_code: Option<u16>,
+    #[serde(default)]
+    pub rx_on_when_idle: bool,
+    #[serde(default)]
+    pub manufacturer: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub sw_build_id: Option<String>,
+    #[serde(default)]
+    pub power_source: Option<u8>,
+    #[serde(default)]
+    pub endpoints: Vec<Endpoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub endpoint: u8,
+    pub profile_id: u16,
+    pub device_id: u16,
+    pub in_clusters: Vec<u16>,
+    pub out_clusters: Vec<u16>,
+}
+
+pub struct Database {
+    path: PathBuf,
+    devices: BTreeMap<u64, Device>,
+}
+
+impl Device {
+    pub fn new(ieee_addr: u64, nwk_addr: u16) -> Self {
+        Self {
+            ieee_addr,
+            nwk_addr,
+            interviewed: false,
+            configured: false,
+            logical_type: None,
+            manufacturer_code: None,
+            rx_on_when_idle: false,
+            manufacturer: None,
+            model: None,
+            sw_build_id: None,
+            power_source: None,
+            endpoints: vec![],
+        }
+    }
+
+    /// First endpoint implementing a server cluster
+    pub fn endpoint_with(&self, cluster_id: u16) -> Option<&Endpoint> {
+        self.endpoints
+            .iter()
+            .find(|e| e.in_clusters.contains(&cluster_id))
+    }
+}
+
+impl Database {
+    /// Loads the database, starting empty if the file doesn't exist yet
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let devices: Vec<Device> = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse device database {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read device database {}", path.display()))
+            }
+        };
+
+        Ok(Self {
+            path,
+            devices: devices.into_iter().map(|d| (d.ieee_addr, d)).collect(),
+        })
+    }
+
+    /// Writes the database, replacing the file atomically
+    pub fn save(&self) -> Result<()> {
+        let devices: Vec<_> = self.devices.values().collect();
+        let contents = serde_json::to_string_pretty(&devices)?;
+
+        let tmp = temp_path(&self.path);
+        std::fs::write(&tmp, contents)
+            .and_then(|()| std::fs::rename(&tmp, &self.path))
+            .with_context(|| format!("Failed to write device database {}", self.path.display()))
+    }
+
+    pub fn get(&self, ieee_addr: u64) -> Option<&Device> {
+        self.devices.get(&ieee_addr)
+    }
+
+    pub fn get_mut(&mut self, ieee_addr: u64) -> Option<&mut Device> {
+        self.devices.get_mut(&ieee_addr)
+    }
+
+    pub fn by_nwk_addr(&self, nwk_addr: u16) -> Option<&Device> {
+        self.devices.values().find(|d| d.nwk_addr == nwk_addr)
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = &Device> {
+        self.devices.values()
+    }
+
+    /// Records a device announcing itself, returns whether anything changed
+    pub fn announce(&mut self, ieee_addr: u64, nwk_addr: u16) -> bool {
+        // another device may have been handed its old address
+        for device in self.devices.values_mut() {
+            if device.nwk_addr == nwk_addr && device.ieee_addr != ieee_addr {
+                device.nwk_addr = 0xfffe;
+            }
+        }
+
+        match self.devices.get_mut(&ieee_addr) {
+            Some(device) if device.nwk_addr == nwk_addr => false,
+            Some(device) => {
+                device.nwk_addr = nwk_addr;
+                true
+            }
+            None => {
+                self.devices
+                    .insert(ieee_addr, Device::new(ieee_addr, nwk_addr));
+                true
+            }
+        }
+    }
+
+    pub fn insert(&mut self, device: Device) {
+        self.devices.insert(device.ieee_addr, device);
+    }
+
+    pub fn remove(&mut self, ieee_addr: u64) -> Option<Device> {
+        self.devices.remove(&ieee_addr)
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+pub(crate) fn serialize_ieee<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{value:016x}"))
+}
+
+pub(crate) fn deserialize_ieee<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u64, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persistence() {
+        let path =
+            std::env::temp_dir().join(format!("michiru-zstack-db-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut database = Database::load(&path).unwrap();
+        assert!(database.announce(0x00124b0012345678, 0x1234));
+        assert!(!database.announce(0x00124b0012345678, 0x1234));
+
+        let mut device = database.get(0x00124b0012345678).unwrap().clone();
+        device.interviewed = true;
+        device.logical_type = Some(LogicalType::EndDevice);
+        device.model = Some("lumi.weather".into());
+        device.endpoints.push(Endpoint {
+            endpoint: 1,
+            profile_id: 0x0104,
+            device_id: 0x5f01,
+            in_clusters: vec![0x0000, 0x0402],
+            out_clusters: vec![],
+        });
+        database.insert(device.clone());
+        database.save().unwrap();
+
+        // rejoined with a new address, and someone else got the old one
+        let mut database = Database::load(&path).unwrap();
+        assert!(database.announce(0x00124b0012345678, 0x4321));
+        assert!(database.announce(0x00158d0000000001, 0x1234));
+        assert_eq!(database.by_nwk_addr(0x4321).unwrap().model, device.model);
+        assert_eq!(
+            database
+                .get(0x00124b0012345678)
+                .unwrap()
+                .endpoint_with(0x0402)
+                .unwrap()
+                .endpoint,
+            1
+        );
+        assert_eq!(database.devices().count(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+//! Discovering what a newly joined device is
+//!
+//! The interview walks the ZDO descriptors (node, active endpoints, simple
+//! descriptor per endpoint) and reads the identifying attributes of the
+//! Basic cluster.
+
+use std::{future::Future, time::Duration};
+
+use anyhow::Result;
+
+use crate::{
+    client::ZStack,
+    commands::zdo::NodeDescriptor,
+    database::{Device, Endpoint},
+    network,
+    zcl::clusters::basic,
+};
+
+const ATTEMPTS: usize = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub async fn interview(zstack: &ZStack, ieee_addr: u64, nwk_addr: u16) -> Result<Device> {
+    let mut device = Device::new(ieee_addr, nwk_addr);
+
+    let node = retry(|| network::node_descriptor(zstack, nwk_addr)).await?;
+    tracing::debug!(?node, "Node descriptor");
+
+    device.logical_type = Some(node.logical_type());
+    device.manufacturer_code = Some(node.manufacturer_code);
+    device.rx_on_when_idle =
+        node.mac_capabilities & NodeDescriptor::CAPABILITY_RX_ON_WHEN_IDLE != 0;
+
+    let endpoints = retry(|| network::active_endpoints(zstack, nwk_addr)).await?;
+    tracing::debug!(?endpoints, "Active endpoints");
+
+    for endpoint in endpoints {
+        let descriptor = retry(|| network::simple_descriptor(zstack, nwk_addr, endpoint)).await?;
+        tracing::debug!(?descriptor, "Simple descriptor");
+
+        device.endpoints.push(Endpoint {
+            endpoint: descriptor.endpoint,
+            profile_id: descriptor.profile_id,
+            device_id: descriptor.device_id,
+            in_clusters: descriptor.in_clusters,
+            out_clusters: descriptor.out_clusters,
+        });
+    }
+
+    // some devices don't list Basic even though they implement it
+    let basic_endpoint = device
+        .endpoint_with(basic::ID)
+        .or(device.endpoints.first())
+        .map(|e| e.endpoint);
+
+    if let Some(endpoint) = basic_endpoint {
+        let attributes = [
+            basic::MANUFACTURER_NAME,
+            basic::MODEL_IDENTIFIER,
+            basic::POWER_SOURCE,
+            basic::SW_BUILD_ID,
+        ];
+
+        let records =
+            retry(|| network::read_attributes(zstack, nwk_addr, endpoint, basic::ID, &attributes))
+                .await?;
+
+        for record in records {
+            let Some(value) = record.value else {
+                continue;
+            };
+
+            match record.attribute {
+                basic::MANUFACTURER_NAME => device.manufacturer = value.as_str().map(Into::into),
+                basic::MODEL_IDENTIFIER => device.model = value.as_str().map(Into::into),
+                basic::SW_BUILD_ID => device.sw_build_id = value.as_str().map(Into::into),
+                basic::POWER_SOURCE => device.power_source = value.as_i64().map(|v| v as u8),
+                _ => {}
+            }
+        }
+    }
+
+    device.interviewed = true;
+
+    Ok(device)
+}
+
+async fn retry<T, F, Fut>(mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < ATTEMPTS => {
+                tracing::debug!(attempt, "Interview step failed: {e:#}");
+                attempt += 1;
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{af, zdo, Command, Status},
+        database::Endpoint,
+        transport::simulator::Simulator,
+        zcl::{self, global::ReadAttributeStatus, Frame, GlobalCommand, Value},
+    };
+
+    const NWK_ADDR: u16 = 0x1234;
+
+    fn read_response(request: &af::DataRequest) -> af::IncomingMsg {
+        let request = Frame::parse(&request.data).unwrap();
+        let GlobalCommand::ReadAttributes(attributes) = request.global_command().unwrap() else {
+            panic!("not a read attributes request");
+        };
+
+        let records = attributes
+            .into_iter()
+            .map(|attribute| {
+                let value = match attribute {
+                    basic::MANUFACTURER_NAME => Value::CharString("IKEA of Sweden".into()),
+                    basic::MODEL_IDENTIFIER => Value::CharString("TRADFRI bulb E27 WW".into()),
+                    basic::POWER_SOURCE => Value::Enum8(0x01),
+                    _ => {
+                        return ReadAttributeStatus {
+                            attribute,
+                            status: zcl::Status::UNSUPPORTED_ATTRIBUTE,
+                            value: None,
+                        }
+                    }
+                };
+
+                ReadAttributeStatus {
+                    attribute,
+                    status: zcl::Status::SUCCESS,
+                    value: Some(value),
+                }
+            })
+            .collect();
+
+        let mut response =
+            Frame::global(request.header.sequence, &GlobalCommand::ReadAttributesResponse(records));
+        response.header.direction = zcl::Direction::ServerToClient;
+
+        af::IncomingMsg {
+            group_id: 0,
+            cluster_id: basic::ID,
+            src_addr: NWK_ADDR,
+            src_endpoint: 1,
+            dst_endpoint: 1,
+            was_broadcast: false,
+            link_quality: 120,
+            security_use: false,
+            timestamp: 0,
+            trans_seq: 0,
+            data: response.serialize(),
+        }
+    }
+
+    #[tokio::test]
+    async fn light_bulb() {
+        let (transport, _) = Simulator::new()
+            .on(|_: zdo::NodeDescReq| {
+                vec![
+                    zdo::NodeDescReqResponse { status: Status::SUCCESS }.to_message(),
+                    zdo::NodeDescRsp {
+                        src_addr: NWK_ADDR,
+                        status: Status::SUCCESS,
+                        nwk_addr: NWK_ADDR,
+                        descriptor: Some(NodeDescriptor {
+                            flags: 0x01,
+                            aps_flags: 0x40,
+                            mac_capabilities: 0x8e,
+                            manufacturer_code: 0x117c,
+                            max_buffer_size: 82,
+                            max_in_transfer_size: 128,
+                            server_mask: 0,
+                            max_out_transfer_size: 128,
+                            descriptor_capabilities: 0,
+                        }),
+                    }
+                    .to_message(),
+                ]
+            })
+            .on(|_: zdo::ActiveEpReq| {
+                vec![
+                    zdo::ActiveEpReqResponse { status: Status::SUCCESS }.to_message(),
+                    zdo::ActiveEpRsp {
+                        src_addr: NWK_ADDR,
+                        status: Status::SUCCESS,
+                        nwk_addr: NWK_ADDR,
+                        active_eps: vec![1],
+                    }
+                    .to_message(),
+                ]
+            })
+            .on(|request: zdo::SimpleDescReq| {
+                vec![
+                    zdo::SimpleDescReqResponse { status: Status::SUCCESS }.to_message(),
+                    zdo::SimpleDescRsp {
+                        src_addr: NWK_ADDR,
+                        status: Status::SUCCESS,
+                        nwk_addr: NWK_ADDR,
+                        len: 20,
+                        descriptor: Some(zdo::SimpleDescriptor {
+                            endpoint: request.endpoint,
+                            profile_id: 0x0104,
+                            device_id: 0x0101,
+                            device_version: 2,
+                            in_clusters: vec![0x0000, 0x0006, 0x0008],
+                            out_clusters: vec![0x0019],
+                        }),
+                    }
+                    .to_message(),
+                ]
+            })
+            .on(|request: af::DataRequest| {
+                vec![
+                    af::DataRequestResponse { status: Status::SUCCESS }.to_message(),
+                    af::DataConfirm {
+                        status: Status::SUCCESS,
+                        endpoint: request.dst_endpoint,
+                        trans_id: request.trans_id,
+                    }
+                    .to_message(),
+                    read_response(&request).to_message(),
+                ]
+            })
+            .start();
+        let zstack = ZStack::new(transport);
+
+        let device = interview(&zstack, 0x000b57fffe123456, NWK_ADDR)
+            .await
+            .unwrap();
+
+        assert!(device.interviewed);
+        assert!(device.rx_on_when_idle);
+        assert_eq!(device.manufacturer_code, Some(0x117c));
+        assert_eq!(device.manufacturer.as_deref(), Some("IKEA of Sweden"));
+        assert_eq!(device.model.as_deref(), Some("TRADFRI bulb E27 WW"));
+        assert_eq!(device.power_source, Some(0x01));
+        assert_eq!(device.sw_build_id, None);
+        assert_eq!(device.endpoints, [Endpoint {
+            endpoint: 1,
+            profile_id: 0x0104,
+            device_id: 0x0101,
+            in_clusters: vec![0x0000, 0x0006, 0x0008],
+            out_clusters: vec![0x0019],
+        }]);
+    }
+}
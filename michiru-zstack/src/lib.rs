@@ -0,0 +1,15 @@
+pub mod backup;
+pub mod capture;
+pub mod client;
+pub mod codec;
+pub mod commands;
+pub mod coordinator;
+pub mod database;
+pub mod interview;
+pub mod map;
+pub mod message;
+pub mod network;
+pub mod nv;
+pub mod reporting;
+pub mod transport;
+pub mod zcl;
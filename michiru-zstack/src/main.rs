@@ -1,149 +1,397 @@
-use std::{io::ErrorKind, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::{Context, Result};
-use nom::{number::complete::u8, IResult};
-use serialport::{SerialPortType, UsbPortInfo};
-
-fn main() -> Result<()> {
-    let port = serialport::available_ports()
-        .context("No serial ports found")?
-        .into_iter()
-        .find(|p| match p.port_type {
-            SerialPortType::UsbPort(UsbPortInfo { product: Some(ref product), .. }) => {
-                product.contains("CC2531")
-            }
-            _ => false,
-        })
-        .context("No CC2531 found")?;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
+use michiru_zstack::{
+    backup::{self, Backup},
+    capture::{self, Capture},
+    client::ZStack,
+    commands::{af, zdo},
+    coordinator,
+    database::{Database, Device},
+    interview::interview,
+    map, network,
+    reporting::{self, Reporting},
+};
+use tokio::sync::mpsc;
 
-    let mut port = serialport::new(port.port_name, 115200)
-        .open()
-        .context("Failed to open serial port")?;
+use crate::{
+    bridge::{Bridge, PendingSet},
+    config::{Config, ModelConfig},
+};
 
-    port.set_timeout(Duration::from_secs(10))
-        .context("Failed to set timeout")?;
+mod bridge;
+mod config;
 
-    port.write_all(&[0xfe, 0x00, 0x21, 0x01, 0x20])?;
+#[derive(Debug, Parser)]
+struct Args {
+    /// Path to the TOML configuration file
+    #[arg(short, long, global = true, default_value = "michiru-zstack.toml")]
+    config: PathBuf,
 
-    let mut buf = [0u8; 1024];
-    loop {
-        let n = match port.read(&mut buf) {
-            Ok(n) => n,
-            Err(e) if e.kind() == ErrorKind::TimedOut => continue,
-            Err(e) => return Err(e).context("Failed to read from serial port"),
-        };
-
-        if n > 0 {
-            let msg = Message::parse(&buf[..n]);
-            println!("{:?}", msg);
-            dbg!(msg.map(|(_, m)| (m.verify(), m.cmd_type(), m.subsystem(), m.cmd_id())));
-        }
-    }
+    /// Log the MT traffic to this file, for the decode command
+    #[arg(long, global = true)]
+    capture: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Debug)]
-struct Message<'a> {
-    command_id: [u8; 2],
-    data: &'a [u8],
-    check: u8,
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the bridge, the default
+    Run {
+        /// Open the network for joining for this many seconds after startup
+        #[arg(long)]
+        permit_join: Option<u8>,
+    },
+    /// Open the network for joining
+    PermitJoin {
+        /// In seconds, 0 closes the network and 255 keeps it open
+        #[arg(default_value_t = 60)]
+        duration: u8,
+    },
+    /// Ask a device to leave the network and forget it
+    Leave {
+        /// IEEE address of the device
+        #[arg(value_parser = parse_ieee)]
+        ieee_addr: u64,
+    },
+    /// Walk the mesh and print a network map
+    Map {
+        #[arg(long, value_enum, default_value_t = MapFormat::Json)]
+        format: MapFormat,
+        /// Write the map to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Save the network state in the open coordinator backup format
+    Backup {
+        /// Write the backup to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Restore the network state from a backup, overwriting the current network
+    Restore {
+        /// Backup in the open coordinator backup format
+        input: PathBuf,
+    },
+    /// Print the frames of a traffic capture
+    Decode {
+        /// File written with --capture
+        input: PathBuf,
+    },
 }
 
-impl<'a> Message<'a> {
-    pub fn parse(src: &'a [u8]) -> IResult<&'a [u8], Self> {
-        use nom::{bytes::streaming::*, number::streaming::*, sequence::*};
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum MapFormat {
+    Json,
+    Dot,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
 
-        let (src, len) = preceded(tag([0xfe]), u8)(src)?;
-        let (src, (command_id, data, check)) = tuple((be_u16, take(len), u8))(src)?;
+    let args = Args::parse();
+    let command = args.command.unwrap_or(Command::Run { permit_join: None });
 
-        Ok((src, Message {
-            command_id: command_id.to_be_bytes(),
-            data,
-            check,
-        }))
+    // works offline, without a configuration
+    if let Command::Decode { input } = &command {
+        return decode(input);
     }
 
-    pub fn verify(&self) -> Result<(), ()> {
-        let mut check = 0u8;
-        check ^= self.command_id[0];
-        check ^= self.command_id[1];
-        check ^= self.data.len() as u8;
-        check = self.data.iter().fold(check, |acc, b| acc ^ b);
-        check ^= self.check;
+    let config = Config::load(&args.config)?;
+
+    let mut transport = config.transport().open().await?;
+    if let Some(path) = &args.capture {
+        transport = Box::new(Capture::create(transport, path)?);
+    }
+    let zstack = Arc::new(ZStack::new(transport));
 
-        if check == 0 {
+    match command {
+        Command::Run { permit_join } => run(config, zstack, permit_join).await,
+        Command::PermitJoin { duration } => {
+            coordinator::start(&zstack, &config.network).await?;
+            network::permit_join(&zstack, duration).await?;
+
+            tracing::info!(duration, "Network open for joining");
             Ok(())
-        } else {
-            Err(())
         }
-    }
+        Command::Leave { ieee_addr } => {
+            let mut database = Database::load(&config.database)?;
+            let nwk_addr = database
+                .get(ieee_addr)
+                .with_context(|| format!("Unknown device {ieee_addr:016x}"))?
+                .nwk_addr;
 
-    pub fn cmd_type(&self) -> Result<CmdType, ()> {
-        CmdType::try_from(self.command_id[0] >> 5)
-    }
+            coordinator::start(&zstack, &config.network).await?;
+            network::leave(&zstack, nwk_addr, ieee_addr).await?;
+
+            database.remove(ieee_addr);
+            database.save()?;
+
+            tracing::info!(ieee_addr = format!("{ieee_addr:016x}"), "Device left the network");
+            Ok(())
+        }
+        Command::Map { format, output } => {
+            let database = Database::load(&config.database)?;
+            let coordinator = coordinator::start(&zstack, &config.network).await?;
+
+            let mut map = map::scan(&zstack, &coordinator).await;
+            map.name_devices(&database);
+
+            let contents = match format {
+                MapFormat::Json => serde_json::to_string_pretty(&map)? + "\n",
+                MapFormat::Dot => map.to_dot(),
+            };
+
+            match output {
+                Some(path) => std::fs::write(&path, contents)
+                    .with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{contents}"),
+            }
+
+            Ok(())
+        }
+        Command::Backup { output } => {
+            let backup = backup::backup(&zstack).await?;
+            let contents = serde_json::to_string_pretty(&backup)? + "\n";
+
+            match output {
+                Some(path) => std::fs::write(&path, contents)
+                    .with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{contents}"),
+            }
+
+            tracing::info!(devices = backup.devices.len(), "Network backed up");
+            Ok(())
+        }
+        Command::Restore { input } => {
+            let contents = std::fs::read_to_string(&input)
+                .with_context(|| format!("Failed to read {}", input.display()))?;
+            let backup: Backup = serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", input.display()))?;
+
+            anyhow::ensure!(
+                backup.metadata.format == backup::FORMAT
+                    && backup.metadata.version == backup::VERSION,
+                "Unsupported backup format {} version {}",
+                backup.metadata.format,
+                backup.metadata.version
+            );
+            backup.check_config(&config.network)?;
+
+            backup::restore_address(&zstack, &backup).await?;
+            coordinator::start(&zstack, &config.network).await?;
+
+            backup::restore(&zstack, &backup).await?;
+            coordinator::reset(&zstack).await?;
+            let coordinator = coordinator::start(&zstack, &config.network).await?;
+
+            if coordinator.ieee_addr != backup.coordinator_ieee {
+                tracing::warn!(
+                    ieee_addr = format!("{:016x}", coordinator.ieee_addr),
+                    "The firmware ignored the restored coordinator address"
+                );
+            }
 
-    pub fn subsystem(&self) -> Result<Subsystem, ()> {
-        Subsystem::try_from(self.command_id[0] & 0x1f)
+            tracing::info!(devices = backup.devices.len(), "Network restored");
+            Ok(())
+        }
+        Command::Decode { .. } => unreachable!(),
     }
+}
 
-    pub fn cmd_id(&self) -> u8 {
-        self.command_id[1]
+fn decode(path: &Path) -> Result<()> {
+    let records = capture::read(path)?;
+
+    for (timestamp, direction, event) in capture::dissect(&records) {
+        println!(
+            "{}.{:06} {} {}",
+            timestamp.as_secs(),
+            timestamp.subsec_micros(),
+            direction.symbol(),
+            capture::describe(&event)
+        );
     }
+
+    Ok(())
 }
 
-#[derive(Debug)]
-enum CmdType {
-    Poll,
-    SyncRequest,
-    AsyncRequest,
-    SyncResponse,
+fn parse_ieee(value: &str) -> Result<u64, std::num::ParseIntError> {
+    u64::from_str_radix(&value.trim_start_matches("0x").replace(':', ""), 16)
 }
 
-impl TryFrom<u8> for CmdType {
-    type Error = ();
+async fn run(config: Config, zstack: Arc<ZStack>, permit_join: Option<u8>) -> Result<()> {
+    let mut database = Database::load(&config.database)?;
+    let mut announcements = zstack.indications::<zdo::EndDeviceAnnceInd>();
+    let mut messages = zstack.indications::<af::IncomingMsg>();
 
-    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
-        match value {
-            0x00 => Ok(CmdType::Poll),
-            0x01 => Ok(CmdType::SyncRequest),
-            0x02 => Ok(CmdType::AsyncRequest),
-            0x03 => Ok(CmdType::SyncResponse),
-            _ => Err(()),
+    let coordinator = coordinator::start(&zstack, &config.network).await?;
+    let models = Arc::new(config.models);
+
+    if let Some(duration) = permit_join {
+        network::permit_join(&zstack, duration).await?;
+        tracing::info!(duration, "Network open for joining");
+    }
+
+    let (mut bridge, mut commands) = Bridge::new(zstack.clone(), config.mqtt);
+    for device in database.devices().filter(|d| d.interviewed) {
+        bridge.add(device).await?;
+    }
+
+    let (setups_tx, mut setups) = mpsc::channel::<(u64, Result<Device>)>(8);
+    let (sets_tx, mut sets) = mpsc::channel::<(PendingSet, Result<()>)>(8);
+    let mut in_setup = HashSet::new();
+
+    // interviews and configures a device in the background
+    let spawn_setup = |in_setup: &mut HashSet<u64>, device: Device| {
+        if !in_setup.insert(device.ieee_addr) {
+            return;
+        }
+
+        let zstack = zstack.clone();
+        let models = models.clone();
+        let tx = setups_tx.clone();
+
+        tokio::spawn(async move {
+            let ieee_addr = device.ieee_addr;
+            let result = setup(&zstack, coordinator.ieee_addr, &models, device)
+                .await
+                .with_context(|| format!("Failed to set up {ieee_addr:016x}"));
+            let _ = tx.send((ieee_addr, result)).await;
+        });
+    };
+
+    loop {
+        tokio::select! {
+            Some(annce) = announcements.next() => {
+                let ieee_addr = annce.ieee_addr;
+                tracing::info!(
+                    ieee_addr = format!("{ieee_addr:016x}"),
+                    nwk_addr = format!("{:#06x}", annce.nwk_addr),
+                    "Device announced"
+                );
+
+                if database.announce(ieee_addr, annce.nwk_addr) {
+                    database.save()?;
+                }
+                bridge.update_address(ieee_addr, annce.nwk_addr);
+
+                let device = database.get(ieee_addr).unwrap();
+                if !device.configured {
+                    spawn_setup(&mut in_setup, device.clone());
+                }
+            }
+            Some((ieee_addr, result)) = setups.recv() => {
+                in_setup.remove(&ieee_addr);
+
+                match result {
+                    Ok(device) => {
+                        tracing::info!(
+                            ieee_addr = format!("{:016x}", device.ieee_addr),
+                            manufacturer = device.manufacturer,
+                            model = device.model,
+                            configured = device.configured,
+                            "Device set up"
+                        );
+
+                        // keep the address if the device rejoined in the meantime
+                        let nwk_addr = database.get(device.ieee_addr).map(|d| d.nwk_addr);
+                        let device = Device {
+                            nwk_addr: nwk_addr.unwrap_or(device.nwk_addr),
+                            ..device
+                        };
+
+                        bridge.add(&device).await?;
+                        database.insert(device);
+                        database.save()?;
+                    }
+                    Err(e) => {
+                        // retried on the next announcement
+                        tracing::warn!("{e:#}");
+                    }
+                }
+            }
+            Some(message) = messages.next() => {
+                if let Err(e) = bridge.handle_message(&message).await {
+                    tracing::warn!("Failed to handle message: {e:#}");
+                }
+            }
+            Some((ieee_addr, command)) = commands.recv() => {
+                if bridge::is_configure(&command) {
+                    if let Some(device) = database.get(ieee_addr) {
+                        tracing::info!(ieee_addr = format!("{ieee_addr:016x}"), "Reconfiguring");
+                        spawn_setup(&mut in_setup, Device { configured: false, ..device.clone() });
+                    }
+                    continue;
+                }
+
+                // delivery to sleepy devices is slow, don't hold up the others
+                match bridge.prepare_set(ieee_addr, command) {
+                    Ok(set) => {
+                        let tx = sets_tx.clone();
+                        tokio::spawn(async move {
+                            let result = set.send().await;
+                            let _ = tx.send((set, result)).await;
+                        });
+                    }
+                    Err(e) => {
+                        tracing::warn!(ieee_addr = format!("{ieee_addr:016x}"), "Failed to set: {e:#}");
+                    }
+                }
+            }
+            Some((set, result)) = sets.recv() => {
+                let ieee_addr = set.ieee_addr;
+                let result = match result {
+                    Ok(()) => bridge.set_delivered(set).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    tracing::warn!(ieee_addr = format!("{ieee_addr:016x}"), "Failed to set: {e:#}");
+                }
+            }
+            else => break,
         }
     }
-}
 
-#[derive(Debug)]
-enum Subsystem {
-    Sys,
-    Mac,
-    Nwk,
-    Af,
-    Zdo,
-    Sapi,
-    Util,
-    Debug,
-    App,
-    AppConfig,
-    GreenPower,
+    Ok(())
 }
 
-impl TryFrom<u8> for Subsystem {
-    type Error = ();
-
-    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
-        match value {
-            0x01 => Ok(Subsystem::Sys),
-            0x02 => Ok(Subsystem::Mac),
-            0x03 => Ok(Subsystem::Nwk),
-            0x04 => Ok(Subsystem::Af),
-            0x05 => Ok(Subsystem::Zdo),
-            0x06 => Ok(Subsystem::Sapi),
-            0x07 => Ok(Subsystem::Util),
-            0x08 => Ok(Subsystem::Debug),
-            0x09 => Ok(Subsystem::App),
-            0x0f => Ok(Subsystem::AppConfig),
-            0x15 => Ok(Subsystem::GreenPower),
-            _ => Err(()),
-        }
+/// Interviews the device if needed, then sets up bindings and reporting
+///
+/// Failing to configure reporting is not fatal, the device is then retried
+/// on its next announcement.
+async fn setup(
+    zstack: &ZStack,
+    coordinator_ieee: u64,
+    models: &HashMap<String, ModelConfig>,
+    mut device: Device,
+) -> Result<Device> {
+    if !device.interviewed {
+        device = interview(zstack, device.ieee_addr, device.nwk_addr).await?;
     }
+
+    let overrides = device
+        .model
+        .as_ref()
+        .and_then(|model| models.get(model))
+        .map(|m| m.reporting.as_slice())
+        .unwrap_or_default();
+    let reportings: Vec<Reporting> = reporting::for_device(&device, overrides);
+
+    match reporting::configure(zstack, coordinator_ieee, &device, &reportings).await {
+        Ok(()) => device.configured = true,
+        Err(e) => tracing::warn!("{e:#}"),
+    }
+
+    Ok(device)
 }
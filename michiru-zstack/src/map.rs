@@ -0,0 +1,244 @@
+//! Network map, built by walking the neighbor and routing tables of every
+//! router starting at the coordinator
+
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fmt::Write,
+};
+
+use serde::Serialize;
+
+use crate::{
+    client::ZStack,
+    commands::zdo::{LogicalType, Neighbor, Route},
+    coordinator::Coordinator,
+    database, network,
+};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NetworkMap {
+    pub nodes: Vec<MapNode>,
+    pub links: Vec<Link>,
+    pub routes: Vec<MapRoute>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MapNode {
+    #[serde(serialize_with = "database::serialize_ieee")]
+    pub ieee_addr: u64,
+    pub nwk_addr: u16,
+    pub logical_type: LogicalType,
+    /// Model identifier, if the device is known
+    pub name: Option<String>,
+    /// The tables of this router could not be read
+    pub failed: bool,
+}
+
+/// An entry in the neighbor table of `source`
+#[derive(Debug, Clone, Serialize)]
+pub struct Link {
+    pub source: u16,
+    pub target: u16,
+    pub lqi: u8,
+    pub depth: u8,
+    pub relationship: &'static str,
+}
+
+/// An entry in the routing table of `source`
+#[derive(Debug, Clone, Serialize)]
+pub struct MapRoute {
+    pub source: u16,
+    pub destination: u16,
+    pub next_hop: u16,
+    pub status: &'static str,
+}
+
+/// Walks the mesh, routers that don't answer are marked as failed
+pub async fn scan(zstack: &ZStack, coordinator: &Coordinator) -> NetworkMap {
+    let mut map = NetworkMap::default();
+    let mut nodes = BTreeMap::new();
+
+    nodes.insert(coordinator.nwk_addr, MapNode {
+        ieee_addr: coordinator.ieee_addr,
+        nwk_addr: coordinator.nwk_addr,
+        logical_type: LogicalType::Coordinator,
+        name: None,
+        failed: false,
+    });
+
+    let mut queue = VecDeque::from([coordinator.nwk_addr]);
+    let mut visited = HashSet::from([coordinator.nwk_addr]);
+
+    while let Some(nwk_addr) = queue.pop_front() {
+        tracing::debug!(nwk_addr = format!("{nwk_addr:#06x}"), "Reading neighbor table");
+
+        let neighbors = match network::neighbors(zstack, nwk_addr).await {
+            Ok(neighbors) => neighbors,
+            Err(e) => {
+                tracing::warn!(nwk_addr = format!("{nwk_addr:#06x}"), "{e:#}");
+                nodes.get_mut(&nwk_addr).unwrap().failed = true;
+                continue;
+            }
+        };
+
+        for neighbor in neighbors {
+            let logical_type = neighbor.logical_type();
+
+            nodes.entry(neighbor.nwk_addr).or_insert(MapNode {
+                ieee_addr: neighbor.ieee_addr,
+                nwk_addr: neighbor.nwk_addr,
+                logical_type,
+                name: None,
+                failed: false,
+            });
+
+            map.links.push(link(nwk_addr, &neighbor));
+
+            if logical_type == LogicalType::Router && visited.insert(neighbor.nwk_addr) {
+                queue.push_back(neighbor.nwk_addr);
+            }
+        }
+
+        match network::routes(zstack, nwk_addr).await {
+            Ok(routes) => map.routes.extend(routes.iter().map(|r| route(nwk_addr, r))),
+            Err(e) => tracing::warn!(nwk_addr = format!("{nwk_addr:#06x}"), "{e:#}"),
+        }
+    }
+
+    map.nodes = nodes.into_values().collect();
+    map
+}
+
+fn link(source: u16, neighbor: &Neighbor) -> Link {
+    Link {
+        source,
+        target: neighbor.nwk_addr,
+        lqi: neighbor.lqi,
+        depth: neighbor.depth,
+        relationship: match neighbor.relationship() {
+            0 => "parent",
+            1 => "child",
+            2 => "sibling",
+            4 => "previous_child",
+            _ => "none",
+        },
+    }
+}
+
+fn route(source: u16, route: &Route) -> MapRoute {
+    MapRoute {
+        source,
+        destination: route.dst_addr,
+        next_hop: route.next_hop,
+        status: match route.status {
+            0 => "active",
+            1 => "discovery_underway",
+            2 => "discovery_failed",
+            3 => "inactive",
+            _ => "validation_underway",
+        },
+    }
+}
+
+impl NetworkMap {
+    /// Fills in names from the device database
+    pub fn name_devices(&mut self, database: &database::Database) {
+        for node in &mut self.nodes {
+            node.name = database.get(node.ieee_addr).and_then(|d| d.model.clone());
+        }
+    }
+
+    /// Graphviz rendering of the nodes and neighbor links
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph network {\n");
+
+        for node in &self.nodes {
+            let kind = match node.logical_type {
+                LogicalType::Coordinator => "Coordinator",
+                LogicalType::Router => "Router",
+                LogicalType::EndDevice => "End device",
+                LogicalType::Other(_) => "Unknown",
+            };
+            let shape = match node.logical_type {
+                LogicalType::Coordinator => "doubleoctagon",
+                LogicalType::Router => "box",
+                _ => "ellipse",
+            };
+            let name = node.name.as_deref().unwrap_or(kind);
+            let style = if node.failed { ", style=dashed" } else { "" };
+
+            writeln!(
+                out,
+                "    \"{:#06x}\" [label=\"{}\\n{:016x}\\n{:#06x}\", shape={shape}{style}];",
+                node.nwk_addr,
+                escape(name),
+                node.ieee_addr,
+                node.nwk_addr,
+            )
+            .unwrap();
+        }
+
+        for link in &self.links {
+            writeln!(
+                out,
+                "    \"{:#06x}\" -> \"{:#06x}\" [label=\"{}\"];",
+                link.source, link.target, link.lqi,
+            )
+            .unwrap();
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot() {
+        let map = NetworkMap {
+            nodes: vec![
+                MapNode {
+                    ieee_addr: 0x00124b0001abcdef,
+                    nwk_addr: 0x0000,
+                    logical_type: LogicalType::Coordinator,
+                    name: None,
+                    failed: false,
+                },
+                MapNode {
+                    ieee_addr: 0x00158d0001020304,
+                    nwk_addr: 0xa53e,
+                    logical_type: LogicalType::EndDevice,
+                    name: Some("lumi.sensor \"ht\"".into()),
+                    failed: false,
+                },
+            ],
+            links: vec![Link {
+                source: 0x0000,
+                target: 0xa53e,
+                lqi: 115,
+                depth: 1,
+                relationship: "child",
+            }],
+            routes: vec![],
+        };
+
+        assert_eq!(
+            map.to_dot(),
+            "digraph network {\n    \"0x0000\" [label=\"Coordinator\\n00124b0001abcdef\\n0x0000\", \
+             shape=doubleoctagon];\n    \"0xa53e\" [label=\"lumi.sensor \\\"ht\\\"\\n00158d0001020304\\n0xa53e\", \
+             shape=ellipse];\n    \"0x0000\" -> \"0xa53e\" [label=\"115\"];\n}\n"
+        );
+
+        let json = serde_json::to_value(&map).unwrap();
+        assert_eq!(json["nodes"][1]["ieee_addr"], "00158d0001020304");
+        assert_eq!(json["nodes"][1]["logical_type"], "end_device");
+        assert_eq!(json["links"][0]["relationship"], "child");
+    }
+}
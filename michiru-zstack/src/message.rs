@@ -0,0 +1,227 @@
+use std::fmt;
+
+use nom::IResult;
+
+/// Start of frame
+pub const SOF: u8 = 0xfe;
+
+/// Maximum length of the data field of a frame
+pub const MAX_DATA_LEN: usize = 250;
+
+/// Command id of the SRSP Z-Stack sends for requests it can't handle, its
+/// subsystem (RES0) isn't a [`Subsystem`]
+pub const RPC_ERROR: [u8; 2] = [0x60, 0x00];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    command_id: [u8; 2],
+    data: Vec<u8>,
+    check: u8,
+}
+
+impl Message {
+    pub fn new(cmd_type: CmdType, subsystem: Subsystem, cmd_id: u8, data: Vec<u8>) -> Self {
+        let mut message = Message {
+            command_id: [u8::from(cmd_type) << 5 | u8::from(subsystem), cmd_id],
+            data,
+            check: 0,
+        };
+
+        message.check = message.fcs();
+        message
+    }
+
+    /// The SRSP rejecting the request with command id `request`
+    pub fn rpc_error(code: u8, request: [u8; 2]) -> Self {
+        let mut message = Message {
+            command_id: RPC_ERROR,
+            data: vec![code, request[0], request[1]],
+            check: 0,
+        };
+
+        message.check = message.fcs();
+        message
+    }
+
+    pub fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        use nom::{bytes::streaming::*, number::streaming::*, sequence::*};
+
+        let (src, len) = preceded(tag([SOF]), u8)(src)?;
+        let (src, (command_id, data, check)) = tuple((be_u16, take(len), u8))(src)?;
+
+        Ok((src, Message {
+            command_id: command_id.to_be_bytes(),
+            data: data.to_vec(),
+            check,
+        }))
+    }
+
+    /// Frame check sequence over the length, command and data fields
+    pub fn fcs(&self) -> u8 {
+        let mut check = 0u8;
+        check ^= self.command_id[0];
+        check ^= self.command_id[1];
+        check ^= self.data.len() as u8;
+        self.data.iter().fold(check, |acc, b| acc ^ b)
+    }
+
+    pub fn verify(&self) -> Result<(), InvalidFcs> {
+        if self.fcs() == self.check {
+            Ok(())
+        } else {
+            Err(InvalidFcs {
+                expected: self.fcs(),
+                actual: self.check,
+            })
+        }
+    }
+
+    pub fn command_id(&self) -> [u8; 2] {
+        self.command_id
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn check(&self) -> u8 {
+        self.check
+    }
+
+    pub fn cmd_type(&self) -> Result<CmdType, UnknownValue> {
+        CmdType::try_from(self.command_id[0] >> 5)
+    }
+
+    pub fn subsystem(&self) -> Result<Subsystem, UnknownValue> {
+        Subsystem::try_from(self.command_id[0] & 0x1f)
+    }
+
+    pub fn cmd_id(&self) -> u8 {
+        self.command_id[1]
+    }
+
+    pub fn is_rpc_error(&self) -> bool {
+        self.command_id == RPC_ERROR
+    }
+
+    /// Command id of the request an RPC error rejects
+    pub fn rpc_error_request(&self) -> Option<[u8; 2]> {
+        if !self.is_rpc_error() {
+            return None;
+        }
+
+        self.data.get(1..3)?.try_into().ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidFcs {
+    pub expected: u8,
+    pub actual: u8,
+}
+
+impl fmt::Display for InvalidFcs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid FCS {:#04x}, expected {:#04x}", self.actual, self.expected)
+    }
+}
+
+impl std::error::Error for InvalidFcs {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownValue(pub u8);
+
+impl fmt::Display for UnknownValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown value {:#04x}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownValue {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CmdType {
+    Poll,
+    SyncRequest,
+    AsyncRequest,
+    SyncResponse,
+}
+
+impl TryFrom<u8> for CmdType {
+    type Error = UnknownValue;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(CmdType::Poll),
+            0x01 => Ok(CmdType::SyncRequest),
+            0x02 => Ok(CmdType::AsyncRequest),
+            0x03 => Ok(CmdType::SyncResponse),
+            _ => Err(UnknownValue(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    Sys,
+    Mac,
+    Nwk,
+    Af,
+    Zdo,
+    Sapi,
+    Util,
+    Debug,
+    App,
+    AppConfig,
+    GreenPower,
+}
+
+impl From<CmdType> for u8 {
+    fn from(value: CmdType) -> Self {
+        match value {
+            CmdType::Poll => 0x00,
+            CmdType::SyncRequest => 0x01,
+            CmdType::AsyncRequest => 0x02,
+            CmdType::SyncResponse => 0x03,
+        }
+    }
+}
+
+impl TryFrom<u8> for Subsystem {
+    type Error = UnknownValue;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Subsystem::Sys),
+            0x02 => Ok(Subsystem::Mac),
+            0x03 => Ok(Subsystem::Nwk),
+            0x04 => Ok(Subsystem::Af),
+            0x05 => Ok(Subsystem::Zdo),
+            0x06 => Ok(Subsystem::Sapi),
+            0x07 => Ok(Subsystem::Util),
+            0x08 => Ok(Subsystem::Debug),
+            0x09 => Ok(Subsystem::App),
+            0x0f => Ok(Subsystem::AppConfig),
+            0x15 => Ok(Subsystem::GreenPower),
+            _ => Err(UnknownValue(value)),
+        }
+    }
+}
+
+impl From<Subsystem> for u8 {
+    fn from(value: Subsystem) -> Self {
+        match value {
+            Subsystem::Sys => 0x01,
+            Subsystem::Mac => 0x02,
+            Subsystem::Nwk => 0x03,
+            Subsystem::Af => 0x04,
+            Subsystem::Zdo => 0x05,
+            Subsystem::Sapi => 0x06,
+            Subsystem::Util => 0x07,
+            Subsystem::Debug => 0x08,
+            Subsystem::App => 0x09,
+            Subsystem::AppConfig => 0x0f,
+            Subsystem::GreenPower => 0x15,
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Requests to remote devices on the network
+//!
+//! ZDO requests are answered asynchronously, and ZCL frames travel through
+//! `AF_DATA_REQUEST` / `AF_INCOMING_MSG`. The helpers here correlate the
+//! answers with their request.
+
+use std::{
+    any::type_name,
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use futures::{stream::BoxStream, StreamExt};
+
+use crate::{
+    client::ZStack,
+    commands::{af, zdo},
+    zcl::{
+        global::{ReadAttributeStatus, ReportingConfiguration},
+        Direction, Frame, GlobalCommand,
+    },
+};
+
+/// Endpoint registered by the coordinator, used as source of all frames
+pub const COORDINATOR_ENDPOINT: u8 = 1;
+
+/// Remote devices, sleepy ones in particular, can take a while to answer
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_RADIUS: u8 = 30;
+
+static SEQUENCE: AtomicU8 = AtomicU8::new(1);
+
+/// Next transaction sequence number, used for both AF and ZCL
+pub fn next_sequence() -> u8 {
+    SEQUENCE.fetch_add(1, Ordering::Relaxed)
+}
+
+pub async fn node_descriptor(zstack: &ZStack, nwk_addr: u16) -> Result<zdo::NodeDescriptor> {
+    let mut responses = zstack.indications::<zdo::NodeDescRsp>();
+
+    zstack
+        .request(&zdo::NodeDescReq {
+            dst_addr: nwk_addr,
+            nwk_addr_of_interest: nwk_addr,
+        })
+        .await?
+        .status
+        .ok()?;
+
+    let response = wait_for(&mut responses, |r| r.src_addr == nwk_addr).await?;
+    response.status.ok()?;
+
+    response.descriptor.context("Node descriptor missing")
+}
+
+pub async fn active_endpoints(zstack: &ZStack, nwk_addr: u16) -> Result<Vec<u8>> {
+    let mut responses = zstack.indications::<zdo::ActiveEpRsp>();
+
+    zstack
+        .request(&zdo::ActiveEpReq {
+            dst_addr: nwk_addr,
+            nwk_addr_of_interest: nwk_addr,
+        })
+        .await?
+        .status
+        .ok()?;
+
+    let response = wait_for(&mut responses, |r| r.src_addr == nwk_addr).await?;
+    response.status.ok()?;
+
+    Ok(response.active_eps)
+}
+
+pub async fn simple_descriptor(
+    zstack: &ZStack,
+    nwk_addr: u16,
+    endpoint: u8,
+) -> Result<zdo::SimpleDescriptor> {
+    let mut responses = zstack.indications::<zdo::SimpleDescRsp>();
+
+    zstack
+        .request(&zdo::SimpleDescReq {
+            dst_addr: nwk_addr,
+            nwk_addr_of_interest: nwk_addr,
+            endpoint,
+        })
+        .await?
+        .status
+        .ok()?;
+
+    let response = wait_for(&mut responses, |r| {
+        r.src_addr == nwk_addr && !matches!(&r.descriptor, Some(d) if d.endpoint != endpoint)
+    })
+    .await?;
+    response.status.ok()?;
+
+    response
+        .descriptor
+        .with_context(|| format!("Simple descriptor for endpoint {endpoint} missing"))
+}
+
+/// Opens the network for joining through all routers, 0 closes it again
+pub async fn permit_join(zstack: &ZStack, duration: u8) -> Result<()> {
+    zstack
+        .request(&zdo::MgmtPermitJoinReq {
+            addr_mode: zdo::ADDR_MODE_BROADCAST,
+            dst_addr: zdo::BROADCAST_ROUTERS,
+            duration,
+            tc_significance: 0,
+        })
+        .await?
+        .status
+        .ok()
+        .context("Failed to permit joining")
+}
+
+/// Asks a device to leave the network, without rejoining
+pub async fn leave(zstack: &ZStack, nwk_addr: u16, ieee_addr: u64) -> Result<()> {
+    let mut responses = zstack.indications::<zdo::MgmtLeaveRsp>();
+
+    zstack
+        .request(&zdo::MgmtLeaveReq {
+            dst_addr: nwk_addr,
+            device_address: ieee_addr,
+            remove_children_rejoin: 0,
+        })
+        .await?
+        .status
+        .ok()?;
+
+    wait_for(&mut responses, |r| r.src_addr == nwk_addr)
+        .await?
+        .status
+        .ok()
+        .with_context(|| format!("Device {ieee_addr:016x} refused to leave"))
+}
+
+/// Reads the whole neighbor table of a router
+pub async fn neighbors(zstack: &ZStack, nwk_addr: u16) -> Result<Vec<zdo::Neighbor>> {
+    let mut neighbors = vec![];
+
+    loop {
+        let mut responses = zstack.indications::<zdo::MgmtLqiRsp>();
+        let start_index = neighbors.len() as u8;
+
+        zstack
+            .request(&zdo::MgmtLqiReq { dst_addr: nwk_addr, start_index })
+            .await?
+            .status
+            .ok()?;
+
+        let response =
+            wait_for(&mut responses, |r| r.src_addr == nwk_addr && r.start_index == start_index)
+                .await?;
+        response.status.ok()?;
+
+        if response.neighbors.is_empty() {
+            break;
+        }
+
+        neighbors.extend(response.neighbors);
+
+        if neighbors.len() >= response.neighbor_table_entries as usize {
+            break;
+        }
+    }
+
+    Ok(neighbors)
+}
+
+/// Reads the whole routing table of a router
+pub async fn routes(zstack: &ZStack, nwk_addr: u16) -> Result<Vec<zdo::Route>> {
+    let mut routes = vec![];
+
+    loop {
+        let mut responses = zstack.indications::<zdo::MgmtRtgRsp>();
+        let start_index = routes.len() as u8;
+
+        zstack
+            .request(&zdo::MgmtRtgReq { dst_addr: nwk_addr, start_index })
+            .await?
+            .status
+            .ok()?;
+
+        let response =
+            wait_for(&mut responses, |r| r.src_addr == nwk_addr && r.start_index == start_index)
+                .await?;
+        response.status.ok()?;
+
+        if response.routes.is_empty() {
+            break;
+        }
+
+        routes.extend(response.routes);
+
+        if routes.len() >= response.routing_table_entries as usize {
+            break;
+        }
+    }
+
+    Ok(routes)
+}
+
+/// Binds a cluster of a remote device to an endpoint identified by IEEE
+/// address, so reports are sent there
+pub async fn bind(
+    zstack: &ZStack,
+    nwk_addr: u16,
+    src_address: u64,
+    src_endpoint: u8,
+    cluster_id: u16,
+    dst_address: u64,
+    dst_endpoint: u8,
+) -> Result<()> {
+    let mut responses = zstack.indications::<zdo::BindRsp>();
+
+    zstack
+        .request(&zdo::BindReq {
+            dst_addr: nwk_addr,
+            src_address,
+            src_endpoint,
+            cluster_id,
+            dst_addr_mode: zdo::ADDR_MODE_IEEE,
+            dst_address,
+            dst_endpoint,
+        })
+        .await?
+        .status
+        .ok()?;
+
+    wait_for(&mut responses, |r| r.src_addr == nwk_addr)
+        .await?
+        .status
+        .ok()
+        .with_context(|| format!("Failed to bind cluster {cluster_id:#06x}"))
+}
+
+/// Sends a ZCL frame and waits until the network layer confirms delivery
+pub async fn send_zcl(
+    zstack: &ZStack,
+    nwk_addr: u16,
+    endpoint: u8,
+    cluster_id: u16,
+    frame: &Frame,
+) -> Result<()> {
+    let trans_id = next_sequence();
+    let mut confirms = zstack.indications::<af::DataConfirm>();
+
+    zstack
+        .request(&af::DataRequest {
+            dst_addr: nwk_addr,
+            dst_endpoint: endpoint,
+            src_endpoint: COORDINATOR_ENDPOINT,
+            cluster_id,
+            trans_id,
+            options: 0,
+            radius: DEFAULT_RADIUS,
+            data: frame.serialize(),
+        })
+        .await?
+        .status
+        .ok()?;
+
+    wait_for(&mut confirms, |c| c.trans_id == trans_id)
+        .await?
+        .status
+        .ok()
+        .with_context(|| format!("Failed to deliver frame to {nwk_addr:#06x}"))
+}
+
+/// Sends a ZCL frame and waits for the frame answering it
+pub async fn zcl_request(
+    zstack: &ZStack,
+    nwk_addr: u16,
+    endpoint: u8,
+    cluster_id: u16,
+    frame: &Frame,
+) -> Result<Frame> {
+    let mut incoming = zstack.indications::<af::IncomingMsg>();
+
+    send_zcl(zstack, nwk_addr, endpoint, cluster_id, frame).await?;
+
+    let sequence = frame.header.sequence;
+
+    tokio::time::timeout(RESPONSE_TIMEOUT, async {
+        while let Some(message) = incoming.next().await {
+            if message.src_addr != nwk_addr || message.cluster_id != cluster_id {
+                continue;
+            }
+
+            let Ok(response) = Frame::parse(&message.data) else {
+                continue;
+            };
+
+            if response.header.sequence == sequence
+                && response.header.direction == Direction::ServerToClient
+            {
+                return Ok(response);
+            }
+        }
+
+        bail!("Z-Stack connection closed")
+    })
+    .await
+    .with_context(|| format!("Timed out waiting for ZCL response from {nwk_addr:#06x}"))?
+}
+
+pub async fn read_attributes(
+    zstack: &ZStack,
+    nwk_addr: u16,
+    endpoint: u8,
+    cluster_id: u16,
+    attributes: &[u16],
+) -> Result<Vec<ReadAttributeStatus>> {
+    let frame = Frame::global(next_sequence(), &GlobalCommand::ReadAttributes(attributes.to_vec()));
+
+    let response = zcl_request(zstack, nwk_addr, endpoint, cluster_id, &frame).await?;
+
+    match response.global_command()? {
+        GlobalCommand::ReadAttributesResponse(records) => Ok(records),
+        GlobalCommand::DefaultResponse { status, .. } => {
+            status.ok()?;
+            bail!("Unexpected default response to read attributes")
+        }
+        other => bail!("Unexpected response to read attributes: {other:?}"),
+    }
+}
+
+pub async fn configure_reporting(
+    zstack: &ZStack,
+    nwk_addr: u16,
+    endpoint: u8,
+    cluster_id: u16,
+    configurations: Vec<ReportingConfiguration>,
+) -> Result<()> {
+    let frame = Frame::global(next_sequence(), &GlobalCommand::ConfigureReporting(configurations));
+
+    let response = zcl_request(zstack, nwk_addr, endpoint, cluster_id, &frame).await?;
+
+    match response.global_command()? {
+        GlobalCommand::ConfigureReportingResponse(records) => {
+            for record in records {
+                record.status.ok().with_context(|| match record.attribute {
+                    Some(attribute) => format!("Failed to configure reporting of {attribute:#06x}"),
+                    None => "Failed to configure reporting".into(),
+                })?;
+            }
+
+            Ok(())
+        }
+        GlobalCommand::DefaultResponse { status, .. } => status.ok(),
+        other => bail!("Unexpected response to configure reporting: {other:?}"),
+    }
+}
+
+/// Waits for the first item matching `predicate`
+async fn wait_for<C>(
+    stream: &mut BoxStream<'static, C>,
+    mut predicate: impl FnMut(&C) -> bool,
+) -> Result<C> {
+    tokio::time::timeout(RESPONSE_TIMEOUT, async {
+        while let Some(item) = stream.next().await {
+            if predicate(&item) {
+                return Ok(item);
+            }
+        }
+
+        bail!("Z-Stack connection closed")
+    })
+    .await
+    .with_context(|| format!("Timed out waiting for {}", type_name::<C>()))?
+}
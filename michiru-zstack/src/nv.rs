@@ -0,0 +1,295 @@
+//! OSAL non-volatile memory items and helpers to access them
+
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    client::ZStack,
+    commands::{sys, LongBytes, Status},
+};
+
+pub const EXTADDR: u16 = 0x0001;
+pub const STARTUP_OPTION: u16 = 0x0003;
+pub const NIB: u16 = 0x0021;
+pub const EXTENDED_PAN_ID: u16 = 0x002d;
+pub const NWK_ACTIVE_KEY_INFO: u16 = 0x003a;
+/// Z-Stack 3.x on CC2530/CC2531 keeps the outgoing frame counters here
+pub const LEGACY_NWK_SEC_MATERIAL_TABLE_START: u16 = 0x0075;
+pub const LEGACY_NWK_SEC_MATERIAL_TABLE_END: u16 = 0x0080;
+pub const NWKKEY: u16 = 0x0082;
+pub const PRECFGKEY: u16 = 0x0062;
+pub const PRECFGKEYS_ENABLE: u16 = 0x0063;
+pub const PANID: u16 = 0x0083;
+pub const CHANLIST: u16 = 0x0084;
+pub const LOGICAL_TYPE: u16 = 0x0087;
+pub const ZDO_DIRECT_CB: u16 = 0x008f;
+pub const ADDRMGR: u16 = 0x00c0;
+pub const TCLK_SEED: u16 = 0x0101;
+
+/// System id of the Z-Stack items in the extended NV of CC13xx/CC26xx firmwares
+pub const EX_SYS_ID_ZSTACK: u8 = 0x01;
+/// Extended item holding one address manager entry per sub id
+pub const EX_ADDRMGR: u16 = 0x0001;
+
+/// Not a Z-Stack item, marks that michiru has configured the device
+pub const MICHIRU_CONFIGURED: u16 = 0x0f00;
+pub const MICHIRU_CONFIGURED_VALUE: u8 = 0x55;
+
+pub const STARTUP_OPTION_CLEAR_STATE: u8 = 0x02;
+pub const LOGICAL_TYPE_COORDINATOR: u8 = 0x00;
+
+/// Largest chunk that fits in a single MT frame along with the other fields
+const CHUNK_SIZE: usize = 240;
+
+/// Reads a whole item, or `None` if it doesn't exist
+///
+/// Items longer than a single frame are read in chunks, past the first 255
+/// bytes with the 16-bit offset of the extended commands.
+pub async fn read(zstack: &ZStack, id: u16) -> Result<Option<Vec<u8>>> {
+    let length = zstack.request(&sys::OsalNvLength { id }).await?.length as usize;
+    if length == 0 {
+        return Ok(None);
+    }
+
+    let mut value = Vec::with_capacity(length);
+    while value.len() < length {
+        let (status, chunk) = match u8::try_from(value.len()) {
+            Ok(offset) => {
+                let response = zstack.request(&sys::OsalNvRead { id, offset }).await?;
+                (response.status, response.value)
+            }
+            Err(_) => {
+                let offset = value.len() as u16;
+                let response = zstack.request(&sys::OsalNvReadExt { id, offset }).await?;
+                (response.status, response.value)
+            }
+        };
+
+        match status {
+            Status::SUCCESS => {}
+            Status::NV_ITEM_UNINIT | Status::INVALID_PARAMETER => return Ok(None),
+            status => bail!("Failed to read NV item {id:#06x}: {status}"),
+        }
+        ensure!(!chunk.is_empty(), "Empty read of NV item {id:#06x}");
+
+        value.extend_from_slice(&chunk);
+    }
+    value.truncate(length);
+
+    Ok(Some(value))
+}
+
+/// Writes an item, creating it first if needed
+pub async fn write(zstack: &ZStack, id: u16, value: &[u8]) -> Result<()> {
+    ensure!(
+        value.len() <= u16::MAX as usize,
+        "NV item {id:#06x} is too long ({} bytes)",
+        value.len()
+    );
+    let response = zstack
+        .request(&sys::OsalNvItemInit {
+            id,
+            item_len: value.len() as u16,
+            init_data: value[..value.len().min(CHUNK_SIZE)].to_vec(),
+        })
+        .await?;
+
+    ensure!(
+        matches!(response.status, Status::SUCCESS | Status::NV_ITEM_UNINIT),
+        "Failed to create NV item {id:#06x}: {}",
+        response.status
+    );
+
+    for (i, chunk) in value.chunks(CHUNK_SIZE).enumerate() {
+        let status = match u8::try_from(i * CHUNK_SIZE) {
+            Ok(offset) => {
+                zstack
+                    .request(&sys::OsalNvWrite { id, offset, value: chunk.to_vec() })
+                    .await?
+                    .status
+            }
+            Err(_) => {
+                zstack
+                    .request(&sys::OsalNvWriteExt {
+                        id,
+                        offset: (i * CHUNK_SIZE) as u16,
+                        value: LongBytes(chunk.to_vec()),
+                    })
+                    .await?
+                    .status
+            }
+        };
+
+        ensure!(status.is_success(), "Failed to write NV item {id:#06x}: {status}");
+    }
+
+    Ok(())
+}
+
+/// Reads an extended item, or `None` if it doesn't exist
+pub async fn read_ex(zstack: &ZStack, item_id: u16, sub_id: u16) -> Result<Option<Vec<u8>>> {
+    let sys_id = EX_SYS_ID_ZSTACK;
+    let length = zstack
+        .request(&sys::NvLength { sys_id, item_id, sub_id })
+        .await?
+        .length as usize;
+    if length == 0 {
+        return Ok(None);
+    }
+
+    let mut value = Vec::with_capacity(length);
+    while value.len() < length {
+        let response = zstack
+            .request(&sys::NvRead {
+                sys_id,
+                item_id,
+                sub_id,
+                offset: value.len() as u16,
+                length: (length - value.len()).min(CHUNK_SIZE) as u8,
+            })
+            .await?;
+
+        ensure!(
+            response.status.is_success() && !response.value.is_empty(),
+            "Failed to read NV item {item_id:#06x}/{sub_id:#06x}: {}",
+            response.status
+        );
+
+        value.extend_from_slice(&response.value);
+    }
+
+    Ok(Some(value))
+}
+
+/// Writes an extended item, creating it first if needed
+pub async fn write_ex(zstack: &ZStack, item_id: u16, sub_id: u16, value: &[u8]) -> Result<()> {
+    let sys_id = EX_SYS_ID_ZSTACK;
+    // fails if the item already exists, the writes below tell whether it's usable
+    zstack
+        .request(&sys::NvCreate {
+            sys_id,
+            item_id,
+            sub_id,
+            length: value.len() as u32,
+        })
+        .await?;
+
+    for (i, chunk) in value.chunks(CHUNK_SIZE).enumerate() {
+        let response = zstack
+            .request(&sys::NvWrite {
+                sys_id,
+                item_id,
+                sub_id,
+                offset: (i * CHUNK_SIZE) as u16,
+                value: chunk.to_vec(),
+            })
+            .await?;
+
+        ensure!(
+            response.status.is_success(),
+            "Failed to write NV item {item_id:#06x}/{sub_id:#06x}: {}",
+            response.status
+        );
+    }
+
+    Ok(())
+}
+
+/// Answers the OSAL NV commands from `items`
+#[cfg(test)]
+pub(crate) fn simulate(
+    simulator: crate::transport::simulator::Simulator,
+    items: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<u16, Vec<u8>>>>,
+) -> crate::transport::simulator::Simulator {
+    fn read(item: Option<&Vec<u8>>, offset: usize) -> (Status, Vec<u8>) {
+        match item {
+            Some(item) => {
+                let end = item.len().min(offset + CHUNK_SIZE);
+                (Status::SUCCESS, item[offset.min(end)..end].to_vec())
+            }
+            None => (Status::INVALID_PARAMETER, vec![]),
+        }
+    }
+
+    fn write(item: Option<&mut Vec<u8>>, offset: usize, value: &[u8]) -> Status {
+        match item {
+            Some(item) if offset + value.len() <= item.len() => {
+                item[offset..offset + value.len()].copy_from_slice(value);
+                Status::SUCCESS
+            }
+            _ => Status::INVALID_PARAMETER,
+        }
+    }
+
+    let length_items = items.clone();
+    let init_items = items.clone();
+    let read_items = items.clone();
+    let read_ext_items = items.clone();
+    let write_items = items.clone();
+    let write_ext_items = items;
+
+    simulator
+        .respond(move |request: sys::OsalNvLength| sys::OsalNvLengthResponse {
+            length: length_items
+                .lock()
+                .unwrap()
+                .get(&request.id)
+                .map_or(0, |item| item.len() as u16),
+        })
+        .respond(move |request: sys::OsalNvItemInit| {
+            let mut items = init_items.lock().unwrap();
+            let status = match items.contains_key(&request.id) {
+                true => Status::SUCCESS,
+                false => Status::NV_ITEM_UNINIT,
+            };
+            items
+                .entry(request.id)
+                .or_insert_with(|| vec![0; request.item_len as usize]);
+            sys::OsalNvItemInitResponse { status }
+        })
+        .respond(move |request: sys::OsalNvRead| {
+            let items = read_items.lock().unwrap();
+            let (status, value) = read(items.get(&request.id), request.offset as usize);
+            sys::OsalNvReadResponse { status, value }
+        })
+        .respond(move |request: sys::OsalNvReadExt| {
+            let items = read_ext_items.lock().unwrap();
+            let (status, value) = read(items.get(&request.id), request.offset as usize);
+            sys::OsalNvReadExtResponse { status, value }
+        })
+        .respond(move |request: sys::OsalNvWrite| {
+            let mut items = write_items.lock().unwrap();
+            let status = write(items.get_mut(&request.id), request.offset as usize, &request.value);
+            sys::OsalNvWriteResponse { status }
+        })
+        .respond(move |request: sys::OsalNvWriteExt| {
+            let mut items = write_ext_items.lock().unwrap();
+            let status =
+                write(items.get_mut(&request.id), request.offset as usize, &request.value.0);
+            sys::OsalNvWriteExtResponse { status }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::transport::simulator::Simulator;
+
+    #[tokio::test]
+    async fn long_item() {
+        let items = Arc::new(Mutex::new(HashMap::new()));
+        let (transport, _) = simulate(Simulator::new(), items.clone()).start();
+        let zstack = ZStack::new(transport);
+
+        // a full address manager table
+        let value: Vec<u8> = (0..11 * 60).map(|i| i as u8).collect();
+        write(&zstack, ADDRMGR, &value).await.unwrap();
+        assert_eq!(items.lock().unwrap()[&ADDRMGR], value);
+        assert_eq!(read(&zstack, ADDRMGR).await.unwrap(), Some(value));
+        assert_eq!(read(&zstack, NIB).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,226 @@
+//! Bindings and attribute reporting
+//!
+//! Sleepy sensors only send updates when reporting is configured, and the
+//! reports only reach the coordinator if the cluster is bound to it.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    client::ZStack,
+    database::Device,
+    network,
+    zcl::{
+        clusters::{
+            level_control, occupancy_sensing, on_off, power_configuration, relative_humidity,
+            temperature_measurement,
+        },
+        global::ReportingConfiguration,
+        value::data_type,
+        Value,
+    },
+};
+
+/// Reporting configuration of a single attribute
+///
+/// A `max_interval` of `0xffff` disables reporting of the attribute.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Reporting {
+    pub cluster: u16,
+    pub attribute: u16,
+    pub data_type: u8,
+    pub min_interval: u16,
+    pub max_interval: u16,
+    /// Raw attribute units, only used for analog data types
+    #[serde(default)]
+    pub reportable_change: Option<i64>,
+}
+
+impl Reporting {
+    fn configuration(&self) -> ReportingConfiguration {
+        let reportable_change = if data_type::is_analog(self.data_type) {
+            Value::from_i64(self.data_type, self.reportable_change.unwrap_or(0))
+        } else {
+            None
+        };
+
+        ReportingConfiguration {
+            attribute: self.attribute,
+            data_type: self.data_type,
+            min_interval: self.min_interval,
+            max_interval: self.max_interval,
+            reportable_change,
+        }
+    }
+}
+
+pub fn defaults() -> Vec<Reporting> {
+    let reporting = |cluster, attribute, data_type, min_interval, max_interval, change| Reporting {
+        cluster,
+        attribute,
+        data_type,
+        min_interval,
+        max_interval,
+        reportable_change: change,
+    };
+
+    vec![
+        reporting(on_off::ID, on_off::ON_OFF, data_type::BOOL, 0, 3600, None),
+        reporting(
+            level_control::ID,
+            level_control::CURRENT_LEVEL,
+            data_type::UINT8,
+            1,
+            3600,
+            Some(1),
+        ),
+        reporting(
+            temperature_measurement::ID,
+            temperature_measurement::MEASURED_VALUE,
+            data_type::INT16,
+            10,
+            3600,
+            Some(10),
+        ),
+        reporting(
+            relative_humidity::ID,
+            relative_humidity::MEASURED_VALUE,
+            data_type::UINT16,
+            10,
+            3600,
+            Some(100),
+        ),
+        reporting(
+            occupancy_sensing::ID,
+            occupancy_sensing::OCCUPANCY,
+            data_type::BITMAP8,
+            0,
+            3600,
+            None,
+        ),
+        reporting(
+            power_configuration::ID,
+            power_configuration::BATTERY_PERCENTAGE_REMAINING,
+            data_type::UINT8,
+            3600,
+            43200,
+            Some(2),
+        ),
+    ]
+}
+
+/// Defaults for the clusters the device implements, with `overrides`
+/// replacing entries for the same attribute
+pub fn for_device(device: &Device, overrides: &[Reporting]) -> Vec<Reporting> {
+    let mut reportings = BTreeMap::new();
+
+    for reporting in defaults().into_iter().chain(overrides.iter().cloned()) {
+        if device.endpoint_with(reporting.cluster).is_some() {
+            reportings.insert((reporting.cluster, reporting.attribute), reporting);
+        }
+    }
+
+    reportings.into_values().collect()
+}
+
+/// Binds every reported cluster to the coordinator and configures reporting
+///
+/// All clusters are attempted even if one fails.
+pub async fn configure(
+    zstack: &ZStack,
+    coordinator_ieee: u64,
+    device: &Device,
+    reportings: &[Reporting],
+) -> Result<()> {
+    let mut clusters = BTreeMap::<u16, Vec<ReportingConfiguration>>::new();
+    for reporting in reportings {
+        clusters
+            .entry(reporting.cluster)
+            .or_default()
+            .push(reporting.configuration());
+    }
+
+    let mut failed = vec![];
+
+    for (cluster, configurations) in clusters {
+        let result = configure_cluster(zstack, coordinator_ieee, device, cluster, configurations)
+            .await
+            .with_context(|| format!("cluster {cluster:#06x}"));
+
+        if let Err(e) = result {
+            tracing::warn!(
+                ieee_addr = format!("{:016x}", device.ieee_addr),
+                "Failed to configure reporting: {e:#}"
+            );
+            failed.push(cluster);
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Failed to configure clusters {failed:04x?}"))
+    }
+}
+
+async fn configure_cluster(
+    zstack: &ZStack,
+    coordinator_ieee: u64,
+    device: &Device,
+    cluster: u16,
+    configurations: Vec<ReportingConfiguration>,
+) -> Result<()> {
+    let endpoint = device
+        .endpoint_with(cluster)
+        .context("no endpoint implements the cluster")?
+        .endpoint;
+
+    network::bind(
+        zstack,
+        device.nwk_addr,
+        device.ieee_addr,
+        endpoint,
+        cluster,
+        coordinator_ieee,
+        network::COORDINATOR_ENDPOINT,
+    )
+    .await?;
+
+    network::configure_reporting(zstack, device.nwk_addr, endpoint, cluster, configurations).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Endpoint;
+
+    #[test]
+    fn overrides() {
+        let mut device = Device::new(0x00158d0001020304, 0x1234);
+        device.endpoints.push(Endpoint {
+            endpoint: 1,
+            profile_id: 0x0104,
+            device_id: 0x0302,
+            in_clusters: vec![0x0000, 0x0001, 0x0402],
+            out_clusters: vec![],
+        });
+
+        let overrides = [Reporting {
+            cluster: temperature_measurement::ID,
+            attribute: temperature_measurement::MEASURED_VALUE,
+            data_type: data_type::INT16,
+            min_interval: 60,
+            max_interval: 600,
+            reportable_change: Some(50),
+        }];
+
+        let reportings = for_device(&device, &overrides);
+        assert_eq!(reportings.len(), 2);
+        assert_eq!(reportings[0].cluster, power_configuration::ID);
+        assert_eq!(reportings[1], overrides[0]);
+
+        assert_eq!(reportings[1].configuration().reportable_change, Some(Value::Int16(50)));
+    }
+}
@@ -0,0 +1,163 @@
+//! Byte streams to a Z-Stack device
+//!
+//! The client only needs something to read and write MT frames from, which
+//! is a USB serial port for a CC2531 plugged into the machine, a TCP socket
+//! for one shared over the network with ser2net, or the [`simulator`] in
+//! tests. A PTY, e.g. one created by socat, is opened like a serial port.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_serial::{SerialPortBuilderExt, SerialPortType, SerialStream, UsbPortInfo};
+
+pub mod simulator;
+
+/// USB ids of the CC2531 running TI's Z-Stack firmware
+pub const CC2531_VID: u16 = 0x0451;
+pub const CC2531_PID: u16 = 0x16a8;
+
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+#[derive(Debug, Clone)]
+pub enum TransportConfig {
+    Serial(SerialConfig),
+    Tcp(TcpConfig),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SerialConfig {
+    /// Serial port or PTY to use, searched by USB ids if unset
+    pub path: Option<PathBuf>,
+    /// USB vendor id to search for, a CC2531 if unset
+    pub vid: Option<u16>,
+    /// USB product id to search for, any if unset
+    pub pid: Option<u16>,
+    pub baud_rate: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TcpConfig {
+    /// `host:port` of the socket, e.g. a ser2net raw port
+    pub address: String,
+}
+
+impl TransportConfig {
+    pub async fn open(&self) -> Result<Box<dyn Transport>> {
+        Ok(match self {
+            Self::Serial(config) => Box::new(config.open()?),
+            Self::Tcp(config) => Box::new(config.connect().await?),
+        })
+    }
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            vid: None,
+            pid: None,
+            baud_rate: 115200,
+        }
+    }
+}
+
+impl SerialConfig {
+    pub fn open(&self) -> Result<SerialStream> {
+        let path = match &self.path {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None => self.find_port()?,
+        };
+
+        tracing::debug!(path, baud_rate = self.baud_rate, "Opening serial port");
+
+        tokio_serial::new(&path, self.baud_rate)
+            .open_native_async()
+            .with_context(|| format!("Failed to open serial port {path}"))
+    }
+
+    fn find_port(&self) -> Result<String> {
+        let ports = tokio_serial::available_ports().context("No serial ports found")?;
+
+        let port = ports.into_iter().find(|port| {
+            let SerialPortType::UsbPort(UsbPortInfo { vid, pid, ref product, .. }) = port.port_type
+            else {
+                return false;
+            };
+
+            match self.vid {
+                Some(wanted) => vid == wanted && self.pid.unwrap_or(pid) == pid,
+                // older firmwares report other ids, but keep the product name
+                None => {
+                    (vid, pid) == (CC2531_VID, CC2531_PID)
+                        || product.as_ref().is_some_and(|p| p.contains("CC2531"))
+                }
+            }
+        });
+
+        match (port, self.vid) {
+            (Some(port), _) => Ok(port.port_name),
+            (None, Some(vid)) => anyhow::bail!(
+                "No USB serial port with id {vid:04x}:{}",
+                self.pid.map_or("*".into(), |pid| format!("{pid:04x}"))
+            ),
+            (None, None) => anyhow::bail!("No CC2531 found"),
+        }
+    }
+}
+
+impl TcpConfig {
+    pub async fn connect(&self) -> Result<TcpStream> {
+        tracing::debug!(address = self.address, "Connecting");
+
+        let stream = TcpStream::connect(&self.address)
+            .await
+            .with_context(|| format!("Failed to connect to {}", self.address))?;
+        stream.set_nodelay(true)?;
+
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_util::codec::Framed;
+
+    use super::*;
+    use crate::{
+        client::ZStack,
+        codec::MtCodec,
+        commands::{sys, Command},
+    };
+
+    #[tokio::test]
+    async fn tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = TransportConfig::Tcp(TcpConfig {
+            address: listener.local_addr().unwrap().to_string(),
+        });
+
+        let zstack = ZStack::new(config.open().await.unwrap());
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut device = Framed::new(socket, MtCodec);
+
+        let request = tokio::spawn(async move { zstack.request(&sys::Ping {}).await });
+
+        assert!(sys::Ping::matches(&device.next().await.unwrap().unwrap()));
+        device
+            .send(sys::PingResponse { capabilities: 0x0659 }.to_message())
+            .await
+            .unwrap();
+
+        assert_eq!(request.await.unwrap().unwrap().capabilities, 0x0659);
+    }
+}
@@ -0,0 +1,192 @@
+//! In-memory Z-Stack for tests
+//!
+//! The simulator answers requests with scripted handlers, the first handler
+//! whose command matches a request answers it. Requests nothing handles get
+//! the RPC error Z-Stack sends for unknown commands. Indications can be
+//! injected at any time through the [`SimulatorHandle`].
+
+use std::sync::{Arc, Mutex};
+
+use futures::{SinkExt, StreamExt};
+use tokio::{io::DuplexStream, sync::mpsc};
+use tokio_util::codec::Framed;
+
+use crate::{
+    codec::MtCodec,
+    commands::{Command, Request},
+    message::{CmdType, Message},
+};
+
+/// RPC error code for a command id the firmware doesn't know
+pub const RPC_ERROR_INVALID_COMMAND: u8 = 0x02;
+
+type Handler = Box<dyn FnMut(&Message) -> Option<Vec<Message>> + Send>;
+
+#[derive(Default)]
+pub struct Simulator {
+    handlers: Vec<Handler>,
+}
+
+#[derive(Clone)]
+pub struct SimulatorHandle {
+    indications: mpsc::UnboundedSender<Message>,
+    received: Arc<Mutex<Vec<Message>>>,
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers commands of type `C` with the messages returned by `f`,
+    /// usually the synchronous response followed by indications
+    pub fn on<C, F>(mut self, mut f: F) -> Self
+    where
+        C: Command,
+        F: FnMut(C) -> Vec<Message> + Send + 'static,
+    {
+        self.handlers.push(Box::new(move |message| {
+            if !C::matches(message) {
+                return None;
+            }
+
+            match C::from_message(message) {
+                Ok(command) => Some(f(command)),
+                Err(e) => {
+                    tracing::warn!("Simulator failed to parse request: {e:#}");
+                    None
+                }
+            }
+        }));
+        self
+    }
+
+    /// Answers requests of type `R` with a single synchronous response
+    pub fn respond<R, F>(self, mut f: F) -> Self
+    where
+        R: Request,
+        F: FnMut(R) -> R::Response + Send + 'static,
+    {
+        self.on(move |request: R| vec![f(request).to_message()])
+    }
+
+    /// Runs the simulator in the background, the returned stream is the
+    /// transport to hand to the client
+    pub fn start(mut self) -> (DuplexStream, SimulatorHandle) {
+        let (client, device) = tokio::io::duplex(1024);
+        let (indications, mut indications_rx) = mpsc::unbounded_channel();
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let handle = SimulatorHandle {
+            indications,
+            received: received.clone(),
+        };
+        let mut device = Framed::new(device, MtCodec);
+
+        tokio::spawn(async move {
+            loop {
+                let replies = tokio::select! {
+                    Some(message) = indications_rx.recv() => vec![message],
+                    message = device.next() => match message {
+                        Some(Ok(message)) => {
+                            received.lock().unwrap().push(message.clone());
+                            self.handle(&message)
+                        }
+                        _ => break,
+                    },
+                };
+
+                for reply in replies {
+                    if device.send(reply).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        (client, handle)
+    }
+
+    fn handle(&mut self, message: &Message) -> Vec<Message> {
+        if let Some(replies) = self
+            .handlers
+            .iter_mut()
+            .find_map(|handler| handler(message))
+        {
+            return replies;
+        }
+
+        match message.cmd_type() {
+            Ok(CmdType::SyncRequest) => {
+                vec![Message::rpc_error(RPC_ERROR_INVALID_COMMAND, message.command_id())]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+impl SimulatorHandle {
+    /// Sends an indication to the client
+    pub fn indicate<C: Command>(&self, command: &C) {
+        let _ = self.indications.send(command.to_message());
+    }
+
+    /// All messages received from the client so far
+    pub fn received(&self) -> Vec<Message> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::ZStack,
+        commands::{af, sys, zdo},
+    };
+
+    #[tokio::test]
+    async fn scripted() {
+        let (transport, handle) = Simulator::new()
+            .respond(|_: sys::Ping| sys::PingResponse { capabilities: 0x0659 })
+            .start();
+        let zstack = ZStack::new(transport);
+
+        assert_eq!(zstack.request(&sys::Ping {}).await.unwrap().capabilities, 0x0659);
+
+        // unhandled requests are rejected like the firmware does
+        let err = zstack
+            .request(&af::Register {
+                endpoint: 1,
+                profile_id: 0x0104,
+                device_id: 0x0005,
+                device_version: 0,
+                latency: 0,
+                in_clusters: vec![],
+                out_clusters: vec![],
+            })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("rejected"), "{err}");
+
+        let mut states = zstack.indications::<zdo::StateChangeInd>();
+        handle.indicate(&zdo::StateChangeInd { state: zdo::DeviceState::Coordinator });
+        assert_eq!(states.next().await.unwrap().state, zdo::DeviceState::Coordinator);
+
+        let received = handle.received();
+        assert_eq!(received.len(), 2);
+        assert!(sys::Ping::matches(&received[0]));
+        assert!(af::Register::matches(&received[1]));
+    }
+
+    #[test]
+    fn rpc_error_frame() {
+        let request = sys::Version {}.to_message();
+        let replies = Simulator::new().handle(&request);
+
+        let [cmd0, cmd1] = request.command_id();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].command_id(), [0x60, 0x00]);
+        assert_eq!(replies[0].data(), [RPC_ERROR_INVALID_COMMAND, cmd0, cmd1]);
+    }
+}
@@ -0,0 +1,268 @@
+//! Cluster, attribute and command definitions
+
+use super::{cluster_command, Value};
+
+/// Human readable cluster name
+pub fn name(cluster_id: u16) -> Option<&'static str> {
+    Some(match cluster_id {
+        basic::ID => "Basic",
+        power_configuration::ID => "Power Configuration",
+        on_off::ID => "On/Off",
+        level_control::ID => "Level Control",
+        color_control::ID => "Color Control",
+        temperature_measurement::ID => "Temperature Measurement",
+        relative_humidity::ID => "Relative Humidity Measurement",
+        occupancy_sensing::ID => "Occupancy Sensing",
+        ias_zone::ID => "IAS Zone",
+        _ => return None,
+    })
+}
+
+pub mod basic {
+    pub const ID: u16 = 0x0000;
+
+    pub const ZCL_VERSION: u16 = 0x0000;
+    pub const APPLICATION_VERSION: u16 = 0x0001;
+    pub const STACK_VERSION: u16 = 0x0002;
+    pub const HW_VERSION: u16 = 0x0003;
+    pub const MANUFACTURER_NAME: u16 = 0x0004;
+    pub const MODEL_IDENTIFIER: u16 = 0x0005;
+    pub const DATE_CODE: u16 = 0x0006;
+    pub const POWER_SOURCE: u16 = 0x0007;
+    pub const SW_BUILD_ID: u16 = 0x4000;
+}
+
+pub mod power_configuration {
+    use super::Value;
+
+    pub const ID: u16 = 0x0001;
+
+    pub const BATTERY_VOLTAGE: u16 = 0x0020;
+    pub const BATTERY_PERCENTAGE_REMAINING: u16 = 0x0021;
+
+    /// Battery voltage in volts, reported in units of 100 mV
+    pub fn battery_voltage(value: &Value) -> Option<f64> {
+        match value.as_i64()? {
+            0xff => None,
+            v => Some(v as f64 / 10.0),
+        }
+    }
+
+    /// Battery percentage, reported in units of 0.5 %
+    pub fn battery_percentage(value: &Value) -> Option<f64> {
+        match value.as_i64()? {
+            0xff => None,
+            v => Some(v as f64 / 2.0),
+        }
+    }
+}
+
+pub mod on_off {
+    use super::cluster_command;
+
+    pub const ID: u16 = 0x0006;
+
+    pub const ON_OFF: u16 = 0x0000;
+
+    cluster_command! {
+        pub struct Off: ID, 0x00, ClientToServer {}
+    }
+
+    cluster_command! {
+        pub struct On: ID, 0x01, ClientToServer {}
+    }
+
+    cluster_command! {
+        pub struct Toggle: ID, 0x02, ClientToServer {}
+    }
+}
+
+pub mod level_control {
+    use super::cluster_command;
+
+    pub const ID: u16 = 0x0008;
+
+    pub const CURRENT_LEVEL: u16 = 0x0000;
+
+    /// Highest level, 0xff is reserved
+    pub const MAX_LEVEL: u8 = 0xfe;
+
+    cluster_command! {
+        pub struct MoveToLevel: ID, 0x00, ClientToServer {
+            pub level: u8,
+            /// In tenths of a second
+            pub transition_time: u16,
+        }
+    }
+
+    cluster_command! {
+        pub struct Move: ID, 0x01, ClientToServer {
+            /// 0 = up, 1 = down
+            pub mode: u8,
+            /// Units per second
+            pub rate: u8,
+        }
+    }
+
+    cluster_command! {
+        pub struct Stop: ID, 0x03, ClientToServer {}
+    }
+
+    cluster_command! {
+        /// Like [`MoveToLevel`], but also turns the device on or off
+        pub struct MoveToLevelWithOnOff: ID, 0x04, ClientToServer {
+            pub level: u8,
+            pub transition_time: u16,
+        }
+    }
+}
+
+pub mod color_control {
+    use super::cluster_command;
+
+    pub const ID: u16 = 0x0300;
+
+    pub const CURRENT_HUE: u16 = 0x0000;
+    pub const CURRENT_SATURATION: u16 = 0x0001;
+    pub const CURRENT_X: u16 = 0x0003;
+    pub const CURRENT_Y: u16 = 0x0004;
+    pub const COLOR_TEMPERATURE: u16 = 0x0007;
+    pub const COLOR_MODE: u16 = 0x0008;
+
+    pub const DIRECTION_SHORTEST: u8 = 0x00;
+
+    cluster_command! {
+        pub struct MoveToHue: ID, 0x00, ClientToServer {
+            pub hue: u8,
+            pub direction: u8,
+            pub transition_time: u16,
+        }
+    }
+
+    cluster_command! {
+        pub struct MoveToSaturation: ID, 0x03, ClientToServer {
+            pub saturation: u8,
+            pub transition_time: u16,
+        }
+    }
+
+    cluster_command! {
+        pub struct MoveToHueAndSaturation: ID, 0x06, ClientToServer {
+            pub hue: u8,
+            pub saturation: u8,
+            pub transition_time: u16,
+        }
+    }
+
+    cluster_command! {
+        /// CIE 1931 coordinates scaled by 65536
+        pub struct MoveToColor: ID, 0x07, ClientToServer {
+            pub x: u16,
+            pub y: u16,
+            pub transition_time: u16,
+        }
+    }
+
+    cluster_command! {
+        pub struct MoveToColorTemperature: ID, 0x0a, ClientToServer {
+            /// In mireds
+            pub color_temperature: u16,
+            pub transition_time: u16,
+        }
+    }
+}
+
+pub mod temperature_measurement {
+    use super::Value;
+
+    pub const ID: u16 = 0x0402;
+
+    pub const MEASURED_VALUE: u16 = 0x0000;
+    pub const MIN_MEASURED_VALUE: u16 = 0x0001;
+    pub const MAX_MEASURED_VALUE: u16 = 0x0002;
+
+    /// Temperature in °C, reported in hundredths of a degree
+    pub fn celsius(value: &Value) -> Option<f64> {
+        match value {
+            Value::Int16(i16::MIN) => None,
+            v => Some(v.as_i64()? as f64 / 100.0),
+        }
+    }
+}
+
+pub mod relative_humidity {
+    use super::Value;
+
+    pub const ID: u16 = 0x0405;
+
+    pub const MEASURED_VALUE: u16 = 0x0000;
+    pub const MIN_MEASURED_VALUE: u16 = 0x0001;
+    pub const MAX_MEASURED_VALUE: u16 = 0x0002;
+
+    /// Relative humidity in %, reported in hundredths of a percent
+    pub fn percent(value: &Value) -> Option<f64> {
+        match value.as_i64()? {
+            0xffff => None,
+            v => Some(v as f64 / 100.0),
+        }
+    }
+}
+
+pub mod occupancy_sensing {
+    use super::Value;
+
+    pub const ID: u16 = 0x0406;
+
+    pub const OCCUPANCY: u16 = 0x0000;
+    pub const OCCUPANCY_SENSOR_TYPE: u16 = 0x0001;
+
+    pub fn occupied(value: &Value) -> Option<bool> {
+        Some(value.as_i64()? & 0x01 != 0)
+    }
+}
+
+pub mod ias_zone {
+    use super::cluster_command;
+
+    pub const ID: u16 = 0x0500;
+
+    pub const ZONE_STATE: u16 = 0x0000;
+    pub const ZONE_TYPE: u16 = 0x0001;
+    pub const ZONE_STATUS: u16 = 0x0002;
+    pub const IAS_CIE_ADDRESS: u16 = 0x0010;
+    pub const ZONE_ID: u16 = 0x0011;
+
+    // zone status bits
+    pub const ALARM1: u16 = 0x0001;
+    pub const ALARM2: u16 = 0x0002;
+    pub const TAMPER: u16 = 0x0004;
+    pub const BATTERY: u16 = 0x0008;
+    pub const SUPERVISION_REPORTS: u16 = 0x0010;
+    pub const RESTORE_REPORTS: u16 = 0x0020;
+    pub const TROUBLE: u16 = 0x0040;
+    pub const AC_MAINS: u16 = 0x0080;
+
+    cluster_command! {
+        pub struct ZoneEnrollResponse: ID, 0x00, ClientToServer {
+            pub enroll_response_code: u8,
+            pub zone_id: u8,
+        }
+    }
+
+    cluster_command! {
+        pub struct ZoneStatusChangeNotification: ID, 0x00, ServerToClient {
+            pub zone_status: u16,
+            pub extended_status: u8,
+            pub zone_id: u8,
+            /// Missing on older devices
+            pub delay: Option<u16>,
+        }
+    }
+
+    cluster_command! {
+        pub struct ZoneEnrollRequest: ID, 0x01, ServerToClient {
+            pub zone_type: u16,
+            pub manufacturer_code: u16,
+        }
+    }
+}
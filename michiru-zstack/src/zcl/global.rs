@@ -0,0 +1,237 @@
+//! Global (profile-wide) commands
+
+use anyhow::{anyhow, bail, Result};
+use nom::{
+    combinator::{cond, eof},
+    multi::many_till,
+    number::complete::{le_u16, u8},
+    IResult,
+};
+
+use super::{
+    value::{self, Value},
+    Status,
+};
+use crate::commands::Field;
+
+pub const READ_ATTRIBUTES: u8 = 0x00;
+pub const READ_ATTRIBUTES_RESPONSE: u8 = 0x01;
+pub const WRITE_ATTRIBUTES: u8 = 0x02;
+pub const WRITE_ATTRIBUTES_RESPONSE: u8 = 0x04;
+pub const CONFIGURE_REPORTING: u8 = 0x06;
+pub const CONFIGURE_REPORTING_RESPONSE: u8 = 0x07;
+pub const REPORT_ATTRIBUTES: u8 = 0x0a;
+pub const DEFAULT_RESPONSE: u8 = 0x0b;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlobalCommand {
+    ReadAttributes(Vec<u16>),
+    ReadAttributesResponse(Vec<ReadAttributeStatus>),
+    WriteAttributes(Vec<Attribute>),
+    WriteAttributesResponse(Vec<WriteAttributeStatus>),
+    ConfigureReporting(Vec<ReportingConfiguration>),
+    ConfigureReportingResponse(Vec<ConfigureReportingStatus>),
+    ReportAttributes(Vec<Attribute>),
+    DefaultResponse { command_id: u8, status: Status },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub attribute: u16,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadAttributeStatus {
+    pub attribute: u16,
+    pub status: Status,
+    /// Only present on success
+    pub value: Option<Value>,
+}
+
+/// A single status without attribute means every write succeeded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteAttributeStatus {
+    pub status: Status,
+    pub attribute: Option<u16>,
+}
+
+/// Asks the device to report an attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportingConfiguration {
+    pub attribute: u16,
+    pub data_type: u8,
+    pub min_interval: u16,
+    pub max_interval: u16,
+    /// Only used for analog data types
+    pub reportable_change: Option<Value>,
+}
+
+/// A single status without attribute means every attribute was configured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigureReportingStatus {
+    pub status: Status,
+    pub attribute: Option<u16>,
+}
+
+impl GlobalCommand {
+    pub fn command_id(&self) -> u8 {
+        match self {
+            GlobalCommand::ReadAttributes(_) => READ_ATTRIBUTES,
+            GlobalCommand::ReadAttributesResponse(_) => READ_ATTRIBUTES_RESPONSE,
+            GlobalCommand::WriteAttributes(_) => WRITE_ATTRIBUTES,
+            GlobalCommand::WriteAttributesResponse(_) => WRITE_ATTRIBUTES_RESPONSE,
+            GlobalCommand::ConfigureReporting(_) => CONFIGURE_REPORTING,
+            GlobalCommand::ConfigureReportingResponse(_) => CONFIGURE_REPORTING_RESPONSE,
+            GlobalCommand::ReportAttributes(_) => REPORT_ATTRIBUTES,
+            GlobalCommand::DefaultResponse { .. } => DEFAULT_RESPONSE,
+        }
+    }
+
+    pub fn parse(command_id: u8, payload: &[u8]) -> Result<Self> {
+        let result = match command_id {
+            READ_ATTRIBUTES => records(le_u16, payload).map(GlobalCommand::ReadAttributes),
+            READ_ATTRIBUTES_RESPONSE => {
+                records(read_attribute_status, payload).map(GlobalCommand::ReadAttributesResponse)
+            }
+            WRITE_ATTRIBUTES => records(attribute, payload).map(GlobalCommand::WriteAttributes),
+            WRITE_ATTRIBUTES_RESPONSE => {
+                records(write_attribute_status, payload).map(GlobalCommand::WriteAttributesResponse)
+            }
+            CONFIGURE_REPORTING => {
+                records(reporting_configuration, payload).map(GlobalCommand::ConfigureReporting)
+            }
+            CONFIGURE_REPORTING_RESPONSE => records(configure_reporting_status, payload)
+                .map(GlobalCommand::ConfigureReportingResponse),
+            REPORT_ATTRIBUTES => records(attribute, payload).map(GlobalCommand::ReportAttributes),
+            DEFAULT_RESPONSE => {
+                let (_, (command_id, status)) = default_response(payload)
+                    .map_err(|e| anyhow!("invalid default response: {e:?}"))?;
+                return Ok(GlobalCommand::DefaultResponse { command_id, status });
+            }
+            _ => bail!("unsupported global command {command_id:#04x}"),
+        };
+
+        result.map_err(|e| anyhow!("invalid global command {command_id:#04x}: {e:?}"))
+    }
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        match self {
+            GlobalCommand::ReadAttributes(attributes) => {
+                for attribute in attributes {
+                    attribute.write(buf);
+                }
+            }
+            GlobalCommand::ReadAttributesResponse(records) => {
+                for record in records {
+                    record.attribute.write(buf);
+                    record.status.write(buf);
+                    if let Some(value) = &record.value {
+                        value.write_typed(buf);
+                    }
+                }
+            }
+            GlobalCommand::WriteAttributes(records) | GlobalCommand::ReportAttributes(records) => {
+                for record in records {
+                    record.attribute.write(buf);
+                    record.value.write_typed(buf);
+                }
+            }
+            GlobalCommand::WriteAttributesResponse(records) => {
+                for record in records {
+                    record.status.write(buf);
+                    record.attribute.write(buf);
+                }
+            }
+            GlobalCommand::ConfigureReporting(records) => {
+                for record in records {
+                    // direction: reported by the server
+                    buf.push(0x00);
+                    record.attribute.write(buf);
+                    buf.push(record.data_type);
+                    record.min_interval.write(buf);
+                    record.max_interval.write(buf);
+                    if let Some(change) = &record.reportable_change {
+                        change.write(buf);
+                    }
+                }
+            }
+            GlobalCommand::ConfigureReportingResponse(records) => {
+                for record in records {
+                    record.status.write(buf);
+                    if let Some(attribute) = record.attribute {
+                        buf.push(0x00);
+                        attribute.write(buf);
+                    }
+                }
+            }
+            GlobalCommand::DefaultResponse { command_id, status } => {
+                buf.push(*command_id);
+                status.write(buf);
+            }
+        }
+    }
+}
+
+/// Parses records until the payload is exhausted
+fn records<'a, T>(
+    record: impl FnMut(&'a [u8]) -> IResult<&'a [u8], T>,
+    src: &'a [u8],
+) -> Result<Vec<T>, nom::Err<nom::error::Error<&'a [u8]>>> {
+    let (_, (records, _)) = many_till(record, eof)(src)?;
+    Ok(records)
+}
+
+fn attribute(src: &[u8]) -> IResult<&[u8], Attribute> {
+    let (src, attribute) = le_u16(src)?;
+    let (src, value) = Value::parse_typed(src)?;
+    Ok((src, Attribute { attribute, value }))
+}
+
+fn read_attribute_status(src: &[u8]) -> IResult<&[u8], ReadAttributeStatus> {
+    let (src, attribute) = le_u16(src)?;
+    let (src, status) = Status::parse(src)?;
+    let (src, value) = cond(status.is_success(), Value::parse_typed)(src)?;
+    Ok((src, ReadAttributeStatus { attribute, status, value }))
+}
+
+fn write_attribute_status(src: &[u8]) -> IResult<&[u8], WriteAttributeStatus> {
+    let (src, status) = Status::parse(src)?;
+    let (src, attribute) = Option::<u16>::parse(src)?;
+    Ok((src, WriteAttributeStatus { status, attribute }))
+}
+
+fn reporting_configuration(src: &[u8]) -> IResult<&[u8], ReportingConfiguration> {
+    let (src, _) = nom::bytes::complete::tag([0x00])(src)?;
+    let (src, attribute) = le_u16(src)?;
+    let (src, data_type) = u8(src)?;
+    let (src, min_interval) = le_u16(src)?;
+    let (src, max_interval) = le_u16(src)?;
+    let (src, reportable_change) =
+        cond(value::data_type::is_analog(data_type), |src| Value::parse(data_type, src))(src)?;
+
+    Ok((src, ReportingConfiguration {
+        attribute,
+        data_type,
+        min_interval,
+        max_interval,
+        reportable_change,
+    }))
+}
+
+fn configure_reporting_status(src: &[u8]) -> IResult<&[u8], ConfigureReportingStatus> {
+    let (src, status) = Status::parse(src)?;
+    if src.is_empty() {
+        return Ok((src, ConfigureReportingStatus { status, attribute: None }));
+    }
+
+    let (src, _direction) = u8(src)?;
+    let (src, attribute) = le_u16(src)?;
+    Ok((src, ConfigureReportingStatus { status, attribute: Some(attribute) }))
+}
+
+fn default_response(src: &[u8]) -> IResult<&[u8], (u8, Status)> {
+    let (src, command_id) = u8(src)?;
+    let (src, status) = Status::parse(src)?;
+    Ok((src, (command_id, status)))
+}
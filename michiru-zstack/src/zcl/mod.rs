@@ -0,0 +1,448 @@
+//! Zigbee Cluster Library frames
+//!
+//! A ZCL frame is carried in the payload of `AF_INCOMING_MSG` and
+//! `AF_DATA_REQUEST`. It consists of a short header followed by either a
+//! global (profile-wide) command or a cluster specific one.
+
+use std::{any::type_name, fmt};
+
+use anyhow::{anyhow, ensure, Result};
+use nom::{
+    combinator::cond,
+    number::complete::{le_u16, u8},
+    IResult,
+};
+
+pub mod clusters;
+pub mod global;
+pub mod value;
+
+pub use global::GlobalCommand;
+pub use value::Value;
+
+use crate::commands::Field;
+
+const FRAME_TYPE_MASK: u8 = 0x03;
+const MANUFACTURER_SPECIFIC: u8 = 0x04;
+const SERVER_TO_CLIENT: u8 = 0x08;
+const DISABLE_DEFAULT_RESPONSE: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Global,
+    ClusterSpecific,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub frame_type: FrameType,
+    pub manufacturer_code: Option<u16>,
+    pub direction: Direction,
+    pub disable_default_response: bool,
+    pub sequence: u8,
+    pub command_id: u8,
+}
+
+impl Header {
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, frame_control) = u8(src)?;
+        let (src, manufacturer_code) =
+            cond(frame_control & MANUFACTURER_SPECIFIC != 0, le_u16)(src)?;
+        let (src, sequence) = u8(src)?;
+        let (src, command_id) = u8(src)?;
+
+        let frame_type = match frame_control & FRAME_TYPE_MASK {
+            0x00 => FrameType::Global,
+            0x01 => FrameType::ClusterSpecific,
+            _ => return nom::combinator::fail(src),
+        };
+
+        let direction = if frame_control & SERVER_TO_CLIENT != 0 {
+            Direction::ServerToClient
+        } else {
+            Direction::ClientToServer
+        };
+
+        Ok((src, Self {
+            frame_type,
+            manufacturer_code,
+            direction,
+            disable_default_response: frame_control & DISABLE_DEFAULT_RESPONSE != 0,
+            sequence,
+            command_id,
+        }))
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        let mut frame_control = match self.frame_type {
+            FrameType::Global => 0x00,
+            FrameType::ClusterSpecific => 0x01,
+        };
+
+        if self.manufacturer_code.is_some() {
+            frame_control |= MANUFACTURER_SPECIFIC;
+        }
+        if self.direction == Direction::ServerToClient {
+            frame_control |= SERVER_TO_CLIENT;
+        }
+        if self.disable_default_response {
+            frame_control |= DISABLE_DEFAULT_RESPONSE;
+        }
+
+        buf.push(frame_control);
+        if let Some(code) = self.manufacturer_code {
+            buf.extend_from_slice(&code.to_le_bytes());
+        }
+        buf.push(self.sequence);
+        buf.push(self.command_id);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub header: Header,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let (payload, header) =
+            Header::parse(data).map_err(|e| anyhow!("invalid ZCL header: {e:?}"))?;
+
+        Ok(Self { header, payload: payload.to_vec() })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.header.write(&mut buf);
+        buf.extend_from_slice(&self.payload);
+        buf
+    }
+
+    /// Builds a global command sent from the coordinator to a device
+    pub fn global(sequence: u8, command: &GlobalCommand) -> Self {
+        let mut payload = vec![];
+        command.serialize(&mut payload);
+
+        Self {
+            header: Header {
+                frame_type: FrameType::Global,
+                manufacturer_code: None,
+                direction: Direction::ClientToServer,
+                disable_default_response: true,
+                sequence,
+                command_id: command.command_id(),
+            },
+            payload,
+        }
+    }
+
+    /// Builds a cluster specific command
+    pub fn cluster<C: ClusterCommand>(sequence: u8, command: &C) -> Self {
+        let mut payload = vec![];
+        command.serialize(&mut payload);
+
+        Self {
+            header: Header {
+                frame_type: FrameType::ClusterSpecific,
+                manufacturer_code: None,
+                direction: C::DIRECTION,
+                disable_default_response: false,
+                sequence,
+                command_id: C::COMMAND_ID,
+            },
+            payload,
+        }
+    }
+
+    pub fn global_command(&self) -> Result<GlobalCommand> {
+        ensure!(self.header.frame_type == FrameType::Global, "not a global command");
+        ensure!(
+            self.header.manufacturer_code.is_none(),
+            "manufacturer specific global commands are not supported"
+        );
+
+        GlobalCommand::parse(self.header.command_id, &self.payload)
+    }
+
+    pub fn cluster_command<C: ClusterCommand>(&self) -> Result<C> {
+        ensure!(C::matches(&self.header), "frame is not a {} command", type_name::<C>());
+
+        let (_, command) = C::parse(&self.payload)
+            .map_err(|e| anyhow!("failed to parse {}: {e:?}", type_name::<C>()))?;
+
+        Ok(command)
+    }
+}
+
+/// A cluster specific command
+pub trait ClusterCommand: Sized {
+    const CLUSTER_ID: u16;
+    const COMMAND_ID: u8;
+    const DIRECTION: Direction;
+
+    fn serialize(&self, buf: &mut Vec<u8>);
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self>;
+
+    fn matches(header: &Header) -> bool {
+        header.frame_type == FrameType::ClusterSpecific
+            && header.manufacturer_code.is_none()
+            && header.direction == Self::DIRECTION
+            && header.command_id == Self::COMMAND_ID
+    }
+}
+
+/// ZCL status code
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Status(pub u8);
+
+impl Status {
+    pub const SUCCESS: Status = Status(0x00);
+    pub const FAILURE: Status = Status(0x01);
+    pub const NOT_AUTHORIZED: Status = Status(0x7e);
+    pub const MALFORMED_COMMAND: Status = Status(0x80);
+    pub const UNSUP_CLUSTER_COMMAND: Status = Status(0x81);
+    pub const UNSUP_GENERAL_COMMAND: Status = Status(0x82);
+    pub const INVALID_FIELD: Status = Status(0x85);
+    pub const UNSUPPORTED_ATTRIBUTE: Status = Status(0x86);
+    pub const INVALID_VALUE: Status = Status(0x87);
+    pub const READ_ONLY: Status = Status(0x88);
+    pub const INSUFFICIENT_SPACE: Status = Status(0x89);
+    pub const UNREPORTABLE_ATTRIBUTE: Status = Status(0x8c);
+    pub const INVALID_DATA_TYPE: Status = Status(0x8d);
+    pub const TIMEOUT: Status = Status(0x94);
+
+    pub fn is_success(self) -> bool {
+        self == Self::SUCCESS
+    }
+
+    pub fn ok(self) -> Result<()> {
+        ensure!(self.is_success(), "ZCL command failed with status {self}");
+        Ok(())
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            Self::SUCCESS => "success",
+            Self::FAILURE => "failure",
+            Self::NOT_AUTHORIZED => "not authorized",
+            Self::MALFORMED_COMMAND => "malformed command",
+            Self::UNSUP_CLUSTER_COMMAND => "unsupported cluster command",
+            Self::UNSUP_GENERAL_COMMAND => "unsupported general command",
+            Self::INVALID_FIELD => "invalid field",
+            Self::UNSUPPORTED_ATTRIBUTE => "unsupported attribute",
+            Self::INVALID_VALUE => "invalid value",
+            Self::READ_ONLY => "read only",
+            Self::INSUFFICIENT_SPACE => "insufficient space",
+            Self::UNREPORTABLE_ATTRIBUTE => "unreportable attribute",
+            Self::INVALID_DATA_TYPE => "invalid data type",
+            Self::TIMEOUT => "timeout",
+            _ => return write!(f, "{:#04x}", self.0),
+        };
+
+        write!(f, "{name} ({:#04x})", self.0)
+    }
+}
+
+impl fmt::Debug for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Status({self})")
+    }
+}
+
+impl Field for Status {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.push(self.0);
+    }
+
+    fn parse(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, v) = u8(src)?;
+        Ok((src, Status(v)))
+    }
+}
+
+/// Declares a cluster specific command along with its [`ClusterCommand`]
+/// implementation
+macro_rules! cluster_command {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident: $cluster_id:expr, $command_id:literal, $direction:ident {
+            $(
+                $(#[$field_meta:meta])*
+                pub $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $name {
+            $(
+                $(#[$field_meta])*
+                pub $field: $ty,
+            )*
+        }
+
+        impl $crate::zcl::ClusterCommand for $name {
+            const CLUSTER_ID: u16 = $cluster_id;
+            const COMMAND_ID: u8 = $command_id;
+            const DIRECTION: $crate::zcl::Direction = $crate::zcl::Direction::$direction;
+
+            #[allow(unused_variables)]
+            fn serialize(&self, buf: &mut Vec<u8>) {
+                $( $crate::commands::Field::write(&self.$field, buf); )*
+            }
+
+            fn parse(src: &[u8]) -> nom::IResult<&[u8], Self> {
+                $( let (src, $field) = <$ty as $crate::commands::Field>::parse(src)?; )*
+                Ok((src, Self { $($field),* }))
+            }
+        }
+    };
+}
+
+pub(crate) use cluster_command;
+
+#[cfg(test)]
+mod tests {
+    use super::{clusters::*, global::*, *};
+
+    #[test]
+    fn header() {
+        let frame = Frame::parse(&[0x1c, 0x5f, 0x11, 0x2a, 0x0a, 0x01, 0x02]).unwrap();
+        assert_eq!(frame.header, Header {
+            frame_type: FrameType::Global,
+            manufacturer_code: Some(0x115f),
+            direction: Direction::ServerToClient,
+            disable_default_response: true,
+            sequence: 0x2a,
+            command_id: 0x0a,
+        });
+        assert_eq!(frame.payload, vec![0x01, 0x02]);
+        assert_eq!(frame.serialize(), vec![0x1c, 0x5f, 0x11, 0x2a, 0x0a, 0x01, 0x02]);
+
+        assert!(Frame::parse(&[0x18, 0x01]).is_err());
+        assert!(Frame::parse(&[0x03, 0x01, 0x00]).is_err());
+    }
+
+    #[test]
+    fn read_attributes() {
+        let frame = Frame::global(
+            3,
+            &GlobalCommand::ReadAttributes(vec![basic::MANUFACTURER_NAME, basic::MODEL_IDENTIFIER]),
+        );
+        assert_eq!(frame.serialize(), vec![0x10, 0x03, 0x00, 0x04, 0x00, 0x05, 0x00]);
+
+        let data = [
+            0x18, 0x03, 0x01, 0x04, 0x00, 0x00, 0x42, 0x04, b'I', b'K', b'E', b'A', 0x05, 0x00,
+            0x86,
+        ];
+        let command = Frame::parse(&data).unwrap().global_command().unwrap();
+        assert_eq!(
+            command,
+            GlobalCommand::ReadAttributesResponse(vec![
+                ReadAttributeStatus {
+                    attribute: basic::MANUFACTURER_NAME,
+                    status: Status::SUCCESS,
+                    value: Some(Value::CharString("IKEA".into())),
+                },
+                ReadAttributeStatus {
+                    attribute: basic::MODEL_IDENTIFIER,
+                    status: Status::UNSUPPORTED_ATTRIBUTE,
+                    value: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn report_attributes() {
+        let data = [0x18, 0x01, 0x0a, 0x00, 0x00, 0x29, 0x2a, 0x09];
+        let command = Frame::parse(&data).unwrap().global_command().unwrap();
+
+        let GlobalCommand::ReportAttributes(records) = command else {
+            panic!("unexpected command {command:?}");
+        };
+        assert_eq!(records[0].attribute, temperature_measurement::MEASURED_VALUE);
+        assert_eq!(temperature_measurement::celsius(&records[0].value), Some(23.46));
+
+        let mut buf = vec![];
+        GlobalCommand::ReportAttributes(records).serialize(&mut buf);
+        assert_eq!(buf, &data[3..]);
+    }
+
+    #[test]
+    fn configure_reporting() {
+        let command = GlobalCommand::ConfigureReporting(vec![
+            ReportingConfiguration {
+                attribute: on_off::ON_OFF,
+                data_type: value::data_type::BOOL,
+                min_interval: 0,
+                max_interval: 3600,
+                reportable_change: None,
+            },
+            ReportingConfiguration {
+                attribute: relative_humidity::MEASURED_VALUE,
+                data_type: value::data_type::UINT16,
+                min_interval: 10,
+                max_interval: 600,
+                reportable_change: Some(Value::Uint16(100)),
+            },
+        ]);
+
+        let mut buf = vec![];
+        command.serialize(&mut buf);
+        assert_eq!(buf, vec![
+            0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x10, 0x0e, // on/off
+            0x00, 0x00, 0x00, 0x21, 0x0a, 0x00, 0x58, 0x02, 0x64, 0x00, // humidity
+        ]);
+        assert_eq!(GlobalCommand::parse(0x06, &buf).unwrap(), command);
+
+        assert_eq!(
+            GlobalCommand::parse(0x07, &[0x00]).unwrap(),
+            GlobalCommand::ConfigureReportingResponse(vec![ConfigureReportingStatus {
+                status: Status::SUCCESS,
+                attribute: None,
+            }])
+        );
+    }
+
+    #[test]
+    fn default_response() {
+        let data = [0x18, 0x05, 0x0b, 0x01, 0x81];
+        let command = Frame::parse(&data).unwrap().global_command().unwrap();
+        assert_eq!(command, GlobalCommand::DefaultResponse {
+            command_id: 0x01,
+            status: Status::UNSUP_CLUSTER_COMMAND,
+        });
+    }
+
+    #[test]
+    fn cluster_commands() {
+        let frame = Frame::cluster(7, &level_control::MoveToLevelWithOnOff {
+            level: 254,
+            transition_time: 10,
+        });
+        assert_eq!(frame.serialize(), vec![0x01, 0x07, 0x04, 0xfe, 0x0a, 0x00]);
+
+        assert_eq!(Frame::cluster(8, &on_off::Toggle {}).serialize(), vec![0x01, 0x08, 0x02]);
+
+        let frame = Frame::parse(&[0x09, 0x10, 0x00, 0x09, 0x00, 0x00, 0x03, 0x00, 0x00]).unwrap();
+        let notification = frame
+            .cluster_command::<ias_zone::ZoneStatusChangeNotification>()
+            .unwrap();
+        assert_eq!(notification.zone_status, ias_zone::ALARM1 | ias_zone::BATTERY);
+        assert_eq!(notification.zone_id, 3);
+        assert!(frame
+            .cluster_command::<ias_zone::ZoneEnrollRequest>()
+            .is_err());
+    }
+}
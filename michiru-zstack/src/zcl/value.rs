@@ -0,0 +1,260 @@
+use nom::{
+    bytes::complete::take,
+    combinator::{fail, map},
+    number::complete::{
+        le_f32, le_f64, le_i16, le_i24, le_i32, le_u16, le_u24, le_u32, le_u64, u8 as le_u8,
+    },
+    IResult,
+};
+
+/// Attribute data types
+pub mod data_type {
+    pub const NO_DATA: u8 = 0x00;
+    pub const DATA8: u8 = 0x08;
+    pub const DATA16: u8 = 0x09;
+    pub const BOOL: u8 = 0x10;
+    pub const BITMAP8: u8 = 0x18;
+    pub const BITMAP16: u8 = 0x19;
+    pub const BITMAP32: u8 = 0x1b;
+    pub const UINT8: u8 = 0x20;
+    pub const UINT16: u8 = 0x21;
+    pub const UINT24: u8 = 0x22;
+    pub const UINT32: u8 = 0x23;
+    pub const UINT48: u8 = 0x25;
+    pub const INT8: u8 = 0x28;
+    pub const INT16: u8 = 0x29;
+    pub const INT24: u8 = 0x2a;
+    pub const INT32: u8 = 0x2b;
+    pub const ENUM8: u8 = 0x30;
+    pub const ENUM16: u8 = 0x31;
+    pub const SINGLE: u8 = 0x39;
+    pub const DOUBLE: u8 = 0x3a;
+    pub const OCTET_STRING: u8 = 0x41;
+    pub const CHAR_STRING: u8 = 0x42;
+    pub const UTC_TIME: u8 = 0xe2;
+    pub const IEEE_ADDRESS: u8 = 0xf0;
+
+    /// Analog types carry a reportable change in reporting configurations
+    pub fn is_analog(data_type: u8) -> bool {
+        matches!(data_type, 0x20..=0x2f | 0x38..=0x3a | 0xe0..=0xe2)
+    }
+}
+
+/// Attribute value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    NoData,
+    Data8(u8),
+    Data16(u16),
+    Bool(bool),
+    Bitmap8(u8),
+    Bitmap16(u16),
+    Bitmap32(u32),
+    Uint8(u8),
+    Uint16(u16),
+    Uint24(u32),
+    Uint32(u32),
+    Uint48(u64),
+    Int8(i8),
+    Int16(i16),
+    Int24(i32),
+    Int32(i32),
+    Enum8(u8),
+    Enum16(u16),
+    Single(f32),
+    Double(f64),
+    OctetString(Vec<u8>),
+    CharString(String),
+    UtcTime(u32),
+    IeeeAddress(u64),
+}
+
+impl Value {
+    pub fn data_type(&self) -> u8 {
+        use data_type::*;
+
+        match self {
+            Value::NoData => NO_DATA,
+            Value::Data8(_) => DATA8,
+            Value::Data16(_) => DATA16,
+            Value::Bool(_) => BOOL,
+            Value::Bitmap8(_) => BITMAP8,
+            Value::Bitmap16(_) => BITMAP16,
+            Value::Bitmap32(_) => BITMAP32,
+            Value::Uint8(_) => UINT8,
+            Value::Uint16(_) => UINT16,
+            Value::Uint24(_) => UINT24,
+            Value::Uint32(_) => UINT32,
+            Value::Uint48(_) => UINT48,
+            Value::Int8(_) => INT8,
+            Value::Int16(_) => INT16,
+            Value::Int24(_) => INT24,
+            Value::Int32(_) => INT32,
+            Value::Enum8(_) => ENUM8,
+            Value::Enum16(_) => ENUM16,
+            Value::Single(_) => SINGLE,
+            Value::Double(_) => DOUBLE,
+            Value::OctetString(_) => OCTET_STRING,
+            Value::CharString(_) => CHAR_STRING,
+            Value::UtcTime(_) => UTC_TIME,
+            Value::IeeeAddress(_) => IEEE_ADDRESS,
+        }
+    }
+
+    /// Parses a value of the given type, without a leading type byte
+    pub fn parse(data_type: u8, src: &[u8]) -> IResult<&[u8], Self> {
+        use data_type::*;
+
+        match data_type {
+            NO_DATA => Ok((src, Value::NoData)),
+            DATA8 => map(le_u8, Value::Data8)(src),
+            DATA16 => map(le_u16, Value::Data16)(src),
+            BOOL => map(le_u8, |v| Value::Bool(v != 0))(src),
+            BITMAP8 => map(le_u8, Value::Bitmap8)(src),
+            BITMAP16 => map(le_u16, Value::Bitmap16)(src),
+            BITMAP32 => map(le_u32, Value::Bitmap32)(src),
+            UINT8 => map(le_u8, Value::Uint8)(src),
+            UINT16 => map(le_u16, Value::Uint16)(src),
+            UINT24 => map(le_u24, Value::Uint24)(src),
+            UINT32 => map(le_u32, Value::Uint32)(src),
+            UINT48 => map(take(6usize), |b: &[u8]| {
+                let mut bytes = [0; 8];
+                bytes[..6].copy_from_slice(b);
+                Value::Uint48(u64::from_le_bytes(bytes))
+            })(src),
+            INT8 => map(le_u8, |v| Value::Int8(v as i8))(src),
+            INT16 => map(le_i16, Value::Int16)(src),
+            INT24 => map(le_i24, Value::Int24)(src),
+            INT32 => map(le_i32, Value::Int32)(src),
+            ENUM8 => map(le_u8, Value::Enum8)(src),
+            ENUM16 => map(le_u16, Value::Enum16)(src),
+            SINGLE => map(le_f32, Value::Single)(src),
+            DOUBLE => map(le_f64, Value::Double)(src),
+            OCTET_STRING => {
+                let (src, len) = le_u8(src)?;
+                map(take(len), |b: &[u8]| Value::OctetString(b.to_vec()))(src)
+            }
+            CHAR_STRING => {
+                let (src, len) = le_u8(src)?;
+                map(take(len), |b: &[u8]| {
+                    // some devices pad their strings with NULs
+                    let s = String::from_utf8_lossy(b);
+                    Value::CharString(s.trim_end_matches('\0').to_string())
+                })(src)
+            }
+            UTC_TIME => map(le_u32, Value::UtcTime)(src),
+            IEEE_ADDRESS => map(le_u64, Value::IeeeAddress)(src),
+            _ => fail(src),
+        }
+    }
+
+    /// Parses a value preceded by its type byte
+    pub fn parse_typed(src: &[u8]) -> IResult<&[u8], Self> {
+        let (src, data_type) = le_u8(src)?;
+        Self::parse(data_type, src)
+    }
+
+    /// Writes the value without a leading type byte
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        match self {
+            Value::NoData => {}
+            Value::Data8(v) | Value::Bitmap8(v) | Value::Uint8(v) | Value::Enum8(v) => buf.push(*v),
+            Value::Bool(v) => buf.push(*v as u8),
+            Value::Data16(v) | Value::Bitmap16(v) | Value::Uint16(v) | Value::Enum16(v) => {
+                buf.extend_from_slice(&v.to_le_bytes())
+            }
+            Value::Bitmap32(v) | Value::Uint32(v) | Value::UtcTime(v) => {
+                buf.extend_from_slice(&v.to_le_bytes())
+            }
+            Value::Uint24(v) => buf.extend_from_slice(&v.to_le_bytes()[..3]),
+            Value::Uint48(v) => buf.extend_from_slice(&v.to_le_bytes()[..6]),
+            Value::Int8(v) => buf.push(*v as u8),
+            Value::Int16(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Int24(v) => buf.extend_from_slice(&v.to_le_bytes()[..3]),
+            Value::Int32(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Single(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Double(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Value::OctetString(v) => {
+                buf.push(v.len() as u8);
+                buf.extend_from_slice(v);
+            }
+            Value::CharString(v) => {
+                buf.push(v.len() as u8);
+                buf.extend_from_slice(v.as_bytes());
+            }
+            Value::IeeeAddress(v) => buf.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+
+    /// Writes the type byte followed by the value
+    pub fn write_typed(&self, buf: &mut Vec<u8>) {
+        buf.push(self.data_type());
+        self.write(buf);
+    }
+
+    /// Numeric value of the given type, `None` for non-numeric types
+    pub fn from_i64(data_type: u8, value: i64) -> Option<Self> {
+        use data_type::*;
+
+        Some(match data_type {
+            DATA8 => Value::Data8(value as u8),
+            DATA16 => Value::Data16(value as u16),
+            BITMAP8 => Value::Bitmap8(value as u8),
+            BITMAP16 => Value::Bitmap16(value as u16),
+            BITMAP32 => Value::Bitmap32(value as u32),
+            UINT8 => Value::Uint8(value as u8),
+            UINT16 => Value::Uint16(value as u16),
+            UINT24 => Value::Uint24(value as u32 & 0xff_ffff),
+            UINT32 => Value::Uint32(value as u32),
+            UINT48 => Value::Uint48(value as u64 & 0xffff_ffff_ffff),
+            INT8 => Value::Int8(value as i8),
+            INT16 => Value::Int16(value as i16),
+            INT24 => Value::Int24(value as i32),
+            INT32 => Value::Int32(value as i32),
+            ENUM8 => Value::Enum8(value as u8),
+            ENUM16 => Value::Enum16(value as u16),
+            SINGLE => Value::Single(value as f32),
+            DOUBLE => Value::Double(value as f64),
+            UTC_TIME => Value::UtcTime(value as u32),
+            _ => return None,
+        })
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        Some(match *self {
+            Value::Data8(v) | Value::Bitmap8(v) | Value::Uint8(v) | Value::Enum8(v) => v as i64,
+            Value::Data16(v) | Value::Bitmap16(v) | Value::Uint16(v) | Value::Enum16(v) => v as i64,
+            Value::Bitmap32(v) | Value::Uint24(v) | Value::Uint32(v) | Value::UtcTime(v) => {
+                v as i64
+            }
+            Value::Uint48(v) => v as i64,
+            Value::Int8(v) => v as i64,
+            Value::Int16(v) => v as i64,
+            Value::Int24(v) | Value::Int32(v) => v as i64,
+            Value::Bool(v) => v as i64,
+            _ => return None,
+        })
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Single(v) => Some(v as f64),
+            Value::Double(v) => Some(v),
+            _ => self.as_i64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::CharString(v) => Some(v),
+            _ => None,
+        }
+    }
+}